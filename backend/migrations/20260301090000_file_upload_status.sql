@@ -0,0 +1,14 @@
+USE ferrum;
+
+-- Two-phase uploads: rows start as 'pending' when the presigned URL is issued
+-- and only become 'committed' once the object has been verified in storage.
+ALTER TABLE files
+    ADD COLUMN status ENUM('pending','committed','failed') NOT NULL DEFAULT 'pending' AFTER is_public;
+
+-- Everything uploaded before this migration is assumed to be complete.
+UPDATE files SET status = 'committed';
+
+-- Files uploaded into the user's root have no folder.
+ALTER TABLE files MODIFY folder_id CHAR(36) NULL;
+
+CREATE INDEX idx_files_folder_status ON files(folder_id, status);
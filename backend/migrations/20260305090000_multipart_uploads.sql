@@ -0,0 +1,6 @@
+USE ferrum;
+
+-- S3 multipart upload id for files uploaded in parts. Kept on the row so the
+-- client can resume (list parts, presign the rest) after a browser refresh.
+ALTER TABLE files
+    ADD COLUMN upload_id VARCHAR(1024) NULL AFTER status;
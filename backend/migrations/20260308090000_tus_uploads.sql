@@ -0,0 +1,17 @@
+USE ferrum;
+
+-- Progress of tus (resumable) uploads. Chunks are buffered into S3 multipart
+-- parts; bytes that don't yet fill a part live in a "{storage_key}.tus-tail" object.
+-- Only one PATCH at a time may write them, the one holding lock_id.
+CREATE TABLE tus_uploads (
+    file_id CHAR(36) PRIMARY KEY,
+    upload_length BIGINT NOT NULL,
+    upload_offset BIGINT NOT NULL DEFAULT 0,
+    next_part_number INT NOT NULL DEFAULT 1,
+    tail_size BIGINT NOT NULL DEFAULT 0,
+    lock_id CHAR(36) NULL,         -- The PATCH currently writing parts, if any
+    locked_until DATETIME NULL,
+    created_at TIMESTAMP DEFAULT NOW(),
+
+    FOREIGN KEY (file_id) REFERENCES files(id) ON DELETE CASCADE
+);
@@ -0,0 +1,21 @@
+USE ferrum;
+
+-- Soft delete. The item the user actually deleted has trashed_with = NULL;
+-- everything inside a trashed folder points at that folder via trashed_with,
+-- so restoring the folder brings back exactly what was trashed with it.
+ALTER TABLE folders
+    ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL,
+    ADD COLUMN deleted_by CHAR(36) NULL,
+    ADD COLUMN trashed_with CHAR(36) NULL,
+    ADD FOREIGN KEY (deleted_by) REFERENCES users(id) ON DELETE SET NULL;
+
+ALTER TABLE files
+    ADD COLUMN deleted_at TIMESTAMP NULL DEFAULT NULL,
+    ADD COLUMN deleted_by CHAR(36) NULL,
+    ADD COLUMN trashed_with CHAR(36) NULL,
+    ADD FOREIGN KEY (deleted_by) REFERENCES users(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_folders_deleted ON folders(deleted_at);
+CREATE INDEX idx_folders_trashed_with ON folders(trashed_with);
+CREATE INDEX idx_files_deleted ON files(deleted_at);
+CREATE INDEX idx_files_trashed_with ON files(trashed_with);
@@ -0,0 +1,21 @@
+USE ferrum;
+
+-- Folder tree copies run in the background; clients poll this table for progress.
+CREATE TABLE copy_jobs (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    source_folder_id CHAR(36) NOT NULL,
+    destination_id CHAR(36), -- NULL = user's root
+    result_folder_id CHAR(36), -- Top-level folder of the copy
+    status ENUM('queued','running','completed','failed') NOT NULL DEFAULT 'queued',
+    total_files INT NOT NULL DEFAULT 0,
+    copied_files INT NOT NULL DEFAULT 0,
+    copied_bytes BIGINT NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TIMESTAMP DEFAULT NOW(),
+    finished_at TIMESTAMP NULL,
+
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_copy_jobs_user ON copy_jobs(user_id);
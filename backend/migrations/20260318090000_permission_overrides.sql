@@ -0,0 +1,8 @@
+USE ferrum;
+
+-- Grants are inherited by subfolders; the nearest explicit grant wins.
+-- 'none' lets a subfolder opt out of access inherited from its parents.
+ALTER TABLE folder_permissions
+    MODIFY permission ENUM('viewer','editor','none') NOT NULL;
+
+CREATE INDEX idx_folder_permissions_user ON folder_permissions(user_id, folder_id);
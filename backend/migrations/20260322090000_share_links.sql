@@ -0,0 +1,21 @@
+USE ferrum;
+
+-- Public links to a single file or folder. Exactly one of file_id / folder_id is set.
+CREATE TABLE share_links (
+    id CHAR(36) PRIMARY KEY,
+    token VARCHAR(64) NOT NULL UNIQUE,
+    file_id CHAR(36),
+    folder_id CHAR(36),
+    created_by CHAR(36) NOT NULL,
+    expires_at TIMESTAMP NULL,
+    max_downloads INT NULL,
+    download_count INT NOT NULL DEFAULT 0,
+    password_hash TEXT,
+    created_at TIMESTAMP DEFAULT NOW(),
+
+    FOREIGN KEY (file_id) REFERENCES files(id) ON DELETE CASCADE,
+    FOREIGN KEY (folder_id) REFERENCES folders(id) ON DELETE CASCADE,
+    FOREIGN KEY (created_by) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_share_links_creator ON share_links(created_by);
@@ -0,0 +1,15 @@
+USE ferrum;
+
+-- Deactivated users keep their data but can no longer sign in or use existing tokens.
+ALTER TABLE users
+    ADD COLUMN is_active BOOLEAN NOT NULL DEFAULT TRUE AFTER role;
+
+-- Deleting a user must never silently wipe their folders and files: the admin API
+-- transfers or purges them first, and the database refuses the delete otherwise.
+ALTER TABLE folders
+    DROP FOREIGN KEY folders_ibfk_2,
+    ADD CONSTRAINT fk_folders_owner FOREIGN KEY (owner_id) REFERENCES users(id) ON DELETE RESTRICT;
+
+ALTER TABLE files
+    DROP FOREIGN KEY files_ibfk_2,
+    ADD CONSTRAINT fk_files_owner FOREIGN KEY (owner_id) REFERENCES users(id) ON DELETE RESTRICT;
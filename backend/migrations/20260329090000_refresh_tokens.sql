@@ -0,0 +1,24 @@
+USE ferrum;
+
+-- Rotating refresh tokens. Only the SHA-256 of the token is stored. Every login starts
+-- a new family; presenting an already-rotated token revokes the whole family.
+CREATE TABLE refresh_tokens (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    family_id CHAR(36) NOT NULL,
+    token_hash CHAR(64) NOT NULL UNIQUE,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP NULL,
+    revoked_at TIMESTAMP NULL,
+    created_at TIMESTAMP DEFAULT NOW(),
+
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_refresh_tokens_user ON refresh_tokens(user_id);
+CREATE INDEX idx_refresh_tokens_family ON refresh_tokens(family_id);
+
+-- Access tokens carry the version current when they were issued (the `ver` claim);
+-- bumping it signs the user out everywhere. Kept here, not in Redis, so a flush
+-- can't bring revoked tokens back.
+ALTER TABLE users ADD COLUMN token_version INT NOT NULL DEFAULT 0;
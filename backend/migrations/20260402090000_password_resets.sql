@@ -0,0 +1,16 @@
+USE ferrum;
+
+-- Single-use password reset tokens, stored as SHA-256. Requesting a new reset
+-- marks any earlier outstanding token for the user as used.
+CREATE TABLE password_reset_tokens (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    token_hash CHAR(64) NOT NULL UNIQUE,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP NULL,
+    created_at TIMESTAMP DEFAULT NOW(),
+
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_password_reset_tokens_user ON password_reset_tokens(user_id);
@@ -0,0 +1,30 @@
+USE ferrum;
+
+-- TOTP (RFC 6238). The secret is stored on enrollment and only takes effect once
+-- confirmed with a valid code (totp_enabled). totp_last_step blocks code replay.
+ALTER TABLE users
+    ADD COLUMN totp_secret VARCHAR(64) NULL,
+    ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN totp_last_step BIGINT NULL;
+
+-- Single-use backup codes, stored as SHA-256
+CREATE TABLE mfa_recovery_codes (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    code_hash CHAR(64) NOT NULL,
+    used_at TIMESTAMP NULL,
+    created_at TIMESTAMP DEFAULT NOW(),
+
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_mfa_recovery_codes_user ON mfa_recovery_codes(user_id);
+
+-- Roles that must use 2FA. Users of such a role who haven't enrolled yet are
+-- sent through enrollment at their next login.
+CREATE TABLE mfa_policies (
+    role ENUM('admin','osis','media_guru') PRIMARY KEY,
+    required BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+INSERT INTO mfa_policies (role, required) VALUES ('admin', TRUE), ('osis', FALSE), ('media_guru', FALSE);
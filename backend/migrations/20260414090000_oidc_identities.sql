@@ -0,0 +1,18 @@
+USE ferrum;
+
+-- External identities (OpenID Connect) linked to local accounts. A user can have
+-- several; (issuer, subject) is the provider's stable id for the person.
+CREATE TABLE oidc_identities (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    issuer VARCHAR(255) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    email VARCHAR(255),
+    created_at TIMESTAMP DEFAULT NOW(),
+    last_login_at TIMESTAMP NULL,
+
+    UNIQUE KEY unique_identity (issuer, subject),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_oidc_identities_user ON oidc_identities(user_id);
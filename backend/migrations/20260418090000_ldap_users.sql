@@ -0,0 +1,8 @@
+USE ferrum;
+
+-- Accounts managed by an LDAP/Active Directory server. ldap_uid is the directory
+-- login name (LDAP_UID_ATTRIBUTE); name, email and optionally role are refreshed
+-- from the directory on every login and the local password is not used.
+ALTER TABLE users
+    ADD COLUMN ldap_uid VARCHAR(255) NULL,
+    ADD UNIQUE KEY unique_ldap_uid (ldap_uid);
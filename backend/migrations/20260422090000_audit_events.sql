@@ -0,0 +1,28 @@
+USE ferrum;
+
+-- Who did what to which object, from where, and whether it worked. Append-only:
+-- the triggers below reject UPDATE and DELETE. Actor and target ids are plain
+-- columns, not foreign keys, so the trail survives the deletion of what it names.
+CREATE TABLE audit_events (
+    id CHAR(36) PRIMARY KEY,
+    created_at TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    actor_id CHAR(36) NULL,            -- NULL = anonymous (failed login, public share link)
+    action VARCHAR(64) NOT NULL,       -- e.g. 'auth.login', 'file.download', 'permission.grant'
+    target_type VARCHAR(32) NULL,      -- 'user', 'file', 'folder', 'share', 'token'
+    target_id VARCHAR(255) NULL,
+    outcome ENUM('success','failure','denied') NOT NULL,
+    ip VARCHAR(45) NULL,
+    user_agent VARCHAR(512) NULL,
+    details JSON NULL
+);
+
+CREATE INDEX idx_audit_events_created ON audit_events(created_at);
+CREATE INDEX idx_audit_events_actor ON audit_events(actor_id, created_at);
+CREATE INDEX idx_audit_events_target ON audit_events(target_type, target_id, created_at);
+CREATE INDEX idx_audit_events_action ON audit_events(action, created_at);
+
+CREATE TRIGGER audit_events_no_update BEFORE UPDATE ON audit_events
+    FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'audit_events is append-only';
+
+CREATE TRIGGER audit_events_no_delete BEFORE DELETE ON audit_events
+    FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'audit_events is append-only';
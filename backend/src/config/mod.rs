@@ -8,38 +8,174 @@ pub struct Config {
     // Redis
     pub redis_url: String,
     
+    // Storage
+    pub storage_backend: String,        // 's3' (MinIO) or 'local'
+    pub local_storage_path: String,     // 'local': directory holding the files
+    pub storage_public_url: String,     // 'local' and proxy downloads: this backend's base URL, used in signed URLs
+    pub storage_signing_secret: String, // 'local' and proxy downloads: HMAC key for signed URLs
+    pub download_mode: String,          // 'presigned' (straight from storage) or 'proxy' (streamed by us)
+    pub download_link_ttl: i64,         // 'proxy': seconds a signed /content link stays valid
+    pub upload_mode: String,            // 'put' (presigned PUT) or 'post' (S3 POST policy pinning size and type)
+
     // MinIO / S3
     pub s3_endpoint: String,
     pub s3_region: String,
     pub s3_bucket: String,
     pub s3_access_key: String,
     pub s3_secret_key: String,
-    pub s3_public_url: String, // For public file access
+
+    pub tus_part_size: usize, // Bytes buffered per S3 part for tus uploads (min 5 MiB)
+    pub user_quota_bytes: i64, // Storage each user may use, including trash (0 = unlimited)
+
+    // Trash
+    pub trash_retention_days: i64, // Trashed items older than this are purged
+    pub trash_purge_interval: u64, // Seconds between purge runs
 
     // Authentication
-    pub jwt_secret: String,
-    pub jwt_expiration: i64, 
+    pub jwt_algorithm: String,           // 'HS256', 'RS256' or 'EdDSA'
+    pub jwt_secret: String,              // HS256 only
+    pub jwt_keys_dir: Option<String>,    // RS256/EdDSA: <kid>.pem files
+    pub jwt_signing_kid: Option<String>, // RS256/EdDSA: key that signs new tokens
+    pub jwt_expiration: i64, // Access token lifetime in seconds
+    pub refresh_token_ttl: i64, // Refresh token lifetime in seconds
     
+    // OpenID Connect (disabled unless OIDC_ISSUER is set)
+    pub oidc_issuer: Option<String>,
+    pub oidc_client_id: String,
+    pub oidc_client_secret: String,
+    pub oidc_redirect_url: String,          // Must point at /api/auth/oidc/callback
+    pub oidc_scopes: String,
+    pub oidc_allowed_domains: Vec<String>,  // Empty = any domain may sign in
+    pub oidc_groups_claim: String,          // ID token claim holding the user's groups
+    pub oidc_role_mapping: Vec<(String, String)>, // OIDC_ROLE_MAPPING="group=role,..." first match wins
+    pub oidc_default_role: String,          // Role for new users no group maps
+    pub oidc_sync_roles: bool,              // Re-apply the mapping on every login
+    pub oidc_require_verified_email: bool,  // New accounts need email_verified=true (default)
+
+    // Login backends
+    pub auth_backends: Vec<String>,         // AUTH_BACKENDS="ldap,local" tried in order
+    pub ldap_url: String,                   // ldap:// or ldaps://
+    pub ldap_starttls: bool,
+    pub ldap_bind_dn: Option<String>,       // Service account for the user search, anonymous if unset
+    pub ldap_bind_password: Option<String>,
+    pub ldap_base_dn: String,
+    pub ldap_user_filter: String,           // `{login}` is replaced with the escaped login name
+    pub ldap_uid_attribute: String,         // Stable login name, e.g. uid or sAMAccountName
+    pub ldap_name_attribute: String,
+    pub ldap_email_attribute: String,
+    pub ldap_group_attribute: String,       // Multi-valued group DNs on the user entry
+    pub ldap_role_mapping: Vec<(String, String)>, // LDAP_ROLE_MAPPING="group=role;..." group is a DN or CN
+    pub ldap_default_role: String,
+    pub ldap_sync_roles: bool,
+
+    // Login throttling
+    pub login_backoff_after: i64,      // Failures per account before each attempt must wait
+    pub login_lockout_after: i64,      // Failures per account that lock it (0 = never)
+    pub login_lockout_seconds: i64,    // Lock duration, also the window failures are counted in
+    pub login_ip_backoff_after: i64,   // Same, per client IP
+    pub login_ip_lockout_after: i64,
+    pub trust_proxy: bool,             // Take the client IP from X-Forwarded-For
+
+    // Password reset
+    pub app_url: String,           // Frontend base URL used in reset links
+    pub password_reset_ttl: i64,   // Seconds a reset token stays valid
+
+    // Mail
+    pub mailer: String,                  // 'smtp' or 'log' (local testing)
+    pub mail_from: String,
+    pub mail_outbox_dir: Option<String>, // 'log' mailer writes .eml files here if set
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_tls: String,                // 'starttls', 'tls' or 'none'
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+
     // App
     pub host: String, // e.g., 0.0.0.0
     pub port: u16,    // e.g., 8080
 }
 
 impl Config {
+    #[allow(clippy::new_without_default)] // Reads the environment, not a plain default
     pub fn new() -> Self {
         dotenv::dotenv().ok();
         
         Self {
             database_url: env::var("DATABASE_URL").expect("DATABASE_URL must be set"),
             redis_url: env::var("REDIS_URL").expect("REDIS_URL must be set"),
-            s3_endpoint: env::var("S3_ENDPOINT").expect("S3_ENDPOINT must be set"),
+            storage_backend: env::var("STORAGE_BACKEND").unwrap_or_else(|_| "s3".to_string()).to_lowercase(),
+            local_storage_path: env::var("LOCAL_STORAGE_PATH").unwrap_or_else(|_| "./data/storage".to_string()),
+            storage_public_url: env::var("STORAGE_PUBLIC_URL").unwrap_or_else(|_| "http://localhost:8080".to_string()),
+            storage_signing_secret: env::var("STORAGE_SIGNING_SECRET").unwrap_or_default(),
+            download_mode: env::var("DOWNLOAD_MODE").unwrap_or_else(|_| "presigned".to_string()).to_lowercase(),
+            download_link_ttl: env::var("DOWNLOAD_LINK_TTL").unwrap_or_else(|_| "21600".to_string()).parse().unwrap_or(21600),
+            upload_mode: env::var("UPLOAD_MODE").unwrap_or_else(|_| "put".to_string()).to_lowercase(),
+            s3_endpoint: env::var("S3_ENDPOINT").unwrap_or_default(),
             s3_region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
-            s3_bucket: env::var("S3_BUCKET").expect("S3_BUCKET must be set"),
-            s3_access_key: env::var("S3_ACCESS_KEY").expect("S3_ACCESS_KEY must be set"),
-            s3_secret_key: env::var("S3_SECRET_KEY").expect("S3_SECRET_KEY must be set"),
-            s3_public_url: env::var("S3_PUBLIC_URL").expect("S3_PUBLIC_URL must be set"),
-            jwt_secret: env::var("JWT_SECRET").expect("JWT_SECRET must be set"),
-            jwt_expiration: env::var("JWT_EXPIRATION").unwrap_or_else(|_| "86400".to_string()).parse().unwrap_or(86400),
+            s3_bucket: env::var("S3_BUCKET").unwrap_or_default(),
+            s3_access_key: env::var("S3_ACCESS_KEY").unwrap_or_default(),
+            s3_secret_key: env::var("S3_SECRET_KEY").unwrap_or_default(),
+            tus_part_size: env::var("TUS_PART_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(8 * 1024 * 1024).max(5 * 1024 * 1024),
+            user_quota_bytes: env::var("USER_QUOTA_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(0),
+            trash_retention_days: env::var("TRASH_RETENTION_DAYS").unwrap_or_else(|_| "30".to_string()).parse().unwrap_or(30),
+            trash_purge_interval: env::var("TRASH_PURGE_INTERVAL").unwrap_or_else(|_| "3600".to_string()).parse().unwrap_or(3600),
+            jwt_algorithm: env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string()),
+            jwt_secret: env::var("JWT_SECRET").unwrap_or_default(),
+            jwt_keys_dir: env::var("JWT_KEYS_DIR").ok().filter(|v| !v.is_empty()),
+            jwt_signing_kid: env::var("JWT_SIGNING_KID").ok().filter(|v| !v.is_empty()),
+            jwt_expiration: env::var("JWT_EXPIRATION").unwrap_or_else(|_| "900".to_string()).parse().unwrap_or(900),
+            refresh_token_ttl: env::var("REFRESH_TOKEN_TTL").unwrap_or_else(|_| "2592000".to_string()).parse().unwrap_or(2592000),
+            oidc_issuer: env::var("OIDC_ISSUER").ok().filter(|v| !v.is_empty()),
+            oidc_client_id: env::var("OIDC_CLIENT_ID").unwrap_or_default(),
+            oidc_client_secret: env::var("OIDC_CLIENT_SECRET").unwrap_or_default(),
+            oidc_redirect_url: env::var("OIDC_REDIRECT_URL").unwrap_or_else(|_| "http://localhost:8080/api/auth/oidc/callback".to_string()),
+            oidc_scopes: env::var("OIDC_SCOPES").unwrap_or_else(|_| "openid email profile".to_string()),
+            oidc_allowed_domains: env::var("OIDC_ALLOWED_DOMAINS").unwrap_or_default()
+                .split(',').map(|d| d.trim().to_lowercase()).filter(|d| !d.is_empty()).collect(),
+            oidc_groups_claim: env::var("OIDC_GROUPS_CLAIM").unwrap_or_else(|_| "groups".to_string()),
+            oidc_role_mapping: env::var("OIDC_ROLE_MAPPING").unwrap_or_default()
+                .split(',')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(group, role)| (group.trim().to_string(), role.trim().to_string()))
+                .collect(),
+            oidc_default_role: env::var("OIDC_DEFAULT_ROLE").unwrap_or_else(|_| "osis".to_string()),
+            oidc_sync_roles: env::var("OIDC_SYNC_ROLES").map(|v| v == "true" || v == "1").unwrap_or(false),
+            oidc_require_verified_email: env::var("OIDC_REQUIRE_VERIFIED_EMAIL").map(|v| v != "false" && v != "0").unwrap_or(true),
+            auth_backends: env::var("AUTH_BACKENDS").unwrap_or_else(|_| "local".to_string())
+                .split(',').map(|b| b.trim().to_lowercase()).filter(|b| !b.is_empty()).collect(),
+            ldap_url: env::var("LDAP_URL").unwrap_or_else(|_| "ldap://localhost:389".to_string()),
+            ldap_starttls: env::var("LDAP_STARTTLS").map(|v| v == "true" || v == "1").unwrap_or(false),
+            ldap_bind_dn: env::var("LDAP_BIND_DN").ok().filter(|v| !v.is_empty()),
+            ldap_bind_password: env::var("LDAP_BIND_PASSWORD").ok().filter(|v| !v.is_empty()),
+            ldap_base_dn: env::var("LDAP_BASE_DN").unwrap_or_default(),
+            ldap_user_filter: env::var("LDAP_USER_FILTER").unwrap_or_else(|_| "(&(objectClass=person)(|(uid={login})(mail={login})))".to_string()),
+            ldap_uid_attribute: env::var("LDAP_UID_ATTRIBUTE").unwrap_or_else(|_| "uid".to_string()),
+            ldap_name_attribute: env::var("LDAP_NAME_ATTRIBUTE").unwrap_or_else(|_| "cn".to_string()),
+            ldap_email_attribute: env::var("LDAP_EMAIL_ATTRIBUTE").unwrap_or_else(|_| "mail".to_string()),
+            ldap_group_attribute: env::var("LDAP_GROUP_ATTRIBUTE").unwrap_or_else(|_| "memberOf".to_string()),
+            ldap_role_mapping: env::var("LDAP_ROLE_MAPPING").unwrap_or_default()
+                .split(';') // DNs contain commas
+                .filter_map(|pair| pair.rsplit_once('='))
+                .map(|(group, role)| (group.trim().to_lowercase(), role.trim().to_string()))
+                .collect(),
+            ldap_default_role: env::var("LDAP_DEFAULT_ROLE").unwrap_or_else(|_| "osis".to_string()),
+            ldap_sync_roles: env::var("LDAP_SYNC_ROLES").map(|v| v == "true" || v == "1").unwrap_or(false),
+            login_backoff_after: env::var("LOGIN_BACKOFF_AFTER").unwrap_or_else(|_| "3".to_string()).parse().unwrap_or(3),
+            login_lockout_after: env::var("LOGIN_LOCKOUT_AFTER").unwrap_or_else(|_| "10".to_string()).parse().unwrap_or(10),
+            login_lockout_seconds: env::var("LOGIN_LOCKOUT_SECONDS").unwrap_or_else(|_| "900".to_string()).parse().unwrap_or(900),
+            login_ip_backoff_after: env::var("LOGIN_IP_BACKOFF_AFTER").unwrap_or_else(|_| "20".to_string()).parse().unwrap_or(20),
+            login_ip_lockout_after: env::var("LOGIN_IP_LOCKOUT_AFTER").unwrap_or_else(|_| "100".to_string()).parse().unwrap_or(100),
+            trust_proxy: env::var("TRUST_PROXY").map(|v| v == "true" || v == "1").unwrap_or(false),
+            app_url: env::var("APP_URL").unwrap_or_else(|_| "http://localhost:3000".to_string()),
+            password_reset_ttl: env::var("PASSWORD_RESET_TTL").unwrap_or_else(|_| "3600".to_string()).parse().unwrap_or(3600),
+            mailer: env::var("MAILER").unwrap_or_else(|_| "log".to_string()),
+            mail_from: env::var("MAIL_FROM").unwrap_or_else(|_| "Ferrum <no-reply@ferrum.local>".to_string()),
+            mail_outbox_dir: env::var("MAIL_OUTBOX_DIR").ok().filter(|v| !v.is_empty()),
+            smtp_host: env::var("SMTP_HOST").unwrap_or_else(|_| "localhost".to_string()),
+            smtp_port: env::var("SMTP_PORT").unwrap_or_else(|_| "587".to_string()).parse().unwrap_or(587),
+            smtp_tls: env::var("SMTP_TLS").unwrap_or_else(|_| "starttls".to_string()),
+            smtp_username: env::var("SMTP_USERNAME").ok().filter(|v| !v.is_empty()),
+            smtp_password: env::var("SMTP_PASSWORD").ok().filter(|v| !v.is_empty()),
             host: env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
             port: env::var("PORT").unwrap_or_else(|_| "8080".to_string()).parse().unwrap_or(8080),
         }
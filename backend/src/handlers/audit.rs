@@ -0,0 +1,179 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    Json,
+    response::IntoResponse,
+};
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+use sqlx::{MySql, QueryBuilder};
+
+use crate::models::{AuditEntry, AuditListResponse};
+use crate::state::AppState;
+use crate::middleware::auth::AuthUser;
+use crate::handlers::user::require_admin;
+
+// An export is one query held in memory; narrow the filters for anything larger
+const MAX_EXPORT_ROWS: i64 = 50_000;
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    pub actor_id: Option<String>,
+    pub action: Option<String>, // Exact, or a prefix ending in '.' (e.g. 'auth.')
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub outcome: Option<String>,
+    pub from: Option<NaiveDateTime>, // Inclusive, UTC
+    pub to: Option<NaiveDateTime>,   // Exclusive, UTC
+    pub format: Option<String>,      // Export only: 'csv' (default) or 'json'
+}
+
+fn push_audit_filters(builder: &mut QueryBuilder<'_, MySql>, params: &AuditQuery) {
+    builder.push(" WHERE 1 = 1");
+    if let Some(actor_id) = &params.actor_id {
+        builder.push(" AND actor_id = ").push_bind(actor_id.clone());
+    }
+    if let Some(action) = params.action.as_deref().filter(|a| !a.is_empty()) {
+        match action.strip_suffix('.') {
+            Some(prefix) => {
+                let pattern = format!("{}.%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+                builder.push(" AND action LIKE ").push_bind(pattern);
+            },
+            None => {
+                builder.push(" AND action = ").push_bind(action.to_string());
+            },
+        }
+    }
+    if let Some(target_type) = &params.target_type {
+        builder.push(" AND target_type = ").push_bind(target_type.clone());
+    }
+    if let Some(target_id) = &params.target_id {
+        builder.push(" AND target_id = ").push_bind(target_id.clone());
+    }
+    if let Some(outcome) = &params.outcome {
+        builder.push(" AND outcome = ").push_bind(outcome.clone());
+    }
+    if let Some(from) = params.from {
+        builder.push(" AND created_at >= ").push_bind(from);
+    }
+    if let Some(to) = params.to {
+        builder.push(" AND created_at < ").push_bind(to);
+    }
+}
+
+pub async fn list_audit_events(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Query(params): Query<AuditQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin(&user) {
+        return e.into_response();
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let per_page = params.per_page.unwrap_or(50).clamp(1, 200);
+
+    let mut count: QueryBuilder<MySql> = QueryBuilder::new("SELECT COUNT(*) FROM audit_events");
+    push_audit_filters(&mut count, &params);
+    let total: i64 = match count.build_query_scalar().fetch_one(&state.db).await {
+        Ok(t) => t,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let mut select: QueryBuilder<MySql> = QueryBuilder::new("SELECT * FROM audit_events");
+    push_audit_filters(&mut select, &params);
+    select.push(" ORDER BY created_at DESC, id LIMIT ").push_bind(per_page)
+        .push(" OFFSET ").push_bind((page - 1) * per_page);
+
+    let events: Vec<AuditEntry> = match select.build_query_as().fetch_all(&state.db).await {
+        Ok(e) => e,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    (StatusCode::OK, Json(AuditListResponse { events, total, page, per_page })).into_response()
+}
+
+// Same filters as the list, oldest first, as a download
+pub async fn export_audit_events(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Query(params): Query<AuditQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin(&user) {
+        return e.into_response();
+    }
+
+    let format = params.format.as_deref().unwrap_or("csv");
+    if format != "csv" && format != "json" {
+        return (StatusCode::BAD_REQUEST, "Format must be 'csv' or 'json'").into_response();
+    }
+
+    let mut count: QueryBuilder<MySql> = QueryBuilder::new("SELECT COUNT(*) FROM audit_events");
+    push_audit_filters(&mut count, &params);
+    let total: i64 = match count.build_query_scalar().fetch_one(&state.db).await {
+        Ok(t) => t,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    if total > MAX_EXPORT_ROWS {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "Too many events, narrow the time range or filters").into_response();
+    }
+
+    let mut select: QueryBuilder<MySql> = QueryBuilder::new("SELECT * FROM audit_events");
+    push_audit_filters(&mut select, &params);
+    select.push(" ORDER BY created_at, id");
+
+    let events: Vec<AuditEntry> = match select.build_query_as().fetch_all(&state.db).await {
+        Ok(e) => e,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let stamp = chrono::Utc::now().format("%Y%m%d-%H%M%S");
+    let (content_type, body) = match format {
+        "json" => match serde_json::to_string(&events) {
+            Ok(body) => ("application/json", body),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        },
+        _ => ("text/csv; charset=utf-8", to_csv(&events)),
+    };
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"audit-{}.{}\"", stamp, format)),
+        ],
+        body,
+    ).into_response()
+}
+
+fn to_csv(events: &[AuditEntry]) -> String {
+    let mut out = String::from("id,created_at,actor_id,action,target_type,target_id,outcome,ip,user_agent,details\r\n");
+    for e in events {
+        let details = e.details.as_ref().map(|d| d.to_string()).unwrap_or_default();
+        let fields = [
+            e.id.as_str(),
+            &e.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+            e.actor_id.as_deref().unwrap_or(""),
+            &e.action,
+            e.target_type.as_deref().unwrap_or(""),
+            e.target_id.as_deref().unwrap_or(""),
+            &e.outcome,
+            e.ip.as_deref().unwrap_or(""),
+            e.user_agent.as_deref().unwrap_or(""),
+            &details,
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&row.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+// Quotes every field, and defuses values a spreadsheet would run as a formula
+// (user agents and file names are attacker-controlled)
+fn csv_field(value: &str) -> String {
+    let guard = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) { "'" } else { "" };
+    format!("\"{}{}\"", guard, value.replace('"', "\"\""))
+}
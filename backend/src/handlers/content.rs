@@ -0,0 +1,259 @@
+//! GET /api/files/:id/content streams a file through the backend, with Range and
+//! conditional request support so browsers can seek and revalidate. Reached through
+//! the signed links of DOWNLOAD_MODE=proxy, or directly with a bearer token.
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+
+use crate::models::File;
+use crate::state::AppState;
+use crate::middleware::auth::OptionalAuthUser;
+use crate::middleware::request_meta::RequestMeta;
+use crate::handlers::file::can_view_file;
+use crate::services::audit::{self, AuditEvent, DENIED, SUCCESS};
+use crate::services::download::{self, Disposition};
+use crate::services::storage::ByteRange;
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+#[derive(Debug, Deserialize)]
+pub struct ContentQuery {
+    pub expires: Option<i64>,
+    pub signature: Option<String>,
+    pub disposition: Option<Disposition>, // Default inline
+}
+
+enum RangeRequest {
+    Full,
+    Partial(u64, u64), // Inclusive
+    Unsatisfiable,
+}
+
+pub async fn file_content(
+    State(state): State<AppState>,
+    OptionalAuthUser(opt_user): OptionalAuthUser,
+    meta: RequestMeta,
+    Path(file_id): Path<String>,
+    Query(params): Query<ContentQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let file: Option<File> = sqlx::query_as("SELECT * FROM files WHERE id = ? AND status = 'committed' AND deleted_at IS NULL")
+        .bind(&file_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    let file = match file {
+        Some(f) => f,
+        None => return (StatusCode::NOT_FOUND, "File not found").into_response(),
+    };
+
+    // 1. A signed link was checked when it was handed out; otherwise check the caller
+    let signed = match (params.expires, params.signature.as_deref()) {
+        (Some(expires), Some(signature)) => download::verify(&state.config, &file.id, expires, signature),
+        _ => false,
+    };
+
+    if !signed && !can_view_file(&state, &file, opt_user.as_ref()).await {
+        let mut event = AuditEvent::new("file.download", DENIED).target("file", &file.id);
+        if let Some(u) = &opt_user {
+            event = event.actor(&u.sub);
+        }
+        audit::record(&state, &meta, event).await;
+
+        return if params.signature.is_some() {
+            (StatusCode::FORBIDDEN, "Invalid or expired link").into_response()
+        } else if opt_user.is_none() {
+            (StatusCode::UNAUTHORIZED, "Login required").into_response()
+        } else {
+            (StatusCode::FORBIDDEN, "Access denied").into_response()
+        };
+    }
+
+    // 2. Committed files never change, so id and size identify the bytes
+    let etag = format!("\"{}-{}\"", file.id, file.size);
+    let last_modified = file.created_at.map(|t| t.format(HTTP_DATE).to_string());
+
+    if not_modified(&headers, &etag, file.created_at) {
+        let mut response = StatusCode::NOT_MODIFIED.into_response();
+        set_validators(response.headers_mut(), &etag, last_modified.as_deref());
+        return response;
+    }
+
+    // 3. Range
+    let size = file.size.max(0) as u64;
+    let range = if if_range_matches(&headers, &etag, file.created_at) {
+        headers
+            .get(header::RANGE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| parse_range(v, size))
+            .unwrap_or(RangeRequest::Full)
+    } else {
+        RangeRequest::Full
+    };
+
+    let byte_range = match range {
+        RangeRequest::Full => None,
+        RangeRequest::Partial(start, end) => Some(ByteRange { start, end: Some(end) }),
+        RangeRequest::Unsatisfiable => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", size))],
+            ).into_response();
+        },
+    };
+
+    let object = match state.storage.get(&file.storage_key, byte_range).await {
+        Ok(Some(o)) => o,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Object not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    // A player issues many range requests per view; only count the one from the start.
+    // Signed links were already counted by the endpoint that issued them.
+    if !signed && byte_range.is_none_or(|r| r.start == 0) {
+        let mut event = AuditEvent::new("file.download", SUCCESS).target("file", &file.id)
+            .details(serde_json::json!({ "name": file.name }));
+        if let Some(u) = &opt_user {
+            event = event.actor(&u.sub);
+        }
+        audit::record(&state, &meta, event).await;
+    }
+
+    // 4. Response
+    let content_type = file.mime_type.clone()
+        .filter(|m| !m.is_empty())
+        .or(object.content_type.clone())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let disposition = params.disposition.unwrap_or(Disposition::Inline);
+
+    let mut response = Body::from_stream(object.stream).into_response();
+
+    if let Some(r) = byte_range {
+        *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+        let end = r.start + (object.content_length.max(1) as u64) - 1;
+        insert(response.headers_mut(), header::CONTENT_RANGE, format!("bytes {}-{}/{}", r.start, end, size));
+    }
+
+    let response_headers = response.headers_mut();
+    insert(response_headers, header::CONTENT_TYPE, content_type);
+    insert(response_headers, header::CONTENT_LENGTH, object.content_length.to_string());
+    insert(response_headers, header::CONTENT_DISPOSITION, download::content_disposition(disposition, &file.name));
+    insert(response_headers, header::ACCEPT_RANGES, "bytes".to_string());
+    insert(response_headers, header::CACHE_CONTROL, "private, no-cache".to_string());
+    // User content served from our own origin: never sniffed into HTML, never scripted
+    insert(response_headers, header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string());
+    insert(response_headers, header::CONTENT_SECURITY_POLICY, "sandbox".to_string());
+    set_validators(response_headers, &etag, last_modified.as_deref());
+
+    response
+}
+
+fn insert(headers: &mut HeaderMap, name: header::HeaderName, value: String) {
+    if let Ok(v) = HeaderValue::from_str(&value) {
+        headers.insert(name, v);
+    }
+}
+
+fn set_validators(headers: &mut HeaderMap, etag: &str, last_modified: Option<&str>) {
+    insert(headers, header::ETAG, etag.to_string());
+    if let Some(lm) = last_modified {
+        insert(headers, header::LAST_MODIFIED, lm.to_string());
+    }
+}
+
+fn parse_http_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.trim(), HTTP_DATE).ok()
+}
+
+// Weak comparison, as If-None-Match uses
+fn etag_listed(list: &str, etag: &str) -> bool {
+    list.split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+// If-None-Match wins over If-Modified-Since when both are sent
+fn not_modified(headers: &HeaderMap, etag: &str, modified: Option<NaiveDateTime>) -> bool {
+    if let Some(list) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        return etag_listed(list, etag);
+    }
+
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_http_date);
+
+    match (since, modified) {
+        // HTTP dates have whole seconds
+        (Some(since), Some(modified)) => modified.and_utc().timestamp() <= since.and_utc().timestamp(),
+        _ => false,
+    }
+}
+
+// A Range only applies if the client's cached copy is still the current one
+fn if_range_matches(headers: &HeaderMap, etag: &str, modified: Option<NaiveDateTime>) -> bool {
+    let value = match headers.get(header::IF_RANGE).and_then(|v| v.to_str().ok()) {
+        Some(v) => v.trim(),
+        None => return true,
+    };
+
+    if value.starts_with('"') || value.starts_with("W/") {
+        // Strong comparison; a weak validator never matches
+        return value == etag;
+    }
+
+    match (parse_http_date(value), modified) {
+        (Some(date), Some(modified)) => modified.and_utc().timestamp() == date.and_utc().timestamp(),
+        _ => false,
+    }
+}
+
+// One `bytes=` range. Anything we don't understand, including multiple ranges,
+// is ignored and the whole file sent, as RFC 9110 allows.
+fn parse_range(value: &str, size: u64) -> RangeRequest {
+    let spec = match value.trim().strip_prefix("bytes=") {
+        Some(s) if !s.contains(',') => s.trim(),
+        _ => return RangeRequest::Full,
+    };
+
+    let (first, last) = match spec.split_once('-') {
+        Some(parts) => parts,
+        None => return RangeRequest::Full,
+    };
+
+    match (first.trim(), last.trim()) {
+        // Suffix: the last n bytes
+        ("", n) => match n.parse::<u64>() {
+            Err(_) => RangeRequest::Full,
+            Ok(0) => RangeRequest::Unsatisfiable,
+            Ok(_) if size == 0 => RangeRequest::Unsatisfiable,
+            Ok(n) => RangeRequest::Partial(size.saturating_sub(n), size - 1),
+        },
+        (first, last) => {
+            let start = match first.parse::<u64>() {
+                Ok(s) => s,
+                Err(_) => return RangeRequest::Full,
+            };
+            let end = match last {
+                "" => None,
+                l => match l.parse::<u64>() {
+                    Ok(e) if e >= start => Some(e),
+                    _ => return RangeRequest::Full,
+                },
+            };
+
+            if start >= size {
+                RangeRequest::Unsatisfiable
+            } else {
+                RangeRequest::Partial(start, end.unwrap_or(u64::MAX).min(size - 1))
+            }
+        },
+    }
+}
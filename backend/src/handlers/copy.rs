@@ -0,0 +1,192 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+    response::IntoResponse,
+};
+use sqlx::{query, query_as};
+use uuid::Uuid;
+
+use crate::models::{CopyJob, CopyRequest, File};
+use crate::state::AppState;
+use crate::middleware::auth::AuthUser;
+use crate::middleware::request_meta::RequestMeta;
+use crate::handlers::file::can_view_file;
+use crate::handlers::folder::{can_edit_folder, can_view_folder, find_folder, is_within};
+use crate::services::audit::{self, AuditEvent, SUCCESS};
+use crate::services::auth::Claims;
+use crate::services::copy::{copy_file as copy_file_object, run_folder_copy, tree_size};
+use crate::services::quota;
+
+/// Resolves where a copy should land: omitted -> `default`, "root" -> caller's root (None).
+/// The caller needs editor rights on the destination folder.
+async fn resolve_destination(
+    state: &AppState,
+    requested: Option<&str>,
+    default: Option<&str>,
+    user: &Claims,
+) -> Result<Option<String>, (StatusCode, &'static str)> {
+    let dest_id = match requested {
+        Some("root") => return Ok(None),
+        Some(id) => id,
+        None => match default {
+            Some(id) => id,
+            None => return Ok(None),
+        },
+    };
+
+    let dest = find_folder(state, dest_id)
+        .await
+        .ok_or((StatusCode::NOT_FOUND, "Destination folder not found"))?;
+
+    if !can_edit_folder(state, &dest, user).await {
+        return Err((StatusCode::FORBIDDEN, "No permission to copy into the destination folder"));
+    }
+
+    Ok(Some(dest.id))
+}
+
+pub async fn copy_file(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(file_id): Path<String>,
+    Json(payload): Json<CopyRequest>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    // Copies are new content charged to the caller, so same roles as uploading
+    let allowed_roles = ["admin", "osis", "media_guru"];
+    if !allowed_roles.contains(&user.role.as_str()) {
+        return (StatusCode::FORBIDDEN, "Insufficient role to copy files").into_response();
+    }
+
+    let file: Option<File> = query_as("SELECT * FROM files WHERE id = ? AND status = 'committed' AND deleted_at IS NULL")
+        .bind(&file_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    let file = match file {
+        Some(f) => f,
+        None => return (StatusCode::NOT_FOUND, "File not found").into_response(),
+    };
+
+    if !can_view_file(&state, &file, Some(&user)).await {
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    let dest = match resolve_destination(&state, payload.destination_id.as_deref(), file.folder_id.as_deref(), &user).await {
+        Ok(d) => d,
+        Err(e) => return e.into_response(),
+    };
+    let name = payload.name.unwrap_or_else(|| file.name.clone());
+
+    match quota::has_room(&state, &user.sub, file.size).await {
+        Ok(true) => {},
+        Ok(false) => return (StatusCode::PAYLOAD_TOO_LARGE, "Storage quota exceeded").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
+    match copy_file_object(&state, &file, dest.as_deref(), &name, &user.sub).await {
+        Ok(copy) => {
+            let _ = state.cache.invalidate_folder_listing(dest.as_deref().unwrap_or("root")).await;
+            audit::record(&state, &meta, AuditEvent::new("file.copy", SUCCESS).actor(&user.sub).target("file", &copy.id)
+                .details(serde_json::json!({ "source_id": file.id, "name": copy.name, "folder_id": copy.folder_id, "size": copy.size }))).await;
+            (StatusCode::CREATED, Json(copy)).into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn copy_folder(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(folder_id): Path<String>,
+    Json(payload): Json<CopyRequest>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let allowed_roles = ["admin", "osis", "media_guru"];
+    if !allowed_roles.contains(&user.role.as_str()) {
+        return (StatusCode::FORBIDDEN, "Insufficient role to copy folders").into_response();
+    }
+
+    let folder = match find_folder(&state, &folder_id).await {
+        Some(f) => f,
+        None => return (StatusCode::NOT_FOUND, "Folder not found").into_response(),
+    };
+
+    if !can_view_folder(&state, &folder, Some(&user)).await {
+        return (StatusCode::FORBIDDEN, "Access denied").into_response();
+    }
+
+    let dest = match resolve_destination(&state, payload.destination_id.as_deref(), folder.parent_id.as_deref(), &user).await {
+        Ok(d) => d,
+        Err(e) => return e.into_response(),
+    };
+
+    if let Some(dest_id) = dest.as_deref() {
+        if is_within(&state, dest_id, &folder.id).await {
+            return (StatusCode::BAD_REQUEST, "Cannot copy a folder into itself or one of its subfolders").into_response();
+        }
+    }
+
+    // Checked against the whole tree before anything is written
+    let size = match tree_size(&state, &folder.id).await {
+        Ok(size) => size,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    match quota::has_room(&state, &user.sub, size).await {
+        Ok(true) => {},
+        Ok(false) => return (StatusCode::PAYLOAD_TOO_LARGE, "Storage quota exceeded").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
+    let name = payload.name.unwrap_or_else(|| folder.name.clone());
+    let job_id = Uuid::new_v4().to_string();
+
+    let result = query("INSERT INTO copy_jobs (id, user_id, source_folder_id, destination_id) VALUES (?, ?, ?, ?)")
+        .bind(&job_id)
+        .bind(&user.sub)
+        .bind(&folder.id)
+        .bind(&dest)
+        .execute(&state.db)
+        .await;
+
+    if let Err(e) = result {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    // The job's own row records how it ends
+    audit::record(&state, &meta, AuditEvent::new("folder.copy", SUCCESS).actor(&user.sub).target("folder", &folder.id)
+        .details(serde_json::json!({ "job_id": job_id, "name": name, "destination_id": dest, "size": size }))).await;
+
+    // Large trees can take a while; run in the background and let the client poll the job
+    tokio::spawn(run_folder_copy(state.clone(), job_id.clone(), folder, dest, name, user.clone()));
+
+    (StatusCode::ACCEPTED, Json(serde_json::json!({ "job_id": job_id }))).into_response()
+}
+
+pub async fn get_copy_job(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(job_id): Path<String>,
+) -> impl IntoResponse {
+    let job: Option<CopyJob> = query_as("SELECT * FROM copy_jobs WHERE id = ?")
+        .bind(&job_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    match job {
+        Some(job) if job.user_id == user.sub || user.role == "admin" => (StatusCode::OK, Json(job)).into_response(),
+        Some(_) => (StatusCode::FORBIDDEN, "Access denied").into_response(),
+        None => (StatusCode::NOT_FOUND, "Job not found").into_response(),
+    }
+}
@@ -1,23 +1,33 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     Json,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
 };
+use serde::Deserialize;
 use sqlx::query;
 use uuid::Uuid;
 use std::time::Duration;
-use crate::models::{FileUploadRequest, FileUploadResponse, File};
+use crate::models::{FileUploadRequest, FileUploadResponse, UpdateFileDto, File};
 use crate::state::AppState;
-use crate::services::minio::{get_presigned_put_url, get_presigned_get_url};
-use crate::services::redis_cache::{increment_usage, invalidate_folder_listing, check_rate_limit};
 use crate::middleware::auth::{AuthUser, OptionalAuthUser};
+use crate::middleware::request_meta::RequestMeta;
+use crate::handlers::folder::{can_edit_folder, find_folder, folder_role};
+use crate::services::audit::{self, AuditEvent, DENIED, FAILURE, SUCCESS};
+use crate::services::auth::Claims;
+use crate::services::download::{self, Disposition};
+use crate::services::quota;
+use crate::services::trash::trash_file;
 
 pub async fn upload_file(
     State(state): State<AppState>,
     AuthUser(user): AuthUser,
     Json(payload): Json<FileUploadRequest>,
 ) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
     // Role Check: Only admin, osis, media_guru can upload files
     let allowed_roles = ["admin", "osis", "media_guru"];
     if !allowed_roles.contains(&user.role.as_str()) {
@@ -25,36 +35,57 @@ pub async fn upload_file(
     }
 
     // 0. Rate Limiting (10 uploads per minute)
-    let allowed = check_rate_limit(&state.redis, &user.sub, "upload", 10, 60).await.unwrap_or(true);
+    let allowed = state.cache.check_rate_limit(&user.sub, "upload", 10, 60).await.unwrap_or(true);
     if !allowed {
         return (StatusCode::TOO_MANY_REQUESTS, "Upload limit exceeded (10/min)").into_response();
     }
 
+    // The declared size is what usage is counted and verified against
+    if payload.size < 0 {
+        return (StatusCode::BAD_REQUEST, "File size must not be negative").into_response();
+    }
+
+    let db_folder_id = match upload_destination(&state, &payload.folder_id, &user).await {
+        Ok(d) => d,
+        Err(e) => return e.into_response(),
+    };
+
+    match quota::has_room(&state, &user.sub, payload.size).await {
+        Ok(true) => {},
+        Ok(false) => return (StatusCode::PAYLOAD_TOO_LARGE, "Storage quota exceeded").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
     let file_id = Uuid::new_v4().to_string();
     let storage_key = format!("{}/{}", payload.folder_id, file_id); // Simple key structure
 
     // 1. Generate Presigned URL
-    let presigned_url = match get_presigned_put_url(
-        &state.s3,
-        &state.config.s3_bucket,
-        &storage_key,
-        Some(&payload.mime_type),
-        Duration::from_secs(3600), // 1 hour
-    ).await {
-        Ok(url) => url,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    // A PUT URL can't limit the size, so /complete catches a wrong one after the fact;
+    // a POST policy makes storage refuse it up front.
+    let presigned = if state.config.upload_mode == "post" {
+        state.storage.presign_post(
+            &storage_key,
+            &payload.mime_type,
+            payload.size,
+            Duration::from_secs(3600), // 1 hour
+        ).await.map(|post| ("POST", post.url, Some(post.fields)))
+    } else {
+        state.storage.presign_put(
+            &storage_key,
+            Some(&payload.mime_type),
+            Duration::from_secs(3600), // 1 hour
+        ).await.map(|url| ("PUT", url, None))
     };
 
-    // 2. Insert Metadata into DB (Optimistic)
-    // Risk: Client fails upload, DB has record. Real systems use status column/webhooks.
-    
-    let db_folder_id = if payload.folder_id == "root" {
-        None
-    } else {
-        Some(payload.folder_id.clone())
+    let (upload_method, presigned_url, fields) = match presigned {
+        Ok(p) => p,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     };
 
-    let result = query("INSERT INTO files (id, name, folder_id, owner_id, storage_key, size, mime_type, is_public) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+    // 2. Insert Metadata into DB as 'pending'
+    // The row stays hidden from listings until the client calls /complete and we verify the object.
+
+    let result = query("INSERT INTO files (id, name, folder_id, owner_id, storage_key, size, mime_type, is_public, status) VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'pending')")
         .bind(&file_id)
         .bind(&payload.name)
         .bind(&db_folder_id)
@@ -68,30 +99,162 @@ pub async fn upload_file(
 
     match result {
         Ok(_) => {
-            // Update Cache
-            let _ = increment_usage(&state.redis, &user.sub, payload.size).await;
-            let _ = invalidate_folder_listing(&state.redis, &payload.folder_id).await;
-            
+            // Usage and listings are only updated once the upload is completed.
             (StatusCode::CREATED, Json(FileUploadResponse {
                 file_id,
                 presigned_url,
                 storage_key,
+                upload_method: upload_method.to_string(),
+                fields,
             })).into_response()
         },
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
+/// Where an upload lands: "root" is the caller's own root (None), anything else must be
+/// a folder they can edit. Shared with the tus creation endpoint.
+pub(crate) async fn upload_destination(state: &AppState, folder_id: &str, user: &Claims) -> Result<Option<String>, (StatusCode, &'static str)> {
+    if folder_id == "root" {
+        return Ok(None);
+    }
+
+    let folder = find_folder(state, folder_id)
+        .await
+        .ok_or((StatusCode::NOT_FOUND, "Folder not found"))?;
+
+    if !can_edit_folder(state, &folder, user).await {
+        return Err((StatusCode::FORBIDDEN, "No permission to upload into this folder"));
+    }
+
+    Ok(Some(folder.id))
+}
+
+pub async fn complete_upload(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(file_id): Path<String>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    // 1. Get File Metadata (a trashed upload stays in the trash)
+    let file: Option<File> = sqlx::query_as("SELECT * FROM files WHERE id = ? AND deleted_at IS NULL")
+        .bind(&file_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    let file = match file {
+        Some(f) => f,
+        None => return (StatusCode::NOT_FOUND, "File not found").into_response(),
+    };
+
+    if file.owner_id != user.sub {
+        return (StatusCode::FORBIDDEN, "Only the uploader can complete this upload").into_response();
+    }
+
+    // A retry on a committed file verifies it again: a presigned PUT stays valid after
+    // the commit and may have replaced the object since
+    if file.status == "failed" {
+        return (StatusCode::CONFLICT, "Upload has already failed verification").into_response();
+    }
+
+    finalize_upload(&state, &meta, file).await
+}
+
+/// Reconciles an upload with storage: the object must have the declared size and type,
+/// which is what usage is counted by. A pending file is then committed; on a mismatch
+/// the object is deleted, the file marked failed and any usage it was counted for given
+/// back. Shared by the single-PUT/POST, multipart and tus completion paths.
+pub(crate) async fn finalize_upload(state: &AppState, meta: &RequestMeta, mut file: File) -> Response {
+    // 2. Verify the object actually landed in storage
+    let head = match state.storage.head(&file.storage_key).await {
+        Ok(Some(h)) => h,
+        Ok(None) => return (StatusCode::CONFLICT, "Object has not been uploaded yet").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let size_matches = head.size == file.size;
+    let type_matches = match (&head.content_type, &file.mime_type) {
+        (Some(actual), Some(declared)) => mime_essence(actual).eq_ignore_ascii_case(mime_essence(declared)),
+        (None, None) => true,
+        _ => false,
+    };
+
+    if !size_matches || !type_matches {
+        // Guarded on the status we loaded, so only one request rejects (and refunds) it
+        let rejected = query("UPDATE files SET status = 'failed' WHERE id = ? AND status = ?")
+            .bind(&file.id)
+            .bind(&file.status)
+            .execute(&state.db)
+            .await;
+
+        if matches!(rejected, Ok(r) if r.rows_affected() == 1) {
+            let _ = state.storage.delete(&file.storage_key).await;
+            if file.status == "committed" {
+                let _ = state.cache.decrement_usage(&file.owner_id, file.size).await;
+                let _ = state.cache.invalidate_folder_listing(file.folder_id.as_deref().unwrap_or("root")).await;
+                let _ = state.cache.invalidate_file_meta(&file.id).await;
+            }
+        }
+
+        audit::record(state, meta, AuditEvent::new("file.upload", FAILURE).actor(&file.owner_id).target("file", &file.id)
+            .details(serde_json::json!({
+                "name": file.name,
+                "reason": "verification_failed",
+                "declared_size": file.size,
+                "actual_size": head.size,
+                "declared_type": file.mime_type,
+                "actual_type": head.content_type,
+            }))).await;
+
+        return (StatusCode::UNPROCESSABLE_ENTITY, "Uploaded object does not match the declared size or type").into_response();
+    }
+
+    // 3. Commit (guarded so a concurrent retry doesn't double count usage)
+    let result = query("UPDATE files SET status = 'committed', upload_id = NULL WHERE id = ? AND status = 'pending'")
+        .bind(&file.id)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) => {
+            if r.rows_affected() == 1 {
+                let _ = state.cache.increment_usage(&file.owner_id, file.size).await;
+                let _ = state.cache.invalidate_folder_listing(file.folder_id.as_deref().unwrap_or("root")).await;
+                audit::record(state, meta, AuditEvent::new("file.upload", SUCCESS).actor(&file.owner_id).target("file", &file.id)
+                    .details(serde_json::json!({ "name": file.name, "size": file.size, "folder_id": file.folder_id }))).await;
+            }
+            file.status = "committed".to_string();
+            file.upload_id = None;
+            (StatusCode::OK, Json(file)).into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+// "text/plain; charset=utf-8" -> "text/plain"
+fn mime_essence(mime: &str) -> &str {
+    mime.split(';').next().unwrap_or("").trim()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DownloadQuery {
+    pub disposition: Option<Disposition>, // Default attachment; inline for in-browser previews
+}
+
 pub async fn download_file(
     State(state): State<AppState>,
     OptionalAuthUser(opt_user): OptionalAuthUser,
+    meta: RequestMeta,
     Path(file_id): Path<String>,
+    Query(params): Query<DownloadQuery>,
 ) -> impl IntoResponse {
-    let user_sub = opt_user.as_ref().map(|u| u.sub.clone());
-    let user_role = opt_user.as_ref().map(|u| u.role.clone());
-
     // 1. Get File Metadata
-    let file: Option<File> = sqlx::query_as("SELECT * FROM files WHERE id = ?")
+    let file: Option<File> = sqlx::query_as("SELECT * FROM files WHERE id = ? AND status = 'committed' AND deleted_at IS NULL")
         .bind(&file_id)
         .fetch_optional(&state.db)
         .await
@@ -103,51 +266,203 @@ pub async fn download_file(
     };
 
     // 2. Check Permission
-    let is_public = file.is_public;
-    let is_owner = user_sub.as_ref() == Some(&file.owner_id);
-    let is_admin = user_role.as_deref() == Some("admin");
-
-    let mut access_granted = is_public || is_owner || is_admin;
-
-    if !access_granted {
-        if let Some(sub) = &user_sub {
-             // Check folder permissions (simplistic check: if user has access to folder, they can download file?)
-             // Or maybe file-specific sharing? The schema only has folder_permissions.
-             // Assumption: Folder View/Edit permission grants access to files inside.
-             let folder_id = &file.folder_id;
-             
-             // Reuse folder permission logic (or simple DB check)
-             let has_perm: Option<i64> = sqlx::query_scalar("SELECT COUNT(*) FROM folder_permissions WHERE folder_id = ? AND user_id = ?") 
-                .bind(folder_id)
-                .bind(sub)
-                .fetch_one(&state.db)
-                .await
-                .ok();
-             
-             if has_perm.unwrap_or(0) > 0 {
-                 access_granted = true;
-             }
-        }
-    }
+    if !can_view_file(&state, &file, opt_user.as_ref()).await {
+         let mut event = AuditEvent::new("file.download", DENIED).target("file", &file.id);
+         if let Some(u) = &opt_user {
+             event = event.actor(&u.sub);
+         }
+         audit::record(&state, &meta, event).await;
 
-    if !access_granted {
-         if user_sub.is_none() {
+         if opt_user.is_none() {
              return (StatusCode::UNAUTHORIZED, "Login required").into_response();
          } else {
              return (StatusCode::FORBIDDEN, "Access denied").into_response();
          }
     }
 
-    // 3. Generate Presigned GET URL
-    let presigned_url = match get_presigned_get_url(
-        &state.s3,
-        &state.config.s3_bucket,
-        &file.storage_key,
+    // 3. Presigned GET URL, or a signed link to /content when downloads are proxied
+    let download_url = match download::download_url(
+        &state,
+        &file,
+        params.disposition.unwrap_or(Disposition::Attachment),
         Duration::from_secs(300), // 5 minutes
     ).await {
         Ok(url) => url,
         Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     };
 
-    (StatusCode::OK, Json(serde_json::json!({ "url": presigned_url }))).into_response()
+    // Public files are downloadable anonymously; those events simply have no actor
+    let mut event = AuditEvent::new("file.download", SUCCESS).target("file", &file.id)
+        .details(serde_json::json!({ "name": file.name }));
+    if let Some(u) = &opt_user {
+        event = event.actor(&u.sub);
+    }
+    audit::record(&state, &meta, event).await;
+
+    (StatusCode::OK, Json(serde_json::json!({ "url": download_url }))).into_response()
+}
+
+/// Public, owner, admin, or any grant on the containing folder.
+/// The schema only has folder_permissions, so folder access grants access to the files inside.
+pub(crate) async fn can_view_file(state: &AppState, file: &File, user: Option<&Claims>) -> bool {
+    if file.is_public {
+        return true;
+    }
+
+    let user = match user {
+        Some(u) => u,
+        None => return false,
+    };
+
+    if file.owner_id == user.sub || user.role == "admin" {
+        return true;
+    }
+
+    match file.folder_id.as_deref() {
+        Some(folder_id) => folder_role(state, folder_id, &user.sub).await.is_some(),
+        None => false,
+    }
+}
+
+/// Owner/admin, or editor on the containing folder.
+pub(crate) async fn can_edit_file(state: &AppState, file: &File, user: &Claims) -> bool {
+    if file.owner_id == user.sub || user.role == "admin" {
+        return true;
+    }
+
+    match file.folder_id.as_deref() {
+        Some(folder_id) => match find_folder(state, folder_id).await {
+            Some(folder) => can_edit_folder(state, &folder, user).await,
+            None => false,
+        },
+        None => false,
+    }
+}
+
+pub async fn delete_file(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(file_id): Path<String>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let file: Option<File> = sqlx::query_as("SELECT * FROM files WHERE id = ? AND deleted_at IS NULL")
+        .bind(&file_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    let file = match file {
+        Some(f) => f,
+        None => return (StatusCode::NOT_FOUND, "File not found").into_response(),
+    };
+
+    if !can_edit_file(&state, &file, &user).await {
+        return (StatusCode::FORBIDDEN, "No permission to delete this file").into_response();
+    }
+
+    match trash_file(&state, &file, &user.sub).await {
+        Ok(_) => {
+            audit::record(&state, &meta, AuditEvent::new("file.delete", SUCCESS).actor(&user.sub).target("file", &file.id)
+                .details(serde_json::json!({ "name": file.name, "folder_id": file.folder_id }))).await;
+            StatusCode::NO_CONTENT.into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn update_file(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(file_id): Path<String>,
+    Json(payload): Json<UpdateFileDto>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let file: Option<File> = sqlx::query_as("SELECT * FROM files WHERE id = ? AND deleted_at IS NULL")
+        .bind(&file_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    let file = match file {
+        Some(f) => f,
+        None => return (StatusCode::NOT_FOUND, "File not found").into_response(),
+    };
+
+    // Source: editor where the file currently lives
+    if !can_edit_file(&state, &file, &user).await {
+        return (StatusCode::FORBIDDEN, "No permission to modify this file").into_response();
+    }
+
+    let name = match &payload.name {
+        Some(n) if n.trim().is_empty() => return (StatusCode::BAD_REQUEST, "Name cannot be empty").into_response(),
+        Some(n) => n.trim().to_string(),
+        None => file.name.clone(),
+    };
+
+    // Normalize destination: omitted -> unchanged, "root" -> None
+    let new_folder_id = match &payload.folder_id {
+        Some(id) if id == "root" => None,
+        Some(id) => Some(id.clone()),
+        None => file.folder_id.clone(),
+    };
+    let moving = new_folder_id != file.folder_id;
+
+    if moving {
+        match &new_folder_id {
+            Some(dest_id) => {
+                let dest = match find_folder(&state, dest_id).await {
+                    Some(d) => d,
+                    None => return (StatusCode::NOT_FOUND, "Destination folder not found").into_response(),
+                };
+                if !can_edit_folder(&state, &dest, &user).await {
+                    return (StatusCode::FORBIDDEN, "No permission to move into the destination folder").into_response();
+                }
+            }
+            None => {
+                if file.owner_id != user.sub && user.role != "admin" {
+                    return (StatusCode::FORBIDDEN, "Only the owner can move this file to root").into_response();
+                }
+            }
+        }
+    }
+
+    let result = query("UPDATE files SET name = ?, folder_id = ? WHERE id = ?")
+        .bind(&name)
+        .bind(&new_folder_id)
+        .bind(&file.id)
+        .execute(&state.db)
+        .await;
+
+    if let Err(e) = result {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    let _ = state.cache.invalidate_file_meta(&file.id).await;
+    let _ = state.cache.invalidate_folder_listing(file.folder_id.as_deref().unwrap_or("root")).await;
+    if moving {
+        let _ = state.cache.invalidate_folder_listing(new_folder_id.as_deref().unwrap_or("root")).await;
+    }
+
+    audit::record(&state, &meta, AuditEvent::new("file.update", SUCCESS).actor(&user.sub).target("file", &file.id)
+        .details(serde_json::json!({
+            "name": name,
+            "previous_name": file.name,
+            "folder_id": new_folder_id,
+            "previous_folder_id": file.folder_id,
+        }))).await;
+
+    let updated = File {
+        name,
+        folder_id: new_folder_id,
+        ..file
+    };
+    (StatusCode::OK, Json(updated)).into_response()
 }
@@ -0,0 +1,17 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    Json,
+    response::IntoResponse,
+};
+
+use crate::state::AppState;
+
+// Public keys other services use to verify our access tokens. Empty with HS256.
+pub async fn jwks(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(state.jwt_keys.jwks().clone()),
+    ).into_response()
+}
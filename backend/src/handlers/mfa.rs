@@ -0,0 +1,436 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+    response::{IntoResponse, Response},
+};
+use bcrypt::verify;
+use chrono::Utc;
+use sqlx::{query, query_as, query_scalar};
+use uuid::Uuid;
+
+use crate::models::{
+    MfaChallengeResponse, MfaCodeDto, MfaConfirmDto, MfaConfirmResponse, MfaDisableDto, MfaEnrollDto,
+    MfaEnrollResponse, MfaPolicy, MfaVerifyDto, UpdateMfaPolicyDto, User,
+};
+use crate::state::AppState;
+use crate::middleware::auth::{AuthUser, OptionalAuthUser};
+use crate::middleware::request_meta::RequestMeta;
+use crate::handlers::user::{auth_response, find_user, require_admin, ROLES};
+use crate::services::audit::{self, AuditEvent, FAILURE, SUCCESS};
+use crate::services::session::{hash_token, issue_tokens, random_token, revoke_user_sessions};
+use crate::services::totp;
+
+const ISSUER: &str = "Ferrum";
+const CHALLENGE_TTL: u64 = 300; // 5 minutes to enter the code
+const RECOVERY_CODE_COUNT: usize = 10;
+
+async fn mfa_required_for(state: &AppState, role: &str) -> bool {
+    let required: Option<bool> = query_scalar("SELECT required FROM mfa_policies WHERE role = ?")
+        .bind(role)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+    required.unwrap_or(false)
+}
+
+/// Called by login once the password checked out. `Some` means the user must pass
+/// (or first set up) a second factor, and the response carries the challenge token.
+pub(crate) async fn login_challenge(state: &AppState, user: &User) -> Option<Response> {
+    match issue_challenge(state, user).await {
+        Ok(Some(challenge)) => Some((StatusCode::OK, Json(challenge)).into_response()),
+        Ok(None) => None,
+        Err(e) => Some(e.into_response()),
+    }
+}
+
+pub(crate) async fn issue_challenge(state: &AppState, user: &User) -> Result<Option<MfaChallengeResponse>, (StatusCode, &'static str)> {
+    let purpose = if user.totp_enabled {
+        "verify"
+    } else if mfa_required_for(state, &user.role).await {
+        "enroll"
+    } else {
+        return Ok(None);
+    };
+
+    let token = random_token();
+    let value = format!("{}:{}", purpose, user.id);
+    state.cache.store_mfa_challenge(&hash_token(&token), &value, CHALLENGE_TTL)
+        .await
+        .map_err(|_| (StatusCode::SERVICE_UNAVAILABLE, "MFA challenge store unavailable"))?;
+
+    Ok(Some(MfaChallengeResponse {
+        mfa_required: true,
+        enrollment_required: purpose == "enroll",
+        mfa_token: token,
+        expires_in: CHALLENGE_TTL,
+    }))
+}
+
+async fn challenge_user(state: &AppState, token: &str, purpose: &str) -> Result<User, (StatusCode, &'static str)> {
+    let value = state.cache.get_mfa_challenge(&hash_token(token))
+        .await
+        .map_err(|_| (StatusCode::SERVICE_UNAVAILABLE, "MFA challenge store unavailable"))?
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid or expired MFA token"))?;
+
+    let user_id = value
+        .strip_prefix(purpose)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid or expired MFA token"))?;
+
+    match find_user(state, user_id).await {
+        Some(u) if u.is_active => Ok(u),
+        _ => Err((StatusCode::UNAUTHORIZED, "Account is deactivated or no longer exists")),
+    }
+}
+
+// Either the caller's own account (access token) or the one behind an enrollment challenge
+async fn enrolling_user(state: &AppState, user_id: Option<&str>, mfa_token: Option<&str>) -> Result<User, (StatusCode, &'static str)> {
+    match (user_id, mfa_token) {
+        (Some(id), _) => find_user(state, id).await.ok_or((StatusCode::NOT_FOUND, "User not found")),
+        (None, Some(token)) => challenge_user(state, token, "enroll").await,
+        (None, None) => Err((StatusCode::UNAUTHORIZED, "Missing bearer token or MFA token")),
+    }
+}
+
+/// Accepts a current TOTP code (each time step only once) or an unused recovery code.
+async fn check_code(state: &AppState, user: &User, code: &str) -> bool {
+    if let Some(secret) = user.totp_secret.as_deref() {
+        if let Some(step) = totp::verify(secret, code, Utc::now().timestamp()) {
+            let accepted = query("UPDATE users SET totp_last_step = ? WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)")
+                .bind(step)
+                .bind(&user.id)
+                .bind(step)
+                .execute(&state.db)
+                .await;
+            return matches!(accepted, Ok(r) if r.rows_affected() == 1);
+        }
+    }
+
+    let used = query("UPDATE mfa_recovery_codes SET used_at = ? WHERE user_id = ? AND code_hash = ? AND used_at IS NULL LIMIT 1")
+        .bind(Utc::now().naive_utc())
+        .bind(&user.id)
+        .bind(hash_token(&normalize_recovery_code(code)))
+        .execute(&state.db)
+        .await;
+    matches!(used, Ok(r) if r.rows_affected() == 1)
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+// Replaces all recovery codes; the plaintext codes are only ever shown in this response
+async fn replace_recovery_codes(state: &AppState, user_id: &str) -> Result<Vec<String>, (StatusCode, &'static str)> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let raw = normalize_recovery_code(&random_token());
+            format!("{}-{}", &raw[..5], &raw[5..10])
+        })
+        .collect();
+
+    let mut tx = state.db.begin().await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))?;
+
+    query("DELETE FROM mfa_recovery_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to replace recovery codes"))?;
+
+    for code in &codes {
+        query("INSERT INTO mfa_recovery_codes (id, user_id, code_hash) VALUES (?, ?, ?)")
+            .bind(Uuid::new_v4().to_string())
+            .bind(user_id)
+            .bind(hash_token(&normalize_recovery_code(code)))
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to store recovery codes"))?;
+    }
+
+    tx.commit().await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))?;
+
+    Ok(codes)
+}
+
+// Second login step: trade the challenge token plus a code for a session
+pub async fn verify_mfa(
+    State(state): State<AppState>,
+    meta: RequestMeta,
+    Json(payload): Json<MfaVerifyDto>,
+) -> impl IntoResponse {
+    let token_hash = hash_token(&payload.mfa_token);
+
+    // Caps guessing per challenge; a new challenge needs the password again
+    let allowed = state.cache.check_rate_limit(&token_hash, "mfa_verify", 5, CHALLENGE_TTL as i64).await.unwrap_or(true);
+    if !allowed {
+        let _ = state.cache.delete_mfa_challenge(&token_hash).await;
+        return (StatusCode::TOO_MANY_REQUESTS, "Too many attempts, sign in again").into_response();
+    }
+
+    let user = match challenge_user(&state, &payload.mfa_token, "verify").await {
+        Ok(u) => u,
+        Err(e) => return e.into_response(),
+    };
+
+    if !check_code(&state, &user, &payload.code).await {
+        audit::record(&state, &meta, AuditEvent::new("auth.mfa_verify", FAILURE).actor(&user.id)).await;
+        return (StatusCode::UNAUTHORIZED, "Invalid code").into_response();
+    }
+
+    let _ = state.cache.delete_mfa_challenge(&token_hash).await;
+
+    audit::record(&state, &meta, AuditEvent::new("auth.mfa_verify", SUCCESS).actor(&user.id)).await;
+
+    match issue_tokens(&state, &user, None).await {
+        Ok(tokens) => (StatusCode::OK, Json(auth_response(&state, tokens, user))).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+// Starts enrollment: generates a secret for the authenticator app. Not active until confirmed.
+pub async fn enroll_mfa(
+    State(state): State<AppState>,
+    OptionalAuthUser(claims): OptionalAuthUser,
+    payload: Option<Json<MfaEnrollDto>>,
+) -> impl IntoResponse {
+    if claims.as_ref().is_some_and(|c| !c.is_session()) {
+        return (StatusCode::FORBIDDEN, "Not available to API tokens").into_response();
+    }
+
+    let mfa_token = payload.and_then(|Json(p)| p.mfa_token);
+    let user = match enrolling_user(&state, claims.as_ref().map(|c| c.sub.as_str()), mfa_token.as_deref()).await {
+        Ok(u) => u,
+        Err(e) => return e.into_response(),
+    };
+
+    if user.totp_enabled {
+        return (StatusCode::CONFLICT, "Two-factor authentication is already enabled").into_response();
+    }
+
+    let secret = totp::generate_secret();
+    let result = query("UPDATE users SET totp_secret = ?, totp_last_step = NULL WHERE id = ? AND totp_enabled = FALSE")
+        .bind(&secret)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(_) => {
+            let otpauth_uri = totp::otpauth_uri(&secret, &user.email, ISSUER);
+            (StatusCode::OK, Json(MfaEnrollResponse { secret, otpauth_uri })).into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+// Finishes enrollment with a first valid code and returns the recovery codes
+pub async fn confirm_mfa(
+    State(state): State<AppState>,
+    OptionalAuthUser(claims): OptionalAuthUser,
+    Json(payload): Json<MfaConfirmDto>,
+) -> impl IntoResponse {
+    if claims.as_ref().is_some_and(|c| !c.is_session()) {
+        return (StatusCode::FORBIDDEN, "Not available to API tokens").into_response();
+    }
+
+    let user = match enrolling_user(&state, claims.as_ref().map(|c| c.sub.as_str()), payload.mfa_token.as_deref()).await {
+        Ok(u) => u,
+        Err(e) => return e.into_response(),
+    };
+
+    if user.totp_enabled {
+        return (StatusCode::CONFLICT, "Two-factor authentication is already enabled").into_response();
+    }
+
+    let step = match user.totp_secret.as_deref().map(|s| totp::verify(s, &payload.code, Utc::now().timestamp())) {
+        None => return (StatusCode::BAD_REQUEST, "Start enrollment first").into_response(),
+        Some(None) => return (StatusCode::UNAUTHORIZED, "Invalid code").into_response(),
+        Some(Some(step)) => step,
+    };
+
+    let enabled = query("UPDATE users SET totp_enabled = TRUE, totp_last_step = ? WHERE id = ? AND totp_enabled = FALSE")
+        .bind(step)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await;
+
+    match enabled {
+        Ok(r) if r.rows_affected() == 0 => return (StatusCode::CONFLICT, "Two-factor authentication is already enabled").into_response(),
+        Ok(_) => {},
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
+    let recovery_codes = match replace_recovery_codes(&state, &user.id).await {
+        Ok(c) => c,
+        Err(e) => return e.into_response(),
+    };
+
+    // Enrolled during login: the challenge is done, start the session
+    let session = match (&claims, &payload.mfa_token) {
+        (None, Some(token)) => {
+            let _ = state.cache.delete_mfa_challenge(&hash_token(token)).await;
+            match issue_tokens(&state, &user, None).await {
+                Ok(tokens) => Some(auth_response(&state, tokens, user)),
+                Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            }
+        },
+        _ => None,
+    };
+
+    (StatusCode::OK, Json(MfaConfirmResponse { recovery_codes, session })).into_response()
+}
+
+pub async fn disable_mfa(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    meta: RequestMeta,
+    Json(payload): Json<MfaDisableDto>,
+) -> impl IntoResponse {
+    if !claims.is_session() {
+        return (StatusCode::FORBIDDEN, "Not available to API tokens").into_response();
+    }
+
+    let user = match find_user(&state, &claims.sub).await {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+    };
+
+    if !user.totp_enabled {
+        return (StatusCode::BAD_REQUEST, "Two-factor authentication is not enabled").into_response();
+    }
+
+    if mfa_required_for(&state, &user.role).await {
+        return (StatusCode::FORBIDDEN, "Two-factor authentication is mandatory for your role").into_response();
+    }
+
+    if !verify(&payload.password, &user.password_hash).unwrap_or(false) || !check_code(&state, &user, &payload.code).await {
+        return (StatusCode::UNAUTHORIZED, "Invalid password or code").into_response();
+    }
+
+    match clear_mfa(&state, &user.id).await {
+        Ok(()) => {
+            audit::record(&state, &meta, AuditEvent::new("mfa.disable", SUCCESS).actor(&user.id).target("user", &user.id)).await;
+            StatusCode::NO_CONTENT.into_response()
+        },
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(payload): Json<MfaCodeDto>,
+) -> impl IntoResponse {
+    if !claims.is_session() {
+        return (StatusCode::FORBIDDEN, "Not available to API tokens").into_response();
+    }
+
+    let user = match find_user(&state, &claims.sub).await {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+    };
+
+    if !user.totp_enabled {
+        return (StatusCode::BAD_REQUEST, "Two-factor authentication is not enabled").into_response();
+    }
+
+    if !check_code(&state, &user, &payload.code).await {
+        return (StatusCode::UNAUTHORIZED, "Invalid code").into_response();
+    }
+
+    match replace_recovery_codes(&state, &user.id).await {
+        Ok(recovery_codes) => (StatusCode::OK, Json(MfaConfirmResponse { recovery_codes, session: None })).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn clear_mfa(state: &AppState, user_id: &str) -> Result<(), (StatusCode, &'static str)> {
+    query("UPDATE users SET totp_enabled = FALSE, totp_secret = NULL, totp_last_step = NULL WHERE id = ?")
+        .bind(user_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to disable two-factor authentication"))?;
+
+    query("DELETE FROM mfa_recovery_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete recovery codes"))?;
+
+    Ok(())
+}
+
+// Admin only: for users who lost their device and their recovery codes.
+// Their sessions end; if their role requires 2FA they re-enroll at next login.
+pub async fn reset_user_mfa(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    meta: RequestMeta,
+    Path(user_id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin(&claims) {
+        return e.into_response();
+    }
+
+    if find_user(&state, &user_id).await.is_none() {
+        return (StatusCode::NOT_FOUND, "User not found").into_response();
+    }
+
+    if let Err(e) = clear_mfa(&state, &user_id).await {
+        return e.into_response();
+    }
+
+    audit::record(&state, &meta, AuditEvent::new("mfa.reset", SUCCESS).actor(&claims.sub).target("user", &user_id)).await;
+
+    match revoke_user_sessions(&state, &user_id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn list_mfa_policies(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin(&claims) {
+        return e.into_response();
+    }
+
+    let policies: Vec<MfaPolicy> = query_as("SELECT CAST(role AS CHAR) AS role, required FROM mfa_policies ORDER BY role")
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+    (StatusCode::OK, Json(policies)).into_response()
+}
+
+pub async fn update_mfa_policy(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    meta: RequestMeta,
+    Path(role): Path<String>,
+    Json(payload): Json<UpdateMfaPolicyDto>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin(&claims) {
+        return e.into_response();
+    }
+
+    if !ROLES.contains(&role.as_str()) {
+        return (StatusCode::BAD_REQUEST, "Role must be 'admin', 'osis' or 'media_guru'").into_response();
+    }
+
+    let result = query("INSERT INTO mfa_policies (role, required) VALUES (?, ?) ON DUPLICATE KEY UPDATE required = VALUES(required)")
+        .bind(&role)
+        .bind(payload.required)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(_) => {
+            audit::record(&state, &meta, AuditEvent::new("mfa.policy_update", SUCCESS).actor(&claims.sub)
+                .details(serde_json::json!({ "role": role, "required": payload.required }))).await;
+            (StatusCode::OK, Json(MfaPolicy { role, required: payload.required })).into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
@@ -1,3 +1,17 @@
 pub mod user;
 pub mod folder;
 pub mod file;
+pub mod content;
+pub mod multipart;
+pub mod tus;
+pub mod trash;
+pub mod copy;
+pub mod permission;
+pub mod share;
+pub mod password;
+pub mod mfa;
+pub mod token;
+pub mod oidc;
+pub mod jwks;
+pub mod audit;
+pub mod storage;
@@ -0,0 +1,254 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+    response::IntoResponse,
+};
+use sqlx::query;
+use std::time::Duration;
+use crate::models::{
+    File, MultipartInitResponse, PresignPartsRequest, PresignedPart, UploadedPartDto,
+    CompleteMultipartRequest,
+};
+use crate::state::AppState;
+use crate::handlers::file::finalize_upload;
+use crate::middleware::auth::AuthUser;
+use crate::middleware::request_meta::RequestMeta;
+
+// Storage limits (as in S3): part numbers are 1..=10000
+const MAX_PART_NUMBER: i32 = 10_000;
+// Cap how many URLs a single request can presign
+const MAX_PRESIGN_BATCH: usize = 100;
+
+/// Loads a file row that the caller owns and that is still waiting for its bytes.
+async fn load_pending_file(state: &AppState, file_id: &str, user_id: &str) -> Result<File, (StatusCode, &'static str)> {
+    let file: Option<File> = sqlx::query_as("SELECT * FROM files WHERE id = ? AND deleted_at IS NULL")
+        .bind(file_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    let file = match file {
+        Some(f) => f,
+        None => return Err((StatusCode::NOT_FOUND, "File not found")),
+    };
+
+    if file.owner_id != user_id {
+        return Err((StatusCode::FORBIDDEN, "Only the uploader can manage this upload"));
+    }
+
+    if file.status != "pending" {
+        return Err((StatusCode::CONFLICT, "Upload is no longer pending"));
+    }
+
+    Ok(file)
+}
+
+fn require_upload_id(file: &File) -> Result<&str, (StatusCode, &'static str)> {
+    file.upload_id
+        .as_deref()
+        .ok_or((StatusCode::CONFLICT, "No multipart upload in progress"))
+}
+
+pub async fn initiate_multipart(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(file_id): Path<String>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let file = match load_pending_file(&state, &file_id, &user.sub).await {
+        Ok(f) => f,
+        Err(e) => return e.into_response(),
+    };
+
+    // Resuming after a refresh: hand back the existing upload instead of starting over
+    if let Some(upload_id) = file.upload_id {
+        return (StatusCode::OK, Json(MultipartInitResponse {
+            file_id: file.id,
+            upload_id,
+            storage_key: file.storage_key,
+        })).into_response();
+    }
+
+    let upload_id = match state.storage.create_multipart(
+        &file.storage_key,
+        file.mime_type.as_deref(),
+    ).await {
+        Ok(id) => id,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    // Guard against two tabs initiating at once: only the first one wins
+    let result = query("UPDATE files SET upload_id = ? WHERE id = ? AND upload_id IS NULL")
+        .bind(&upload_id)
+        .bind(&file.id)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 1 => (StatusCode::CREATED, Json(MultipartInitResponse {
+            file_id: file.id,
+            upload_id,
+            storage_key: file.storage_key,
+        })).into_response(),
+        Ok(_) => {
+            let _ = state.storage.abort_multipart(&file.storage_key, &upload_id).await;
+            (StatusCode::CONFLICT, "Multipart upload already initiated").into_response()
+        },
+        Err(e) => {
+            let _ = state.storage.abort_multipart(&file.storage_key, &upload_id).await;
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        },
+    }
+}
+
+pub async fn presign_parts(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(file_id): Path<String>,
+    Json(payload): Json<PresignPartsRequest>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let file = match load_pending_file(&state, &file_id, &user.sub).await {
+        Ok(f) => f,
+        Err(e) => return e.into_response(),
+    };
+    let upload_id = match require_upload_id(&file) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    if payload.part_numbers.is_empty() || payload.part_numbers.len() > MAX_PRESIGN_BATCH {
+        return (StatusCode::BAD_REQUEST, "Request between 1 and 100 parts per batch").into_response();
+    }
+    if payload.part_numbers.iter().any(|n| *n < 1 || *n > MAX_PART_NUMBER) {
+        return (StatusCode::BAD_REQUEST, "Part numbers must be between 1 and 10000").into_response();
+    }
+
+    let mut urls = Vec::with_capacity(payload.part_numbers.len());
+    for part_number in payload.part_numbers {
+        match state.storage.presign_upload_part(
+            &file.storage_key,
+            upload_id,
+            part_number,
+            Duration::from_secs(3600), // 1 hour
+        ).await {
+            Ok(url) => urls.push(PresignedPart { part_number, url }),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    }
+
+    (StatusCode::OK, Json(urls)).into_response()
+}
+
+pub async fn list_uploaded_parts(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(file_id): Path<String>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let file = match load_pending_file(&state, &file_id, &user.sub).await {
+        Ok(f) => f,
+        Err(e) => return e.into_response(),
+    };
+    let upload_id = match require_upload_id(&file) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    match state.storage.list_parts(&file.storage_key, upload_id).await {
+        Ok(parts) => {
+            let parts: Vec<UploadedPartDto> = parts
+                .into_iter()
+                .map(|p| UploadedPartDto { part_number: p.part_number, etag: p.etag, size: p.size })
+                .collect();
+            (StatusCode::OK, Json(parts)).into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn complete_multipart(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(file_id): Path<String>,
+    Json(payload): Json<CompleteMultipartRequest>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let file = match load_pending_file(&state, &file_id, &user.sub).await {
+        Ok(f) => f,
+        Err(e) => return e.into_response(),
+    };
+    let upload_id = match require_upload_id(&file) {
+        Ok(id) => id.to_string(),
+        Err(e) => return e.into_response(),
+    };
+
+    if payload.parts.is_empty() {
+        return (StatusCode::BAD_REQUEST, "At least one part is required").into_response();
+    }
+
+    // S3 requires ascending part numbers
+    let mut parts: Vec<(i32, String)> = payload.parts
+        .into_iter()
+        .map(|p| (p.part_number, p.etag))
+        .collect();
+    parts.sort_by_key(|(number, _)| *number);
+
+    if let Err(e) = state.storage.complete_multipart(
+        &file.storage_key,
+        &upload_id,
+        &parts,
+    ).await {
+        // Usually a bad/missing ETag; the upload stays open so the client can fix and retry
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    finalize_upload(&state, &meta, file).await
+}
+
+pub async fn abort_multipart(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(file_id): Path<String>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let file = match load_pending_file(&state, &file_id, &user.sub).await {
+        Ok(f) => f,
+        Err(e) => return e.into_response(),
+    };
+    let upload_id = match require_upload_id(&file) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    if let Err(e) = state.storage.abort_multipart(&file.storage_key, upload_id).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    let result = query("UPDATE files SET status = 'failed', upload_id = NULL WHERE id = ?")
+        .bind(&file.id)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
@@ -0,0 +1,367 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    Json,
+    response::{IntoResponse, Redirect},
+};
+use serde::Deserialize;
+use sqlx::{query, query_as, query_scalar};
+use uuid::Uuid;
+
+use crate::models::{OidcIdentity, User};
+use crate::state::AppState;
+use crate::middleware::auth::AuthUser;
+use crate::middleware::request_meta::RequestMeta;
+use crate::handlers::mfa::issue_challenge;
+use crate::handlers::user::{find_user, ROLES};
+use crate::services::audit::{self, AuditEvent, FAILURE, SUCCESS};
+use crate::services::oidc::{self, IdTokenClaims, PendingLogin};
+use crate::services::session::{issue_tokens, random_token};
+
+const LOGIN_STATE_TTL: u64 = 600; // Seconds the user has to finish at the provider
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+// Stores PKCE verifier + nonce under a fresh `state` and builds the provider URL, plus
+// the cookie binding the login to this browser
+async fn start_login(state: &AppState, link_user_id: Option<String>) -> Result<(String, String), (StatusCode, &'static str)> {
+    let issuer = state.config.oidc_issuer.as_deref()
+        .ok_or((StatusCode::NOT_FOUND, "OIDC login is not configured"))?;
+
+    let metadata = oidc::discover(issuer).await.map_err(|e| {
+        tracing::warn!("OIDC discovery failed: {:?}", e);
+        (StatusCode::BAD_GATEWAY, "Identity provider unavailable")
+    })?;
+
+    let oauth_state = random_token();
+    let pending = PendingLogin {
+        code_verifier: random_token(),
+        nonce: random_token(),
+        link_user_id,
+    };
+
+    let value = serde_json::to_string(&pending)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start login"))?;
+    state.cache.store_oidc_login(&oauth_state, &value, LOGIN_STATE_TTL)
+        .await
+        .map_err(|_| (StatusCode::SERVICE_UNAVAILABLE, "Login state store unavailable"))?;
+
+    let url = oidc::authorization_url(
+        &metadata,
+        &state.config,
+        &oauth_state,
+        &pending.nonce,
+        &oidc::pkce_challenge(&pending.code_verifier),
+    );
+    let binding = oidc::browser_binding(&oauth_state, pending.link_user_id.as_deref());
+
+    Ok((url, oidc::binding_cookie(&state.config, &binding, LOGIN_STATE_TTL)))
+}
+
+// Browser entry point: sends the user to the identity provider
+pub async fn oidc_login(State(state): State<AppState>) -> impl IntoResponse {
+    match start_login(&state, None).await {
+        Ok((url, cookie)) => ([(header::SET_COOKIE, cookie)], Redirect::to(&url)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+// Signed-in users link a provider account to their existing one. Returns the URL
+// rather than redirecting, the frontend navigates there itself. It has to call this
+// with credentials so the browser keeps the binding cookie.
+pub async fn oidc_link(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    if !user.is_session() {
+        return (StatusCode::FORBIDDEN, "Not available to API tokens").into_response();
+    }
+
+    match start_login(&state, Some(user.sub)).await {
+        Ok((url, cookie)) => (StatusCode::OK, [(header::SET_COOKIE, cookie)], Json(serde_json::json!({ "authorization_url": url }))).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+// The provider sends the browser back here. Results travel to the frontend in the URL
+// fragment so tokens never reach server logs or Referer headers.
+pub async fn oidc_callback(
+    State(state): State<AppState>,
+    meta: RequestMeta,
+    Query(params): Query<CallbackQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let app_url = state.config.app_url.trim_end_matches('/').to_string();
+    let binding = headers
+        .get(header::COOKIE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(oidc::presented_binding);
+    // Single use, like the state it binds
+    let clear = [(header::SET_COOKIE, oidc::binding_cookie(&state.config, "", 0))];
+
+    match complete_login(&state, &meta, params, binding).await {
+        Ok(fragment) => (clear, Redirect::to(&format!("{}/auth/callback#{}", app_url, fragment))).into_response(),
+        Err(message) => {
+            audit::record(&state, &meta, AuditEvent::new("auth.login", FAILURE)
+                .details(serde_json::json!({ "method": "oidc", "error": message }))).await;
+            (clear, Redirect::to(&format!("{}/login?error={}", app_url, urlencoding::encode(message)))).into_response()
+        },
+    }
+}
+
+async fn complete_login(state: &AppState, meta: &RequestMeta, params: CallbackQuery, binding: Option<&str>) -> Result<String, &'static str> {
+    if let Some(error) = params.error {
+        tracing::info!("OIDC provider returned error: {}", error);
+        return Err("Sign-in was cancelled or denied");
+    }
+
+    let (code, oauth_state) = match (params.code, params.state) {
+        (Some(code), Some(oauth_state)) => (code, oauth_state),
+        _ => return Err("Invalid sign-in response"),
+    };
+
+    let pending: PendingLogin = state.cache.take_oidc_login(&oauth_state)
+        .await
+        .unwrap_or(None)
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .ok_or("Sign-in session expired, please try again")?;
+
+    // Taken above either way, so a state can't be retried with another cookie
+    if binding != Some(oidc::browser_binding(&oauth_state, pending.link_user_id.as_deref()).as_str()) {
+        return Err("Sign-in was started in another browser, please try again");
+    }
+
+    let issuer = state.config.oidc_issuer.as_deref().ok_or("OIDC login is not configured")?;
+
+    let metadata = oidc::discover(issuer).await.map_err(|e| {
+        tracing::warn!("OIDC discovery failed: {:?}", e);
+        "Identity provider unavailable"
+    })?;
+
+    let id_token = oidc::exchange_code(&metadata, &state.config, &code, &pending.code_verifier)
+        .await
+        .map_err(|e| {
+            tracing::warn!("OIDC code exchange failed: {:?}", e);
+            "Sign-in failed"
+        })?;
+
+    let claims = oidc::validate_id_token(&metadata, &state.config, &id_token, &pending.nonce)
+        .await
+        .map_err(|e| {
+            tracing::warn!("OIDC ID token rejected: {:?}", e);
+            "Sign-in failed"
+        })?;
+
+    // Linking: attach the identity and send the user back, their session stays as is
+    if let Some(link_user_id) = pending.link_user_id {
+        link_identity(state, &metadata.issuer, &claims, &link_user_id).await?;
+        audit::record(state, meta, AuditEvent::new("oidc.link", SUCCESS).actor(&link_user_id).target("user", &link_user_id)
+            .details(serde_json::json!({ "issuer": metadata.issuer, "subject": claims.sub }))).await;
+        return Ok("linked=true".to_string());
+    }
+
+    let user = resolve_user(state, &metadata.issuer, &claims).await?;
+
+    if !user.is_active {
+        return Err("Account is deactivated");
+    }
+
+    let _ = query("UPDATE oidc_identities SET last_login_at = NOW(), email = ? WHERE issuer = ? AND subject = ?")
+        .bind(&claims.email)
+        .bind(&metadata.issuer)
+        .bind(&claims.sub)
+        .execute(&state.db)
+        .await;
+
+    // The provider stands in for the password, the local second factor still applies
+    match issue_challenge(state, &user).await {
+        Ok(Some(challenge)) => {
+            audit::record(state, meta, AuditEvent::new("auth.login", SUCCESS).actor(&user.id)
+                .details(serde_json::json!({ "method": "oidc", "mfa_pending": true }))).await;
+            return Ok(format!(
+                "mfa_token={}&enrollment_required={}&expires_in={}",
+                challenge.mfa_token, challenge.enrollment_required, challenge.expires_in,
+            ));
+        },
+        Ok(None) => {},
+        Err((_, message)) => return Err(message),
+    }
+
+    let tokens = issue_tokens(state, &user, None).await.map_err(|_| "Failed to create session")?;
+
+    audit::record(state, meta, AuditEvent::new("auth.login", SUCCESS).actor(&user.id)
+        .details(serde_json::json!({ "method": "oidc" }))).await;
+
+    Ok(format!(
+        "token={}&refresh_token={}&expires_in={}",
+        tokens.access_token, tokens.refresh_token, state.config.jwt_expiration,
+    ))
+}
+
+async fn find_identity_owner(state: &AppState, issuer: &str, subject: &str) -> Option<String> {
+    query_scalar("SELECT user_id FROM oidc_identities WHERE issuer = ? AND subject = ?")
+        .bind(issuer)
+        .bind(subject)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+}
+
+async fn insert_identity(state: &AppState, user_id: &str, issuer: &str, claims: &IdTokenClaims) -> Result<(), &'static str> {
+    query("INSERT INTO oidc_identities (id, user_id, issuer, subject, email) VALUES (?, ?, ?, ?, ?)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(user_id)
+        .bind(issuer)
+        .bind(&claims.sub)
+        .bind(&claims.email)
+        .execute(&state.db)
+        .await
+        .map(|_| ())
+        .map_err(|_| "Failed to link account")
+}
+
+async fn link_identity(state: &AppState, issuer: &str, claims: &IdTokenClaims, user_id: &str) -> Result<(), &'static str> {
+    match find_identity_owner(state, issuer, &claims.sub).await {
+        Some(owner) if owner == user_id => Ok(()),
+        Some(_) => Err("This account is already linked to another user"),
+        None => insert_identity(state, user_id, issuer, claims).await,
+    }
+}
+
+// Known identity -> its user. Otherwise an existing account with the same verified
+// email is linked, or a new account is provisioned with a role from the groups claim
+// (also for a verified email only, unless configured otherwise).
+async fn resolve_user(state: &AppState, issuer: &str, claims: &IdTokenClaims) -> Result<User, &'static str> {
+    let config = &state.config;
+
+    if let Some(user_id) = find_identity_owner(state, issuer, &claims.sub).await {
+        let mut user = find_user(state, &user_id).await.ok_or("Account no longer exists")?;
+
+        if config.oidc_sync_roles {
+            let role = mapped_role(state, claims);
+            if role != user.role {
+                query("UPDATE users SET role = ? WHERE id = ?")
+                    .bind(&role)
+                    .bind(&user.id)
+                    .execute(&state.db)
+                    .await
+                    .map_err(|_| "Failed to update account")?;
+                user.role = role;
+            }
+        }
+
+        return Ok(user);
+    }
+
+    let email = claims.email.as_deref()
+        .map(|e| e.trim().to_lowercase())
+        .ok_or("Identity provider did not share an email address")?;
+
+    if !oidc::domain_allowed(config, &email) {
+        return Err("Your email domain is not allowed to sign in");
+    }
+
+    let existing: Option<User> = query_as("SELECT * FROM users WHERE email = ?")
+        .bind(&email)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    if let Some(user) = existing {
+        // Taking over a password account needs proof the provider owns the address
+        if claims.email_verified != Some(true) {
+            return Err("Sign in with your password and link the account from your settings");
+        }
+        insert_identity(state, &user.id, issuer, claims).await?;
+        return Ok(user);
+    }
+
+    if !oidc::may_provision(config.oidc_require_verified_email, claims.email_verified) {
+        return Err("Your email address is not verified with the identity provider");
+    }
+
+    let user_id = Uuid::new_v4().to_string();
+    let name = claims.name.clone().unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_string());
+
+    // Empty hash: bcrypt never verifies against it, so password login stays closed
+    // until the user sets one through the reset flow
+    query("INSERT INTO users (id, name, email, password_hash, role) VALUES (?, ?, ?, '', ?)")
+        .bind(&user_id)
+        .bind(&name)
+        .bind(&email)
+        .bind(mapped_role(state, claims))
+        .execute(&state.db)
+        .await
+        .map_err(|_| "Failed to create account")?;
+
+    insert_identity(state, &user_id, issuer, claims).await?;
+
+    find_user(state, &user_id).await.ok_or("Failed to create account")
+}
+
+fn mapped_role(state: &AppState, claims: &IdTokenClaims) -> String {
+    let role = oidc::map_role(&state.config, &claims.groups(&state.config.oidc_groups_claim));
+    if ROLES.contains(&role.as_str()) {
+        role
+    } else {
+        tracing::warn!("OIDC role mapping produced unknown role '{}', using 'osis'", role);
+        "osis".to_string()
+    }
+}
+
+pub async fn list_identities(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let identities: Vec<OidcIdentity> = query_as("SELECT * FROM oidc_identities WHERE user_id = ? ORDER BY created_at")
+        .bind(&user.sub)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+    (StatusCode::OK, Json(identities)).into_response()
+}
+
+pub async fn unlink_identity(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(identity_id): Path<String>,
+) -> impl IntoResponse {
+    if !user.is_session() {
+        return (StatusCode::FORBIDDEN, "Not available to API tokens").into_response();
+    }
+
+    let account = match find_user(&state, &user.sub).await {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+    };
+
+    // Don't let a provisioned user remove their only way in
+    if account.password_hash.is_empty() {
+        let count: i64 = query_scalar("SELECT COUNT(*) FROM oidc_identities WHERE user_id = ?")
+            .bind(&account.id)
+            .fetch_one(&state.db)
+            .await
+            .unwrap_or(0);
+        if count <= 1 {
+            return (StatusCode::CONFLICT, "Set a password before removing your last linked account").into_response();
+        }
+    }
+
+    let result = query("DELETE FROM oidc_identities WHERE id = ? AND user_id = ?")
+        .bind(&identity_id)
+        .bind(&account.id)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (StatusCode::NOT_FOUND, "Linked account not found").into_response(),
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
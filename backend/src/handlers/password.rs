@@ -0,0 +1,199 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    Json,
+    response::IntoResponse,
+};
+use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::Utc;
+use sqlx::{query, query_as, query_scalar};
+use uuid::Uuid;
+
+use crate::models::{ChangePasswordDto, ForgotPasswordDto, ResetPasswordDto, User};
+use crate::state::AppState;
+use crate::middleware::auth::AuthUser;
+use crate::middleware::request_meta::RequestMeta;
+use crate::handlers::user::{auth_response, find_user, validate_password};
+use crate::services::audit::{self, AuditEvent, FAILURE, SUCCESS};
+use crate::services::mailer::Email;
+use crate::services::session::{hash_token, issue_tokens, random_token, revoke_user_sessions};
+
+// Changing the password signs out every other session; the caller gets a fresh token pair.
+pub async fn change_password(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Json(payload): Json<ChangePasswordDto>,
+) -> impl IntoResponse {
+    if !user.is_session() {
+        return (StatusCode::FORBIDDEN, "Not available to API tokens").into_response();
+    }
+
+    let account = match find_user(&state, &user.sub).await {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+    };
+
+    if account.ldap_uid.is_some() {
+        return (StatusCode::BAD_REQUEST, "Password is managed by the directory").into_response();
+    }
+
+    let allowed = state.cache.check_rate_limit(&account.id, "password_change", 5, 300).await.unwrap_or(true);
+    if !allowed {
+        return (StatusCode::TOO_MANY_REQUESTS, "Too many attempts").into_response();
+    }
+
+    if !verify(&payload.current_password, &account.password_hash).unwrap_or(false) {
+        audit::record(&state, &meta, AuditEvent::new("auth.password_change", FAILURE).actor(&account.id).target("user", &account.id)).await;
+        return (StatusCode::UNAUTHORIZED, "Current password is incorrect").into_response();
+    }
+
+    if let Err(e) = validate_password(&payload.new_password) {
+        return e.into_response();
+    }
+
+    if let Err(e) = set_password(&state, &account.id, &payload.new_password).await {
+        return e.into_response();
+    }
+
+    audit::record(&state, &meta, AuditEvent::new("auth.password_change", SUCCESS).actor(&account.id).target("user", &account.id)).await;
+
+    let account = match find_user(&state, &account.id).await {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+    };
+
+    match issue_tokens(&state, &account, None).await {
+        Ok(tokens) => (StatusCode::OK, Json(auth_response(&state, tokens, account))).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+// Always answers 202 so the endpoint can't be used to probe which emails have accounts
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ForgotPasswordDto>,
+) -> impl IntoResponse {
+    let email = payload.email.trim().to_lowercase();
+
+    let allowed = state.cache.check_rate_limit(&email, "password_reset", 3, 3600).await.unwrap_or(true);
+    if !allowed {
+        return (StatusCode::TOO_MANY_REQUESTS, "Too many reset requests").into_response();
+    }
+
+    // Directory accounts reset their password in the directory
+    let user: Option<User> = query_as("SELECT * FROM users WHERE email = ? AND is_active = TRUE AND ldap_uid IS NULL")
+        .bind(&email)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    if let Some(user) = user {
+        // Mail delivery runs in the background so response time doesn't reveal anything either
+        tokio::spawn(async move {
+            if let Err(e) = send_reset_email(&state, &user).await {
+                tracing::warn!("Failed to send password reset email to {}: {:?}", user.id, e);
+            }
+        });
+    }
+
+    StatusCode::ACCEPTED.into_response()
+}
+
+async fn send_reset_email(state: &AppState, user: &User) -> anyhow::Result<()> {
+    let now = Utc::now().naive_utc();
+
+    // Only the newest link works
+    query("UPDATE password_reset_tokens SET used_at = ? WHERE user_id = ? AND used_at IS NULL")
+        .bind(now)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await?;
+
+    let token = random_token();
+    query("INSERT INTO password_reset_tokens (id, user_id, token_hash, expires_at) VALUES (?, ?, ?, ?)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(&user.id)
+        .bind(hash_token(&token))
+        .bind(now + chrono::Duration::seconds(state.config.password_reset_ttl))
+        .execute(&state.db)
+        .await?;
+
+    let link = format!("{}/reset-password?token={}", state.config.app_url.trim_end_matches('/'), token);
+    let minutes = state.config.password_reset_ttl / 60;
+
+    state.mailer.send(&Email {
+        to: user.email.clone(),
+        subject: "Reset your Ferrum password".to_string(),
+        body: format!(
+            "Hi {},\n\nSomeone requested a password reset for your Ferrum account.\n\
+             Open this link within {} minutes to choose a new password:\n\n{}\n\n\
+             If you didn't ask for this, you can ignore this email.\n",
+            user.name, minutes, link,
+        ),
+    }).await
+}
+
+pub async fn reset_password(
+    State(state): State<AppState>,
+    meta: RequestMeta,
+    Json(payload): Json<ResetPasswordDto>,
+) -> impl IntoResponse {
+    if let Err(e) = validate_password(&payload.new_password) {
+        return e.into_response();
+    }
+
+    let now = Utc::now().naive_utc();
+
+    let user_id: Option<String> = query_scalar(
+        "SELECT user_id FROM password_reset_tokens WHERE token_hash = ? AND used_at IS NULL AND expires_at > ?",
+    )
+    .bind(hash_token(&payload.token))
+    .bind(now)
+    .fetch_optional(&state.db)
+    .await
+    .unwrap_or(None);
+
+    let user_id = match user_id {
+        Some(id) => id,
+        None => return (StatusCode::BAD_REQUEST, "Invalid or expired reset token").into_response(),
+    };
+
+    // Guarded so two concurrent resets can't both use the same token
+    let claimed = query("UPDATE password_reset_tokens SET used_at = ? WHERE token_hash = ? AND used_at IS NULL")
+        .bind(now)
+        .bind(hash_token(&payload.token))
+        .execute(&state.db)
+        .await;
+
+    match claimed {
+        Ok(r) if r.rows_affected() == 0 => return (StatusCode::BAD_REQUEST, "Invalid or expired reset token").into_response(),
+        Ok(_) => {},
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
+    match set_password(&state, &user_id, &payload.new_password).await {
+        Ok(()) => {
+            audit::record(&state, &meta, AuditEvent::new("auth.password_reset", SUCCESS).actor(&user_id).target("user", &user_id)).await;
+            StatusCode::NO_CONTENT.into_response()
+        },
+        Err(e) => e.into_response(),
+    }
+}
+
+// Stores the new hash and ends every existing session of the user
+async fn set_password(state: &AppState, user_id: &str, password: &str) -> Result<(), (StatusCode, &'static str)> {
+    let password_hash = hash(password, DEFAULT_COST)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password"))?;
+
+    query("UPDATE users SET password_hash = ? WHERE id = ?")
+        .bind(&password_hash)
+        .bind(user_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update password"))?;
+
+    revoke_user_sessions(state, user_id)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to revoke sessions"))
+}
@@ -0,0 +1,222 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+    response::IntoResponse,
+};
+use sqlx::{query, query_as, query_scalar};
+use uuid::Uuid;
+
+use crate::models::{Folder, FolderPermission, FolderPermissionEntry, GrantPermissionDto, UpdatePermissionDto};
+use crate::state::AppState;
+use crate::middleware::auth::AuthUser;
+use crate::middleware::request_meta::RequestMeta;
+use crate::handlers::folder::{find_folder, folder_role};
+use crate::services::audit::{self, AuditEvent, SUCCESS};
+use crate::services::auth::Claims;
+
+// 'none' is an explicit override that blocks access inherited from a parent folder
+const PERMISSIONS: [&str; 3] = ["viewer", "editor", "none"];
+
+/// Sharing is managed by an owner (of the folder or one above it) or an admin only
+/// (editors can't re-share).
+async fn load_managed_folder(state: &AppState, folder_id: &str, user: &Claims) -> Result<Folder, (StatusCode, &'static str)> {
+    let folder = find_folder(state, folder_id)
+        .await
+        .ok_or((StatusCode::NOT_FOUND, "Folder not found"))?;
+
+    let manages = folder.owner_id == user.sub
+        || user.role == "admin"
+        || folder_role(state, &folder.id, &user.sub).await.as_deref() == Some("owner");
+    if !manages {
+        return Err((StatusCode::FORBIDDEN, "Only the owner can manage sharing"));
+    }
+
+    Ok(folder)
+}
+
+pub async fn list_permissions(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(folder_id): Path<String>,
+) -> impl IntoResponse {
+    let folder = match load_managed_folder(&state, &folder_id, &user).await {
+        Ok(f) => f,
+        Err(e) => return e.into_response(),
+    };
+
+    let entries: Vec<FolderPermissionEntry> = query_as(
+        "SELECT p.user_id, u.name, u.email, p.permission, p.created_at
+         FROM folder_permissions p JOIN users u ON u.id = p.user_id
+         WHERE p.folder_id = ? ORDER BY u.name",
+    )
+    .bind(&folder.id)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    (StatusCode::OK, Json(entries)).into_response()
+}
+
+// Grants access, or changes the role if the user already has one
+pub async fn grant_permission(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(folder_id): Path<String>,
+    Json(payload): Json<GrantPermissionDto>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let folder = match load_managed_folder(&state, &folder_id, &user).await {
+        Ok(f) => f,
+        Err(e) => return e.into_response(),
+    };
+
+    if !PERMISSIONS.contains(&payload.permission.as_str()) {
+        return (StatusCode::BAD_REQUEST, "Permission must be 'viewer', 'editor' or 'none'").into_response();
+    }
+
+    let grantee: Option<String> = match (&payload.user_id, &payload.email) {
+        (Some(id), _) => query_scalar("SELECT id FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&state.db)
+            .await
+            .unwrap_or(None),
+        (None, Some(email)) => query_scalar("SELECT id FROM users WHERE email = ?")
+            .bind(email)
+            .fetch_optional(&state.db)
+            .await
+            .unwrap_or(None),
+        (None, None) => return (StatusCode::BAD_REQUEST, "Either user_id or email is required").into_response(),
+    };
+
+    let grantee = match grantee {
+        Some(id) => id,
+        None => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+    };
+
+    // Ownership up the tree can't be overridden, so a grant would only mislead
+    if grantee == folder.owner_id || folder_role(&state, &folder.id, &grantee).await.as_deref() == Some("owner") {
+        return (StatusCode::BAD_REQUEST, "The owner already has full access").into_response();
+    }
+
+    let result = query("INSERT INTO folder_permissions (id, folder_id, user_id, permission) VALUES (?, ?, ?, ?) ON DUPLICATE KEY UPDATE permission = VALUES(permission)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(&folder.id)
+        .bind(&grantee)
+        .bind(&payload.permission)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(_) => {
+            // The grant is inherited, so the grantee's cached roles below this folder may be
+            // stale. A new epoch also voids roles computed before the change but cached after it.
+            let _ = state.cache.bump_user_acl_epoch(&grantee).await;
+            audit::record(&state, &meta, AuditEvent::new("permission.grant", SUCCESS).actor(&user.sub).target("folder", &folder.id)
+                .details(serde_json::json!({ "user_id": grantee, "permission": payload.permission }))).await;
+
+            let perm: Option<FolderPermission> = query_as("SELECT * FROM folder_permissions WHERE folder_id = ? AND user_id = ?")
+                .bind(&folder.id)
+                .bind(&grantee)
+                .fetch_optional(&state.db)
+                .await
+                .unwrap_or(None);
+
+            match perm {
+                Some(perm) => (StatusCode::OK, Json(perm)).into_response(),
+                None => (StatusCode::INTERNAL_SERVER_ERROR, "Permission was not saved").into_response(),
+            }
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn update_permission(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path((folder_id, target_user_id)): Path<(String, String)>,
+    Json(payload): Json<UpdatePermissionDto>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let folder = match load_managed_folder(&state, &folder_id, &user).await {
+        Ok(f) => f,
+        Err(e) => return e.into_response(),
+    };
+
+    if !PERMISSIONS.contains(&payload.permission.as_str()) {
+        return (StatusCode::BAD_REQUEST, "Permission must be 'viewer', 'editor' or 'none'").into_response();
+    }
+
+    let result = query("UPDATE folder_permissions SET permission = ? WHERE folder_id = ? AND user_id = ?")
+        .bind(&payload.permission)
+        .bind(&folder.id)
+        .bind(&target_user_id)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => {
+            // MySQL reports 0 rows when the value didn't change, so double-check existence
+            let exists: Option<String> = query_scalar("SELECT id FROM folder_permissions WHERE folder_id = ? AND user_id = ?")
+                .bind(&folder.id)
+                .bind(&target_user_id)
+                .fetch_optional(&state.db)
+                .await
+                .unwrap_or(None);
+            if exists.is_none() {
+                return (StatusCode::NOT_FOUND, "Permission not found").into_response();
+            }
+            StatusCode::NO_CONTENT.into_response()
+        },
+        Ok(_) => {
+            let _ = state.cache.bump_user_acl_epoch(&target_user_id).await;
+            audit::record(&state, &meta, AuditEvent::new("permission.update", SUCCESS).actor(&user.sub).target("folder", &folder.id)
+                .details(serde_json::json!({ "user_id": target_user_id, "permission": payload.permission }))).await;
+            StatusCode::NO_CONTENT.into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn revoke_permission(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path((folder_id, target_user_id)): Path<(String, String)>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let folder = match load_managed_folder(&state, &folder_id, &user).await {
+        Ok(f) => f,
+        Err(e) => return e.into_response(),
+    };
+
+    let result = query("DELETE FROM folder_permissions WHERE folder_id = ? AND user_id = ?")
+        .bind(&folder.id)
+        .bind(&target_user_id)
+        .execute(&state.db)
+        .await;
+
+    // Void the cached roles regardless, so a revocation is never masked by the cache
+    let _ = state.cache.bump_user_acl_epoch(&target_user_id).await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (StatusCode::NOT_FOUND, "Permission not found").into_response(),
+        Ok(_) => {
+            audit::record(&state, &meta, AuditEvent::new("permission.revoke", SUCCESS).actor(&user.sub).target("folder", &folder.id)
+                .details(serde_json::json!({ "user_id": target_user_id }))).await;
+            StatusCode::NO_CONTENT.into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
@@ -0,0 +1,336 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+    response::{IntoResponse, Response},
+};
+use bcrypt::{hash, verify, DEFAULT_COST};
+use serde::Deserialize;
+use sqlx::{query, query_as};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::models::{CreateShareLinkDto, File, Folder, ShareLink};
+use crate::state::AppState;
+use crate::middleware::auth::AuthUser;
+use crate::middleware::request_meta::RequestMeta;
+use crate::handlers::file::can_edit_file;
+use crate::handlers::folder::{can_edit_folder, contains_folder, find_folder};
+use crate::services::audit::{self, AuditEvent, SUCCESS};
+use crate::services::download::{self, Disposition};
+use crate::services::session::random_token;
+
+pub async fn create_share_link(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Json(payload): Json<CreateShareLinkDto>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    // Only people who can edit an item may publish it
+    match (&payload.file_id, &payload.folder_id) {
+        (Some(file_id), None) => {
+            let file: Option<File> = query_as("SELECT * FROM files WHERE id = ? AND status = 'committed' AND deleted_at IS NULL")
+                .bind(file_id)
+                .fetch_optional(&state.db)
+                .await
+                .unwrap_or(None);
+            match file {
+                Some(f) if can_edit_file(&state, &f, &user).await => {},
+                Some(_) => return (StatusCode::FORBIDDEN, "No permission to share this file").into_response(),
+                None => return (StatusCode::NOT_FOUND, "File not found").into_response(),
+            }
+        },
+        (None, Some(folder_id)) => match find_folder(&state, folder_id).await {
+            Some(f) if can_edit_folder(&state, &f, &user).await => {},
+            Some(_) => return (StatusCode::FORBIDDEN, "No permission to share this folder").into_response(),
+            None => return (StatusCode::NOT_FOUND, "Folder not found").into_response(),
+        },
+        _ => return (StatusCode::BAD_REQUEST, "Provide exactly one of file_id or folder_id").into_response(),
+    }
+
+    let expires_at = match payload.expires_in {
+        Some(secs) if secs <= 0 => return (StatusCode::BAD_REQUEST, "expires_in must be positive").into_response(),
+        Some(secs) => Some(chrono::Utc::now().naive_utc() + chrono::Duration::seconds(secs)),
+        None => None,
+    };
+
+    if matches!(payload.max_downloads, Some(n) if n < 1) {
+        return (StatusCode::BAD_REQUEST, "max_downloads must be at least 1").into_response();
+    }
+
+    let password_hash = match payload.password.as_deref() {
+        Some(p) if !p.is_empty() => match hash(p, DEFAULT_COST) {
+            Ok(h) => Some(h),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        },
+        _ => None,
+    };
+
+    let token = random_token();
+
+    let link = ShareLink {
+        id: Uuid::new_v4().to_string(),
+        token,
+        file_id: payload.file_id,
+        folder_id: payload.folder_id,
+        created_by: user.sub,
+        expires_at,
+        max_downloads: payload.max_downloads,
+        download_count: 0,
+        password_hash,
+        created_at: Some(chrono::Utc::now().naive_utc()),
+    };
+
+    let result = query("INSERT INTO share_links (id, token, file_id, folder_id, created_by, expires_at, max_downloads, password_hash) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(&link.id)
+        .bind(&link.token)
+        .bind(&link.file_id)
+        .bind(&link.folder_id)
+        .bind(&link.created_by)
+        .bind(link.expires_at)
+        .bind(link.max_downloads)
+        .bind(&link.password_hash)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(_) => {
+            audit::record(&state, &meta, AuditEvent::new("share.create", SUCCESS).actor(&link.created_by).target("share", &link.id)
+                .details(serde_json::json!({
+                    "file_id": link.file_id,
+                    "folder_id": link.folder_id,
+                    "expires_at": link.expires_at,
+                    "max_downloads": link.max_downloads,
+                    "password_protected": link.password_hash.is_some(),
+                }))).await;
+            let url = format!("/s/{}", link.token);
+            (StatusCode::CREATED, Json(serde_json::json!({ "link": link, "url": url }))).into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn list_share_links(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    let links: Vec<ShareLink> = query_as("SELECT * FROM share_links WHERE created_by = ? ORDER BY created_at DESC")
+        .bind(&user.sub)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+    (StatusCode::OK, Json(links)).into_response()
+}
+
+pub async fn revoke_share_link(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(link_id): Path<String>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    let result = query("DELETE FROM share_links WHERE id = ? AND (created_by = ? OR ? = 'admin')")
+        .bind(&link_id)
+        .bind(&user.sub)
+        .bind(&user.role)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (StatusCode::NOT_FOUND, "Share link not found").into_response(),
+        Ok(_) => {
+            audit::record(&state, &meta, AuditEvent::new("share.revoke", SUCCESS).actor(&user.sub).target("share", &link_id)).await;
+            StatusCode::NO_CONTENT.into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShareQuery {
+    pub folder_id: Option<String>, // Browse into a subfolder of a shared folder
+    pub file_id: Option<String>,   // Download a file inside a shared folder
+    pub password: Option<String>,  // Alternative to the X-Share-Password header
+}
+
+/// Validates token, expiry, download limit and password.
+async fn resolve_link(
+    state: &AppState,
+    token: &str,
+    password: Option<&str>,
+) -> Result<ShareLink, (StatusCode, &'static str)> {
+    let link: Option<ShareLink> = query_as("SELECT * FROM share_links WHERE token = ?")
+        .bind(token)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    let link = link.ok_or((StatusCode::NOT_FOUND, "Share link not found"))?;
+
+    if matches!(link.expires_at, Some(t) if t <= chrono::Utc::now().naive_utc()) {
+        return Err((StatusCode::GONE, "Share link has expired"));
+    }
+
+    if matches!(link.max_downloads, Some(max) if link.download_count >= max) {
+        return Err((StatusCode::GONE, "Download limit reached"));
+    }
+
+    if let Some(password_hash) = &link.password_hash {
+        let password = password.ok_or((StatusCode::UNAUTHORIZED, "Password required"))?;
+
+        // Throttle guessing per link
+        let allowed = state.cache.check_rate_limit(&link.id, "share_password", 10, 60).await.unwrap_or(true);
+        if !allowed {
+            return Err((StatusCode::TOO_MANY_REQUESTS, "Too many password attempts"));
+        }
+
+        if !verify(password, password_hash).unwrap_or(false) {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid password"));
+        }
+    }
+
+    Ok(link)
+}
+
+/// Whether a folder is inside the shared subtree. Unlike the cycle check, an error
+/// must deny, or the link would open up every folder.
+async fn in_shared_tree(state: &AppState, shared_root: &str, folder_id: &str) -> bool {
+    match contains_folder(state, shared_root, folder_id).await {
+        Ok(inside) => inside,
+        Err(e) => {
+            tracing::warn!("Share link confinement check failed: {:?}", e);
+            false
+        },
+    }
+}
+
+/// Counts the download against the link's limit and returns a short-lived presigned URL.
+async fn share_download(state: &AppState, meta: &RequestMeta, link: &ShareLink, file: &File) -> Response {
+    // Guarded increment so concurrent downloads can't overshoot max_downloads
+    let counted = query("UPDATE share_links SET download_count = download_count + 1 WHERE id = ? AND (max_downloads IS NULL OR download_count < max_downloads)")
+        .bind(&link.id)
+        .execute(&state.db)
+        .await;
+
+    match counted {
+        Ok(r) if r.rows_affected() == 0 => return (StatusCode::GONE, "Download limit reached").into_response(),
+        Ok(_) => {},
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
+    let download_url = match download::download_url(
+        state,
+        file,
+        Disposition::Attachment,
+        Duration::from_secs(300), // 5 minutes
+    ).await {
+        Ok(url) => url,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    // Anonymous by design: the share link is what identifies the access
+    audit::record(state, meta, AuditEvent::new("file.download", SUCCESS).target("file", &file.id)
+        .details(serde_json::json!({ "name": file.name, "share_id": link.id }))).await;
+
+    (StatusCode::OK, Json(serde_json::json!({
+        "type": "file",
+        "name": file.name,
+        "size": file.size,
+        "mime_type": file.mime_type,
+        "url": download_url,
+    }))).into_response()
+}
+
+// Public, unauthenticated
+pub async fn open_share_link(
+    State(state): State<AppState>,
+    meta: RequestMeta,
+    Path(token): Path<String>,
+    Query(params): Query<ShareQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let password = headers
+        .get("X-Share-Password")
+        .and_then(|v| v.to_str().ok())
+        .or(params.password.as_deref());
+
+    let link = match resolve_link(&state, &token, password).await {
+        Ok(l) => l,
+        Err(e) => return e.into_response(),
+    };
+
+    // Single file link
+    if let Some(file_id) = &link.file_id {
+        let file: Option<File> = query_as("SELECT * FROM files WHERE id = ? AND status = 'committed' AND deleted_at IS NULL")
+            .bind(file_id)
+            .fetch_optional(&state.db)
+            .await
+            .unwrap_or(None);
+
+        return match file {
+            Some(f) => share_download(&state, &meta, &link, &f).await,
+            None => (StatusCode::NOT_FOUND, "File not found").into_response(),
+        };
+    }
+
+    // Folder link: browsing and downloads are confined to the shared subtree
+    let shared_root = match &link.folder_id {
+        Some(id) => id.clone(),
+        None => return (StatusCode::NOT_FOUND, "Share link not found").into_response(),
+    };
+
+    if let Some(file_id) = &params.file_id {
+        let file: Option<File> = query_as("SELECT * FROM files WHERE id = ? AND status = 'committed' AND deleted_at IS NULL")
+            .bind(file_id)
+            .fetch_optional(&state.db)
+            .await
+            .unwrap_or(None);
+
+        let inside = match file.as_ref().and_then(|f| f.folder_id.as_deref()) {
+            Some(folder_id) => in_shared_tree(&state, &shared_root, folder_id).await,
+            None => false,
+        };
+
+        return match file {
+            Some(f) if inside => share_download(&state, &meta, &link, &f).await,
+            _ => (StatusCode::NOT_FOUND, "File not found").into_response(),
+        };
+    }
+
+    let target = params.folder_id.unwrap_or_else(|| shared_root.clone());
+    if target != shared_root && !in_shared_tree(&state, &shared_root, &target).await {
+        return (StatusCode::NOT_FOUND, "Folder not found").into_response();
+    }
+
+    let folder = match find_folder(&state, &target).await {
+        Some(f) => f,
+        None => return (StatusCode::NOT_FOUND, "Folder not found").into_response(),
+    };
+
+    let subfolders: Vec<Folder> = query_as("SELECT * FROM folders WHERE parent_id = ? AND deleted_at IS NULL")
+        .bind(&folder.id)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+    let files: Vec<File> = query_as("SELECT * FROM files WHERE folder_id = ? AND status = 'committed' AND deleted_at IS NULL")
+        .bind(&folder.id)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+    (StatusCode::OK, Json(serde_json::json!({
+        "type": "folder",
+        "folder": folder,
+        "subfolders": subfolders,
+        "files": files,
+    }))).into_response()
+}
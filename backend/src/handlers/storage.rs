@@ -0,0 +1,124 @@
+//! Signed upload/download URLs of the local-disk storage backend. S3 serves its own
+//! presigned URLs, so with STORAGE_BACKEND=s3 these routes are all 404.
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, TryStreamExt};
+use serde::Deserialize;
+
+use crate::services::storage::{ByteStream, StorageBackend};
+use crate::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct SignedUrlQuery {
+    pub expires: i64,
+    pub signature: String,
+    // Signed along with the rest: the upload's type for PUT, the response's for GET
+    pub content_type: Option<String>,
+    pub content_disposition: Option<String>, // GET only
+}
+
+fn body_stream(body: Body) -> ByteStream {
+    body.into_data_stream().map_err(std::io::Error::other).boxed()
+}
+
+pub async fn download_object(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(params): Query<SignedUrlQuery>,
+) -> Response {
+    let local = match state.storage.as_local() {
+        Some(l) => l,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let content_type = params.content_type.as_deref();
+    let content_disposition = params.content_disposition.as_deref();
+    if !local.verify("GET", &format!("objects/{}", key), content_type, content_disposition, params.expires, &params.signature) {
+        return (StatusCode::FORBIDDEN, "Invalid or expired signature").into_response();
+    }
+
+    let object = match local.get(&key, None).await {
+        Ok(Some(o)) => o,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Object not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let content_type = params.content_type
+        .or(object.content_type)
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    let mut response = (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CONTENT_LENGTH, object.content_length.to_string()),
+            // Served from our own origin: never sniffed into HTML, never scripted
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            (header::CONTENT_SECURITY_POLICY, "sandbox".to_string()),
+        ],
+        Body::from_stream(object.stream),
+    ).into_response();
+
+    if let Some(cd) = params.content_disposition.and_then(|cd| HeaderValue::from_str(&cd).ok()) {
+        response.headers_mut().insert(header::CONTENT_DISPOSITION, cd);
+    }
+
+    response
+}
+
+pub async fn upload_object(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(params): Query<SignedUrlQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let local = match state.storage.as_local() {
+        Some(l) => l,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let content_type = params.content_type.as_deref();
+    if !local.verify("PUT", &format!("objects/{}", key), content_type, None, params.expires, &params.signature) {
+        return (StatusCode::FORBIDDEN, "Invalid or expired signature").into_response();
+    }
+
+    // Like S3, a signed Content-Type must be the one actually sent
+    let sent = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    if content_type.is_some() && sent != content_type {
+        return (StatusCode::FORBIDDEN, "Content-Type does not match the signed URL").into_response();
+    }
+
+    match local.write_object(&key, sent, body_stream(body)).await {
+        Ok(_) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn upload_part(
+    State(state): State<AppState>,
+    Path((upload_id, part_number)): Path<(String, i32)>,
+    Query(params): Query<SignedUrlQuery>,
+    body: Body,
+) -> Response {
+    let local = match state.storage.as_local() {
+        Some(l) => l,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let resource = format!("uploads/{}/{}", upload_id, part_number);
+    if !local.verify("PUT", &resource, None, None, params.expires, &params.signature) {
+        return (StatusCode::FORBIDDEN, "Invalid or expired signature").into_response();
+    }
+
+    // Clients read the part's ETag from the response, as with S3's UploadPart
+    match local.write_part(&upload_id, part_number, body_stream(body)).await {
+        Ok(etag) => (StatusCode::OK, [(header::ETAG, etag)]).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
@@ -0,0 +1,123 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+    response::IntoResponse,
+};
+use sqlx::{query, query_as};
+use uuid::Uuid;
+
+use crate::models::{ApiToken, CreateApiTokenDto, CreateApiTokenResponse};
+use crate::state::AppState;
+use crate::middleware::auth::{AuthUser, API_TOKEN_PREFIX};
+use crate::middleware::request_meta::RequestMeta;
+use crate::services::audit::{self, AuditEvent, SUCCESS};
+use crate::services::auth::SCOPES;
+use crate::services::session::{hash_token, random_token};
+
+// Tokens are managed from a signed-in session only, a token can't mint more tokens
+pub async fn create_api_token(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Json(payload): Json<CreateApiTokenDto>,
+) -> impl IntoResponse {
+    if !user.is_session() {
+        return (StatusCode::FORBIDDEN, "Not available to API tokens").into_response();
+    }
+
+    let name = payload.name.trim();
+    if name.is_empty() || name.len() > 255 {
+        return (StatusCode::BAD_REQUEST, "Name must be 1-255 characters").into_response();
+    }
+
+    if !SCOPES.contains(&payload.scope.as_str()) {
+        return (StatusCode::BAD_REQUEST, "Scope must be 'read', 'upload' or 'admin'").into_response();
+    }
+
+    let expires_at = match payload.expires_in_days {
+        Some(days) if !(1..=3650).contains(&days) => return (StatusCode::BAD_REQUEST, "expires_in_days must be between 1 and 3650").into_response(),
+        Some(days) => Some(chrono::Utc::now().naive_utc() + chrono::Duration::days(days)),
+        None => None,
+    };
+
+    let token = format!("{}{}", API_TOKEN_PREFIX, random_token());
+
+    let api_token = ApiToken {
+        id: Uuid::new_v4().to_string(),
+        user_id: user.sub,
+        name: name.to_string(),
+        token_prefix: token.chars().take(12).collect(),
+        scope: payload.scope,
+        expires_at,
+        last_used_at: None,
+        created_at: Some(chrono::Utc::now().naive_utc()),
+    };
+
+    let result = query("INSERT INTO api_tokens (id, user_id, name, token_prefix, token_hash, scope, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(&api_token.id)
+        .bind(&api_token.user_id)
+        .bind(&api_token.name)
+        .bind(&api_token.token_prefix)
+        .bind(hash_token(&token))
+        .bind(&api_token.scope)
+        .bind(api_token.expires_at)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(_) => {
+            audit::record(&state, &meta, AuditEvent::new("token.create", SUCCESS).actor(&api_token.user_id).target("token", &api_token.id)
+                .details(serde_json::json!({ "name": api_token.name, "scope": api_token.scope }))).await;
+            (StatusCode::CREATED, Json(CreateApiTokenResponse { token, api_token })).into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn list_api_tokens(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    if !user.is_session() {
+        return (StatusCode::FORBIDDEN, "Not available to API tokens").into_response();
+    }
+
+    let tokens: Vec<ApiToken> = query_as(
+        "SELECT id, user_id, name, token_prefix, CAST(scope AS CHAR) AS scope, expires_at, last_used_at, created_at
+         FROM api_tokens WHERE user_id = ? ORDER BY created_at DESC",
+    )
+    .bind(&user.sub)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    (StatusCode::OK, Json(tokens)).into_response()
+}
+
+pub async fn revoke_api_token(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(token_id): Path<String>,
+) -> impl IntoResponse {
+    if !user.is_session() {
+        return (StatusCode::FORBIDDEN, "Not available to API tokens").into_response();
+    }
+
+    let result = query("DELETE FROM api_tokens WHERE id = ? AND (user_id = ? OR ? = 'admin')")
+        .bind(&token_id)
+        .bind(&user.sub)
+        .bind(&user.role)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (StatusCode::NOT_FOUND, "Token not found").into_response(),
+        Ok(_) => {
+            audit::record(&state, &meta, AuditEvent::new("token.revoke", SUCCESS).actor(&user.sub).target("token", &token_id)).await;
+            StatusCode::NO_CONTENT.into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
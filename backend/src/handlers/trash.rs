@@ -0,0 +1,96 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+    response::IntoResponse,
+};
+use sqlx::query_as;
+
+use crate::models::{File, Folder, TrashResponse};
+use crate::state::AppState;
+use crate::middleware::auth::AuthUser;
+use crate::middleware::request_meta::RequestMeta;
+use crate::services::audit::{self, AuditEvent, SUCCESS};
+use crate::services::trash::{restore_file, restore_folder};
+
+pub async fn list_trash(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    // Only top-level trash entries; their contents come back with them
+    let folders: Vec<Folder> = query_as("SELECT * FROM folders WHERE deleted_at IS NOT NULL AND trashed_with IS NULL AND (owner_id = ? OR deleted_by = ?) ORDER BY deleted_at DESC")
+        .bind(&user.sub)
+        .bind(&user.sub)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+    let files: Vec<File> = query_as("SELECT * FROM files WHERE deleted_at IS NOT NULL AND trashed_with IS NULL AND (owner_id = ? OR deleted_by = ?) ORDER BY deleted_at DESC")
+        .bind(&user.sub)
+        .bind(&user.sub)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+    (StatusCode::OK, Json(TrashResponse { folders, files })).into_response()
+}
+
+pub async fn restore_item(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(item_id): Path<String>,
+) -> impl IntoResponse {
+    if !user.allows("upload") {
+        return (StatusCode::FORBIDDEN, "Token scope does not allow this action").into_response();
+    }
+
+    // The id may refer to either a folder or a file
+    let folder: Option<Folder> = query_as("SELECT * FROM folders WHERE id = ? AND deleted_at IS NOT NULL AND trashed_with IS NULL")
+        .bind(&item_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    if let Some(folder) = folder {
+        let allowed = folder.owner_id == user.sub
+            || folder.deleted_by.as_deref() == Some(user.sub.as_str())
+            || user.role == "admin";
+        if !allowed {
+            return (StatusCode::FORBIDDEN, "No permission to restore this folder").into_response();
+        }
+
+        return match restore_folder(&state, &folder).await {
+            Ok(parent_id) => {
+                audit::record(&state, &meta, AuditEvent::new("folder.restore", SUCCESS).actor(&user.sub).target("folder", &folder.id)).await;
+                (StatusCode::OK, Json(serde_json::json!({ "id": folder.id, "parent_id": parent_id }))).into_response()
+            },
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+    }
+
+    let file: Option<File> = query_as("SELECT * FROM files WHERE id = ? AND deleted_at IS NOT NULL AND trashed_with IS NULL")
+        .bind(&item_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    if let Some(file) = file {
+        let allowed = file.owner_id == user.sub
+            || file.deleted_by.as_deref() == Some(user.sub.as_str())
+            || user.role == "admin";
+        if !allowed {
+            return (StatusCode::FORBIDDEN, "No permission to restore this file").into_response();
+        }
+
+        return match restore_file(&state, &file).await {
+            Ok(folder_id) => {
+                audit::record(&state, &meta, AuditEvent::new("file.restore", SUCCESS).actor(&user.sub).target("file", &file.id)).await;
+                (StatusCode::OK, Json(serde_json::json!({ "id": file.id, "folder_id": folder_id }))).into_response()
+            },
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+    }
+
+    (StatusCode::NOT_FOUND, "Item not found in trash").into_response()
+}
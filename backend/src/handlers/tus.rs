@@ -0,0 +1,553 @@
+//! tus 1.0.0 resumable upload server (core, creation, termination, checksum).
+//! See https://tus.io/protocols/resumable-upload
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures_util::StreamExt;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::query;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::handlers::file::{finalize_upload, upload_destination};
+use crate::middleware::auth::AuthUser;
+use crate::middleware::request_meta::RequestMeta;
+use crate::models::{File, TusUpload};
+use crate::state::AppState;
+use crate::services::quota;
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,checksum";
+const TUS_CHECKSUM_ALGORITHMS: &str = "sha1,sha256,md5";
+
+// How long a PATCH may go without storing a part before another one can take over
+const LOCK_SECS: i64 = 120;
+
+// Non-standard status defined by the checksum extension
+const CHECKSUM_MISMATCH: u16 = 460;
+
+/// Builds a response carrying the `Tus-Resumable` header every tus reply needs.
+fn tus_response(status: StatusCode) -> axum::http::response::Builder {
+    Response::builder()
+        .status(status)
+        .header("Tus-Resumable", TUS_VERSION)
+}
+
+fn tus_error(status: StatusCode, message: &'static str) -> Response {
+    tus_response(status)
+        .body(Body::from(message))
+        .unwrap()
+        .into_response()
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Returns the 412 response to send if the client speaks a different tus version.
+fn version_mismatch(headers: &HeaderMap) -> Option<Response> {
+    if header_str(headers, "Tus-Resumable") == Some(TUS_VERSION) {
+        return None;
+    }
+
+    Some(tus_response(StatusCode::PRECONDITION_FAILED)
+        .header("Tus-Version", TUS_VERSION)
+        .body(Body::from("Unsupported tus version"))
+        .unwrap())
+}
+
+/// Parses `Upload-Metadata: key base64value,key2 base64value2`.
+fn parse_metadata(raw: &str) -> HashMap<String, String> {
+    raw.split(',')
+        .filter_map(|pair| {
+            let mut it = pair.trim().splitn(2, ' ');
+            let key = it.next()?.trim();
+            if key.is_empty() {
+                return None;
+            }
+            let value = match it.next() {
+                Some(encoded) => String::from_utf8(BASE64.decode(encoded.trim()).ok()?).ok()?,
+                None => String::new(),
+            };
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+enum Checksum {
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Md5(md5::Context),
+}
+
+impl Checksum {
+    fn new(algorithm: &str) -> Option<Self> {
+        match algorithm {
+            "sha1" => Some(Checksum::Sha1(Sha1::new())),
+            "sha256" => Some(Checksum::Sha256(Sha256::new())),
+            "md5" => Some(Checksum::Md5(md5::Context::new())),
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Checksum::Sha1(h) => h.update(data),
+            Checksum::Sha256(h) => h.update(data),
+            Checksum::Md5(h) => h.consume(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Checksum::Sha1(h) => h.finalize().to_vec(),
+            Checksum::Sha256(h) => h.finalize().to_vec(),
+            Checksum::Md5(h) => h.compute().0.to_vec(),
+        }
+    }
+}
+
+pub(crate) fn tail_key(storage_key: &str) -> String {
+    format!("{}.tus-tail", storage_key)
+}
+
+/// Loads the tus state and file row for an upload the caller owns.
+async fn load_upload(state: &AppState, file_id: &str, user_id: &str) -> Result<(TusUpload, File), Response> {
+    let upload: Option<TusUpload> = sqlx::query_as("SELECT * FROM tus_uploads WHERE file_id = ?")
+        .bind(file_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    let file: Option<File> = sqlx::query_as("SELECT * FROM files WHERE id = ? AND deleted_at IS NULL")
+        .bind(file_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    match (upload, file) {
+        (Some(u), Some(f)) if f.owner_id == user_id => Ok((u, f)),
+        (Some(_), Some(_)) => Err(tus_error(StatusCode::FORBIDDEN, "Access denied")),
+        _ => Err(tus_error(StatusCode::NOT_FOUND, "Upload not found")),
+    }
+}
+
+pub async fn options() -> impl IntoResponse {
+    tus_response(StatusCode::NO_CONTENT)
+        .header("Tus-Version", TUS_VERSION)
+        .header("Tus-Extension", TUS_EXTENSIONS)
+        .header("Tus-Checksum-Algorithm", TUS_CHECKSUM_ALGORITHMS)
+        .body(Body::empty())
+        .unwrap()
+}
+
+// Creation extension
+pub async fn create_upload(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(resp) = version_mismatch(&headers) {
+        return resp;
+    }
+
+    if !user.allows("upload") {
+        return tus_error(StatusCode::FORBIDDEN, "Token scope does not allow this action");
+    }
+
+    // Same rules as the presigned upload flow
+    let allowed_roles = ["admin", "osis", "media_guru"];
+    if !allowed_roles.contains(&user.role.as_str()) {
+        return tus_error(StatusCode::FORBIDDEN, "Insufficient role to upload files");
+    }
+
+    let allowed = state.cache.check_rate_limit(&user.sub, "upload", 10, 60).await.unwrap_or(true);
+    if !allowed {
+        return tus_error(StatusCode::TOO_MANY_REQUESTS, "Upload limit exceeded (10/min)");
+    }
+
+    // Deferred length isn't supported, so Upload-Length is mandatory
+    let upload_length: i64 = match header_str(&headers, "Upload-Length").and_then(|v| v.parse().ok()) {
+        Some(len) if len >= 0 => len,
+        _ => return tus_error(StatusCode::BAD_REQUEST, "Missing or invalid Upload-Length"),
+    };
+
+    let metadata = header_str(&headers, "Upload-Metadata").map(parse_metadata).unwrap_or_default();
+    let name = match metadata.get("filename").or_else(|| metadata.get("name")) {
+        Some(n) if !n.is_empty() => n.clone(),
+        _ => return tus_error(StatusCode::BAD_REQUEST, "Upload-Metadata must include filename"),
+    };
+    let mime_type = metadata.get("filetype").or_else(|| metadata.get("type"))
+        .filter(|t| !t.is_empty())
+        .cloned()
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let folder_id = metadata.get("folder_id").cloned().unwrap_or_else(|| "root".to_string());
+
+    let db_folder_id = match upload_destination(&state, &folder_id, &user).await {
+        Ok(d) => d,
+        Err((status, message)) => return tus_error(status, message),
+    };
+
+    match quota::has_room(&state, &user.sub, upload_length).await {
+        Ok(true) => {},
+        Ok(false) => return tus_error(StatusCode::PAYLOAD_TOO_LARGE, "Storage quota exceeded"),
+        Err(e) => {
+            tracing::error!("tus quota check failed: {:?}", e);
+            return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to start upload");
+        },
+    }
+
+    let file_id = Uuid::new_v4().to_string();
+    let storage_key = format!("{}/{}", folder_id, file_id);
+
+    let upload_id = match state.storage.create_multipart(&storage_key, Some(&mime_type)).await {
+        Ok(id) => id,
+        Err(e) => {
+            tracing::error!("tus create failed: {:?}", e);
+            return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to start upload");
+        }
+    };
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Database unavailable"),
+    };
+
+    let inserted = query("INSERT INTO files (id, name, folder_id, owner_id, storage_key, size, mime_type, is_public, status, upload_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'pending', ?)")
+        .bind(&file_id)
+        .bind(&name)
+        .bind(&db_folder_id)
+        .bind(&user.sub)
+        .bind(&storage_key)
+        .bind(upload_length)
+        .bind(&mime_type)
+        .bind(false)
+        .bind(&upload_id)
+        .execute(&mut *tx)
+        .await
+        .is_ok()
+        && query("INSERT INTO tus_uploads (file_id, upload_length) VALUES (?, ?)")
+            .bind(&file_id)
+            .bind(upload_length)
+            .execute(&mut *tx)
+            .await
+            .is_ok();
+
+    if !inserted || tx.commit().await.is_err() {
+        let _ = state.storage.abort_multipart(&storage_key, &upload_id).await;
+        return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create upload");
+    }
+
+    tus_response(StatusCode::CREATED)
+        .header(header::LOCATION, format!("/api/tus/{}", file_id))
+        .body(Body::empty())
+        .unwrap()
+}
+
+// Core: report progress so clients can resume
+pub async fn head_upload(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(file_id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(resp) = version_mismatch(&headers) {
+        return resp;
+    }
+
+    if !user.allows("upload") {
+        return tus_error(StatusCode::FORBIDDEN, "Token scope does not allow this action");
+    }
+
+    let (upload, _) = match load_upload(&state, &file_id, &user.sub).await {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+
+    tus_response(StatusCode::OK)
+        .header("Upload-Offset", upload.upload_offset)
+        .header("Upload-Length", upload.upload_length)
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Extends the PATCH's claim on the upload before it writes to storage. False once
+/// the claim lapsed and another request took the upload over.
+async fn renew_lock(state: &AppState, file_id: &str, lock_id: &str) -> bool {
+    let renewed = query("UPDATE tus_uploads SET locked_until = NOW() + INTERVAL ? SECOND WHERE file_id = ? AND lock_id = ?")
+        .bind(LOCK_SECS)
+        .bind(file_id)
+        .bind(lock_id)
+        .execute(&state.db)
+        .await;
+    matches!(renewed, Ok(r) if r.rows_affected() == 1)
+}
+
+// Core: append a chunk at Upload-Offset
+pub async fn patch_upload(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(file_id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    if let Some(resp) = version_mismatch(&headers) {
+        return resp;
+    }
+
+    if !user.allows("upload") {
+        return tus_error(StatusCode::FORBIDDEN, "Token scope does not allow this action");
+    }
+
+    if header_str(&headers, "Content-Type") != Some("application/offset+octet-stream") {
+        return tus_error(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Content-Type must be application/offset+octet-stream");
+    }
+
+    let (upload, file) = match load_upload(&state, &file_id, &user.sub).await {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+
+    let upload_id = match (&file.status[..], &file.upload_id) {
+        ("pending", Some(id)) => id.clone(),
+        _ => return tus_error(StatusCode::GONE, "Upload is no longer active"),
+    };
+
+    let client_offset: Option<i64> = header_str(&headers, "Upload-Offset").and_then(|v| v.parse().ok());
+    if client_offset != Some(upload.upload_offset) {
+        return tus_error(StatusCode::CONFLICT, "Upload-Offset does not match");
+    }
+
+    // Checksum extension: "Upload-Checksum: <algorithm> <base64 digest>"
+    let mut checksum = match header_str(&headers, "Upload-Checksum") {
+        Some(raw) => {
+            let mut it = raw.splitn(2, ' ');
+            let algorithm = it.next().unwrap_or("");
+            let expected = it.next().and_then(|d| BASE64.decode(d.trim()).ok());
+            match (Checksum::new(algorithm), expected) {
+                (Some(c), Some(expected)) => Some((c, expected)),
+                _ => return tus_error(StatusCode::BAD_REQUEST, "Unsupported or malformed Upload-Checksum"),
+            }
+        }
+        None => None,
+    };
+
+    // Claim the upload before writing anything: two PATCHes at the same offset would
+    // otherwise write the same part numbers and tail, and the loser's bytes could end
+    // up in the winner's object
+    let lock_id = Uuid::new_v4().to_string();
+    let claimed = query(
+        "UPDATE tus_uploads SET lock_id = ?, locked_until = NOW() + INTERVAL ? SECOND
+         WHERE file_id = ? AND upload_offset = ? AND (lock_id IS NULL OR locked_until < NOW())",
+    )
+    .bind(&lock_id)
+    .bind(LOCK_SECS)
+    .bind(&file.id)
+    .bind(upload.upload_offset)
+    .execute(&state.db)
+    .await;
+
+    match claimed {
+        Ok(r) if r.rows_affected() == 1 => {}
+        Ok(_) => return tus_error(StatusCode::CONFLICT, "Upload-Offset does not match or another request is writing"),
+        Err(_) => return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to record progress"),
+    }
+
+    let response = async {
+        let storage = &state.storage;
+        let part_size = state.config.tus_part_size;
+
+        // Resume from whatever didn't fill a whole part last time
+        let mut buffer = if upload.tail_size > 0 {
+            let tail = match storage.get(&tail_key(&file.storage_key), None).await {
+                Ok(Some(tail)) => tail.bytes().await,
+                Ok(None) => Err(anyhow::anyhow!("tus tail is missing")),
+                Err(e) => Err(e),
+            };
+            match tail {
+                Ok(b) => b,
+                Err(e) => {
+                    tracing::error!("tus tail read failed: {:?}", e);
+                    return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to resume upload");
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        let mut received: i64 = 0;
+        let mut next_part = upload.next_part_number;
+        let mut stream = body.into_data_stream();
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(_) if checksum.is_none() => break, // Connection dropped: keep what arrived
+                Err(_) => return tus_error(StatusCode::BAD_REQUEST, "Request body interrupted"),
+            };
+
+            received += chunk.len() as i64;
+            if upload.upload_offset + received > upload.upload_length {
+                return tus_error(StatusCode::PAYLOAD_TOO_LARGE, "Chunk exceeds Upload-Length");
+            }
+
+            if let Some((c, _)) = checksum.as_mut() {
+                c.update(&chunk);
+            }
+            buffer.extend_from_slice(&chunk);
+
+            // Parts are only recorded in the DB once the whole PATCH succeeds, so a failed
+            // request just leaves orphan parts that get overwritten or dropped at completion.
+            while buffer.len() >= part_size {
+                let rest = buffer.split_off(part_size);
+                let part = std::mem::replace(&mut buffer, rest);
+                if !renew_lock(&state, &file.id, &lock_id).await {
+                    return tus_error(StatusCode::CONFLICT, "Upload was taken over by another request");
+                }
+                if let Err(e) = storage.upload_part(&file.storage_key, &upload_id, next_part, part).await {
+                    tracing::error!("tus part upload failed: {:?}", e);
+                    return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to store chunk");
+                }
+                next_part += 1;
+            }
+        }
+
+        if let Some((c, expected)) = checksum {
+            if c.finalize() != expected {
+                return tus_error(StatusCode::from_u16(CHECKSUM_MISMATCH).unwrap(), "Checksum mismatch");
+            }
+        }
+
+        let new_offset = upload.upload_offset + received;
+        let finished = new_offset == upload.upload_length;
+        let tail_size = if finished { 0 } else { buffer.len() as i64 };
+
+        if !renew_lock(&state, &file.id, &lock_id).await {
+            return tus_error(StatusCode::CONFLICT, "Upload was taken over by another request");
+        }
+
+        if finished {
+            // The last part may be smaller than the S3 minimum; an empty file still needs one part.
+            if !buffer.is_empty() || next_part == 1 {
+                if let Err(e) = storage.upload_part(&file.storage_key, &upload_id, next_part, buffer).await {
+                    tracing::error!("tus final part upload failed: {:?}", e);
+                    return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to store chunk");
+                }
+                next_part += 1;
+            }
+        } else if !buffer.is_empty() {
+            if let Err(e) = storage.put(&tail_key(&file.storage_key), buffer, None).await {
+                tracing::error!("tus tail write failed: {:?}", e);
+                return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to store chunk");
+            }
+        }
+
+        let updated = query("UPDATE tus_uploads SET upload_offset = ?, next_part_number = ?, tail_size = ? WHERE file_id = ? AND lock_id = ?")
+            .bind(new_offset)
+            .bind(next_part)
+            .bind(tail_size)
+            .bind(&file.id)
+            .bind(&lock_id)
+            .execute(&state.db)
+            .await;
+
+        match updated {
+            Ok(r) if r.rows_affected() == 1 => {}
+            Ok(_) => return tus_error(StatusCode::CONFLICT, "Upload was taken over by another request"),
+            Err(_) => return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to record progress"),
+        }
+
+        if finished {
+            let _ = storage.delete(&tail_key(&file.storage_key)).await;
+
+            let parts = match storage.list_parts(&file.storage_key, &upload_id).await {
+                Ok(p) => p,
+                Err(_) => return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to finalize upload"),
+            };
+            let mut parts: Vec<(i32, String)> = parts
+                .into_iter()
+                .filter(|p| p.part_number < next_part)
+                .map(|p| (p.part_number, p.etag))
+                .collect();
+            parts.sort_by_key(|(number, _)| *number);
+
+            if storage.complete_multipart(&file.storage_key, &upload_id, &parts).await.is_err() {
+                return tus_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to finalize upload");
+            }
+
+            let finalized = finalize_upload(&state, &meta, file).await;
+            if !finalized.status().is_success() {
+                return finalized;
+            }
+
+            let _ = query("DELETE FROM tus_uploads WHERE file_id = ?")
+                .bind(&file_id)
+                .execute(&state.db)
+                .await;
+        }
+
+        tus_response(StatusCode::NO_CONTENT)
+            .header("Upload-Offset", new_offset)
+            .body(Body::empty())
+            .unwrap()
+    }
+    .await;
+
+    // Hand the upload back, whatever happened (a finished upload has no row left)
+    let _ = query("UPDATE tus_uploads SET lock_id = NULL, locked_until = NULL WHERE file_id = ? AND lock_id = ?")
+        .bind(&file_id)
+        .bind(&lock_id)
+        .execute(&state.db)
+        .await;
+
+    response
+}
+
+// Termination extension
+pub async fn terminate_upload(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(file_id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(resp) = version_mismatch(&headers) {
+        return resp;
+    }
+
+    if !user.allows("upload") {
+        return tus_error(StatusCode::FORBIDDEN, "Token scope does not allow this action");
+    }
+
+    let (upload, file) = match load_upload(&state, &file_id, &user.sub).await {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+
+    if let Some(upload_id) = &file.upload_id {
+        let _ = state.storage.abort_multipart(&file.storage_key, upload_id).await;
+    }
+    if upload.tail_size > 0 {
+        let _ = state.storage.delete(&tail_key(&file.storage_key)).await;
+    }
+
+    let _ = query("UPDATE files SET status = 'failed', upload_id = NULL WHERE id = ? AND status = 'pending'")
+        .bind(&file.id)
+        .execute(&state.db)
+        .await;
+    let _ = query("DELETE FROM tus_uploads WHERE file_id = ?")
+        .bind(&file.id)
+        .execute(&state.db)
+        .await;
+
+    tus_response(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap()
+}
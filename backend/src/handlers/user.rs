@@ -1,24 +1,89 @@
 use axum::{
-    extract::State,
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
     Json,
     response::IntoResponse,
 };
-use bcrypt::{hash, verify, DEFAULT_COST};
-use sqlx::{query, query_as, query_scalar};
+use bcrypt::{hash, DEFAULT_COST};
+use serde::Deserialize;
+use sqlx::{query, query_as, query_scalar, MySql, QueryBuilder};
 use uuid::Uuid;
 
-use crate::models::{CreateUserDto, LoginDto, User, AuthResponse};
+use crate::models::{CreateUserDto, LoginDto, LogoutDto, RefreshTokenDto, UpdateUserDto, User, UserListResponse, AuthResponse};
 use crate::state::AppState;
-use crate::services::auth::create_jwt;
+use crate::middleware::auth::AuthUser;
+use crate::middleware::request_meta::RequestMeta;
+use crate::handlers::mfa::login_challenge;
+use crate::services::audit::{self, AuditEvent, DENIED, FAILURE, SUCCESS};
+use crate::services::auth::Claims;
+use crate::services::session::{
+    bump_token_version, find_refresh_token, issue_tokens, mark_used, revoke_access_token, revoke_family, revoke_user_sessions, IssuedTokens,
+};
+use crate::services::users::{owned_item_count, purge_owned_content, transfer_ownership};
+
+// Must match the users.role ENUM
+pub(crate) const ROLES: [&str; 3] = ["admin", "osis", "media_guru"];
+
+pub(crate) fn require_admin(user: &Claims) -> Result<(), (StatusCode, &'static str)> {
+    if user.role != "admin" || !user.allows("admin") {
+        return Err((StatusCode::FORBIDDEN, "Admin only"));
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_password(password: &str) -> Result<(), (StatusCode, &'static str)> {
+    if password.len() < 8 {
+        return Err((StatusCode::BAD_REQUEST, "Password must be at least 8 characters"));
+    }
+    Ok(())
+}
+
+pub(crate) async fn find_user(state: &AppState, user_id: &str) -> Option<User> {
+    query_as("SELECT * FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+}
+
+// True if removing admin rights from `user_id` would leave no active admin
+async fn is_last_admin(state: &AppState, user_id: &str) -> bool {
+    let others: i64 = query_scalar("SELECT COUNT(*) FROM users WHERE role = 'admin' AND is_active = TRUE AND id <> ?")
+        .bind(user_id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(0);
+    others == 0
+}
 
-pub async fn register(
+// Admin only: accounts are provisioned, there is no self-registration
+pub async fn create_user(
     State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
     Json(payload): Json<CreateUserDto>,
-) ->  impl IntoResponse {
+) -> impl IntoResponse {
+    if let Err(e) = require_admin(&user) {
+        return e.into_response();
+    }
+
+    let name = payload.name.trim();
+    let email = payload.email.trim().to_lowercase();
+    if name.is_empty() || !email.contains('@') {
+        return (StatusCode::BAD_REQUEST, "Name and a valid email are required").into_response();
+    }
+    if let Err(e) = validate_password(&payload.password) {
+        return e.into_response();
+    }
+
+    let role = payload.role.unwrap_or_else(|| "osis".to_string());
+    if !ROLES.contains(&role.as_str()) {
+        return (StatusCode::BAD_REQUEST, "Role must be 'admin', 'osis' or 'media_guru'").into_response();
+    }
+
     // 1. Check if user exists
     let exists: Option<String> = query_scalar("SELECT id FROM users WHERE email = ?")
-        .bind(&payload.email)
+        .bind(&email)
         .fetch_optional(&state.db)
         .await
         .unwrap_or(None);
@@ -28,50 +93,524 @@ pub async fn register(
     }
 
     // 2. Hash password
-    let password_hash = hash(payload.password, DEFAULT_COST).expect("Failed to hash password");
+    let password_hash = match hash(&payload.password, DEFAULT_COST) {
+        Ok(h) => h,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
 
     // 3. Create user
     let user_id = Uuid::new_v4().to_string();
-    let role = payload.role.unwrap_or_else(|| "student".to_string());
 
     let result = query("INSERT INTO users (id, name, email, password_hash, role) VALUES (?, ?, ?, ?, ?)")
         .bind(&user_id)
-        .bind(&payload.name)
-        .bind(&payload.email)
+        .bind(name)
+        .bind(&email)
         .bind(&password_hash)
         .bind(&role)
         .execute(&state.db)
         .await;
 
+    if let Err(e) = result {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    audit::record(&state, &meta, AuditEvent::new("user.create", SUCCESS).actor(&user.sub).target("user", &user_id)
+        .details(serde_json::json!({ "email": email, "role": role }))).await;
+
+    match find_user(&state, &user_id).await {
+        Some(created) => (StatusCode::CREATED, Json(created)).into_response(),
+        None => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load created user").into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserListQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    pub q: Option<String>, // Matches name or email
+    pub role: Option<String>,
+    pub active: Option<bool>,
+}
+
+fn push_user_filters(builder: &mut QueryBuilder<'_, MySql>, params: &UserListQuery) {
+    builder.push(" WHERE 1 = 1");
+    if let Some(q) = params.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        let pattern = format!("%{}%", q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        builder.push(" AND (name LIKE ").push_bind(pattern.clone())
+            .push(" OR email LIKE ").push_bind(pattern)
+            .push(")");
+    }
+    if let Some(role) = &params.role {
+        builder.push(" AND role = ").push_bind(role.clone());
+    }
+    if let Some(active) = params.active {
+        builder.push(" AND is_active = ").push_bind(active);
+    }
+}
+
+pub async fn list_users(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Query(params): Query<UserListQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin(&user) {
+        return e.into_response();
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let per_page = params.per_page.unwrap_or(20).clamp(1, 100);
+
+    let mut count: QueryBuilder<MySql> = QueryBuilder::new("SELECT COUNT(*) FROM users");
+    push_user_filters(&mut count, &params);
+    let total: i64 = match count.build_query_scalar().fetch_one(&state.db).await {
+        Ok(t) => t,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let mut select: QueryBuilder<MySql> = QueryBuilder::new("SELECT * FROM users");
+    push_user_filters(&mut select, &params);
+    select.push(" ORDER BY name, id LIMIT ").push_bind(per_page)
+        .push(" OFFSET ").push_bind((page - 1) * per_page);
+
+    let users: Vec<User> = match select.build_query_as().fetch_all(&state.db).await {
+        Ok(u) => u,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    (StatusCode::OK, Json(UserListResponse { users, total, page, per_page })).into_response()
+}
+
+pub async fn get_user(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(user_id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin(&user) {
+        return e.into_response();
+    }
+
+    match find_user(&state, &user_id).await {
+        Some(u) => (StatusCode::OK, Json(u)).into_response(),
+        None => (StatusCode::NOT_FOUND, "User not found").into_response(),
+    }
+}
+
+// Rename, change email/role/password, link a directory login, or (de)activate
+pub async fn update_user(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(user_id): Path<String>,
+    Json(payload): Json<UpdateUserDto>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin(&user) {
+        return e.into_response();
+    }
+
+    let target = match find_user(&state, &user_id).await {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+    };
+
+    let name = match payload.name.as_deref().map(str::trim) {
+        Some("") => return (StatusCode::BAD_REQUEST, "Name cannot be empty").into_response(),
+        Some(n) => n.to_string(),
+        None => target.name.clone(),
+    };
+
+    let email = match payload.email.as_deref().map(|e| e.trim().to_lowercase()) {
+        Some(e) if !e.contains('@') => return (StatusCode::BAD_REQUEST, "Invalid email").into_response(),
+        Some(e) => e,
+        None => target.email.clone(),
+    };
+
+    if email != target.email {
+        let taken: Option<String> = query_scalar("SELECT id FROM users WHERE email = ? AND id <> ?")
+            .bind(&email)
+            .bind(&target.id)
+            .fetch_optional(&state.db)
+            .await
+            .unwrap_or(None);
+        if taken.is_some() {
+            return (StatusCode::CONFLICT, "Email already in use").into_response();
+        }
+    }
+
+    let role = payload.role.unwrap_or_else(|| target.role.clone());
+    if !ROLES.contains(&role.as_str()) {
+        return (StatusCode::BAD_REQUEST, "Role must be 'admin', 'osis' or 'media_guru'").into_response();
+    }
+
+    let is_active = payload.is_active.unwrap_or(target.is_active);
+
+    // Linking is the only way an existing account becomes LDAP-managed; from then on
+    // its local password is no longer accepted
+    let ldap_uid = match payload.ldap_uid.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(uid) => Some(uid.to_string()),
+        None => target.ldap_uid.clone(),
+    };
+
+    if let Some(uid) = ldap_uid.as_deref().filter(|uid| target.ldap_uid.as_deref() != Some(*uid)) {
+        let taken: Option<String> = query_scalar("SELECT id FROM users WHERE ldap_uid = ? AND id <> ?")
+            .bind(uid)
+            .bind(&target.id)
+            .fetch_optional(&state.db)
+            .await
+            .unwrap_or(None);
+        if taken.is_some() {
+            return (StatusCode::CONFLICT, "Directory login already linked to another account").into_response();
+        }
+    }
+
+    // Never lock everyone out of the admin API
+    let loses_admin = target.role == "admin" && target.is_active && (role != "admin" || !is_active);
+    if loses_admin && is_last_admin(&state, &target.id).await {
+        return (StatusCode::CONFLICT, "Cannot demote or deactivate the last active admin").into_response();
+    }
+
+    let password_hash = match payload.password.as_deref() {
+        Some(p) => {
+            if let Err(e) = validate_password(p) {
+                return e.into_response();
+            }
+            match hash(p, DEFAULT_COST) {
+                Ok(h) => h,
+                Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            }
+        },
+        None => target.password_hash.clone(),
+    };
+
+    let result = query("UPDATE users SET name = ?, email = ?, role = ?, is_active = ?, password_hash = ?, ldap_uid = ? WHERE id = ?")
+        .bind(&name)
+        .bind(&email)
+        .bind(&role)
+        .bind(is_active)
+        .bind(&password_hash)
+        .bind(&ldap_uid)
+        .bind(&target.id)
+        .execute(&state.db)
+        .await;
+
+    if let Err(e) = result {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    audit::record(&state, &meta, AuditEvent::new("user.update", SUCCESS).actor(&user.sub).target("user", &target.id)
+        .details(serde_json::json!({
+            "email": email,
+            "role": role,
+            "previous_role": target.role,
+            "is_active": is_active,
+            "password_changed": payload.password.is_some(),
+            "ldap_uid": ldap_uid,
+            "previous_ldap_uid": target.ldap_uid,
+        }))).await;
+
+    // Deactivation or a password reset ends every existing session
+    if !is_active || payload.password.is_some() {
+        if let Err(e) = revoke_user_sessions(&state, &target.id).await {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    }
+
+    match find_user(&state, &target.id).await {
+        Some(u) => (StatusCode::OK, Json(u)).into_response(),
+        None => (StatusCode::NOT_FOUND, "User not found").into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteUserQuery {
+    pub transfer_to: Option<String>, // Hand the user's folders and files to this user
+    pub purge: Option<bool>,         // Or permanently delete them
+}
+
+pub async fn delete_user(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(user_id): Path<String>,
+    Query(params): Query<DeleteUserQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin(&user) {
+        return e.into_response();
+    }
+
+    if user_id == user.sub {
+        return (StatusCode::BAD_REQUEST, "Cannot delete your own account").into_response();
+    }
+
+    let target = match find_user(&state, &user_id).await {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+    };
+
+    if target.role == "admin" && target.is_active && is_last_admin(&state, &target.id).await {
+        return (StatusCode::CONFLICT, "Cannot delete the last active admin").into_response();
+    }
+
+    match (&params.transfer_to, params.purge.unwrap_or(false)) {
+        (Some(_), true) => return (StatusCode::BAD_REQUEST, "Use either transfer_to or purge, not both").into_response(),
+        (Some(new_owner), false) => {
+            if new_owner == &target.id {
+                return (StatusCode::BAD_REQUEST, "Cannot transfer to the user being deleted").into_response();
+            }
+            match find_user(&state, new_owner).await {
+                Some(u) if u.is_active => {},
+                Some(_) => return (StatusCode::BAD_REQUEST, "Cannot transfer to a deactivated user").into_response(),
+                None => return (StatusCode::NOT_FOUND, "Transfer target not found").into_response(),
+            }
+            if let Err(e) = transfer_ownership(&state, &target.id, new_owner).await {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+            }
+        },
+        (None, true) => match purge_owned_content(&state, &target.id).await {
+            Ok(true) => {},
+            Ok(false) => return (StatusCode::CONFLICT, "User's folders contain other users' content; use transfer_to instead").into_response(),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        },
+        (None, false) => match owned_item_count(&state, &target.id).await {
+            Ok(0) => {},
+            Ok(_) => return (StatusCode::CONFLICT, "User still owns folders or files; pass transfer_to or purge=true").into_response(),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        },
+    }
+
+    let result = query("DELETE FROM users WHERE id = ?")
+        .bind(&target.id)
+        .execute(&state.db)
+        .await;
+
     match result {
-        Ok(_) => (StatusCode::CREATED, "User created successfully").into_response(),
+        Ok(_) => {
+            // Refresh tokens went with the row, and access tokens of a missing user are rejected
+            audit::record(&state, &meta, AuditEvent::new("user.delete", SUCCESS).actor(&user.sub).target("user", &target.id)
+                .details(serde_json::json!({
+                    "email": target.email,
+                    "transfer_to": params.transfer_to,
+                    "purge": params.purge.unwrap_or(false),
+                }))).await;
+            StatusCode::NO_CONTENT.into_response()
+        },
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
 pub async fn login(
     State(state): State<AppState>,
+    meta: RequestMeta,
     Json(payload): Json<LoginDto>,
 ) -> impl IntoResponse {
-    // 1. Find user
-    let user: Option<User> = query_as("SELECT * FROM users WHERE email = ?")
-        .bind(&payload.email)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
+    let account_key = format!("acct:{}", payload.email.trim().to_lowercase());
+    let ip_key = format!("ip:{}", meta.ip);
+
+    // 0. Throttling: checked before the password so a locked account can't be probed.
+    // Redis being down must not block logins.
+    if let Ok(Some(wait)) = state.cache.login_retry_after(&[&account_key, &ip_key]).await {
+        audit::record(&state, &meta, AuditEvent::new("auth.login", DENIED)
+            .details(serde_json::json!({ "login": payload.email, "reason": "throttled" }))).await;
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, wait.to_string())],
+            "Too many failed login attempts, try again later",
+        ).into_response();
+    }
 
-    match user {
-        Some(user) => {
-            // 2. Verify password
-            if verify(payload.password, &user.password_hash).unwrap_or(false) {
-                // 3. Generate Token
-                let token = create_jwt(&user.id, &user.role, &state.config).unwrap();
-                
-                (StatusCode::OK, Json(AuthResponse { token, user })).into_response()
-            } else {
-                (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response()
+    // 1. Check credentials against each configured backend in turn
+    let mut user = None;
+    let mut backend_failed = false;
+    for backend in &state.authenticators {
+        match backend.authenticate(&state.db, &payload.email, &payload.password).await {
+            Ok(Some(u)) => {
+                user = Some(u);
+                break;
+            },
+            Ok(None) => {},
+            Err(e) => {
+                backend_failed = true;
+                tracing::warn!("{} authentication failed: {:?}", backend.name(), e);
+            },
+        }
+    }
+
+    let user = match user {
+        Some(u) => u,
+        None => {
+            // A directory outage isn't the user's fault, don't count it against them
+            if !backend_failed {
+                record_failed_login(&state, &account_key, &ip_key).await;
             }
+            audit::record(&state, &meta, AuditEvent::new("auth.login", FAILURE)
+                .details(serde_json::json!({ "login": payload.email }))).await;
+            return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
+        },
+    };
+
+    let _ = state.cache.clear_login_failures(&account_key).await;
+
+    if !user.is_active {
+        audit::record(&state, &meta, AuditEvent::new("auth.login", DENIED).actor(&user.id)
+            .details(serde_json::json!({ "reason": "deactivated" }))).await;
+        return (StatusCode::FORBIDDEN, "Account is deactivated").into_response();
+    }
+
+    // 2. Second factor: hand out a challenge instead of tokens
+    if let Some(challenge) = login_challenge(&state, &user).await {
+        audit::record(&state, &meta, AuditEvent::new("auth.login", SUCCESS).actor(&user.id)
+            .details(serde_json::json!({ "method": "password", "mfa_pending": true }))).await;
+        return challenge;
+    }
+
+    // 3. Generate tokens (new refresh token family)
+    match issue_tokens(&state, &user, None).await {
+        Ok(tokens) => {
+            audit::record(&state, &meta, AuditEvent::new("auth.login", SUCCESS).actor(&user.id)
+                .details(serde_json::json!({ "method": "password" }))).await;
+            (StatusCode::OK, Json(auth_response(&state, tokens, user))).into_response()
+        },
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn record_failed_login(state: &AppState, account_key: &str, ip_key: &str) {
+    let config = &state.config;
+
+    match state.cache.record_login_failure(account_key, config.login_backoff_after, config.login_lockout_after, config.login_lockout_seconds).await {
+        Ok(true) => tracing::warn!("Login locked for {} after repeated failures", account_key),
+        Ok(false) => {},
+        Err(e) => tracing::warn!("Failed to record login failure: {:?}", e),
+    }
+
+    match state.cache.record_login_failure(ip_key, config.login_ip_backoff_after, config.login_ip_lockout_after, config.login_lockout_seconds).await {
+        Ok(true) => tracing::warn!("Login locked for {} after repeated failures", ip_key),
+        Ok(false) => {},
+        Err(e) => tracing::warn!("Failed to record login failure: {:?}", e),
+    }
+}
+
+// Lifts a login lockout early. Both names an account can log in with are cleared.
+pub async fn unlock_user(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    meta: RequestMeta,
+    Path(user_id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(e) = require_admin(&user) {
+        return e.into_response();
+    }
+
+    let target = match find_user(&state, &user_id).await {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+    };
+
+    let mut logins = vec![target.email.to_lowercase()];
+    if let Some(uid) = &target.ldap_uid {
+        logins.push(uid.to_lowercase());
+    }
+
+    for login in logins {
+        if let Err(e) = state.cache.clear_login_failures(&format!("acct:{}", login)).await {
+            return (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response();
         }
-        None => (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response(),
     }
+
+    audit::record(&state, &meta, AuditEvent::new("user.unlock", SUCCESS).actor(&user.sub).target("user", &target.id)).await;
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+pub(crate) fn auth_response(state: &AppState, tokens: IssuedTokens, user: User) -> AuthResponse {
+    AuthResponse {
+        token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        expires_in: state.config.jwt_expiration,
+        user,
+    }
+}
+
+// Trades a refresh token for a new access/refresh pair. Each refresh token works once.
+pub async fn refresh(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshTokenDto>,
+) -> impl IntoResponse {
+    let token = match find_refresh_token(&state, &payload.refresh_token).await {
+        Some(t) => t,
+        None => return (StatusCode::UNAUTHORIZED, "Invalid refresh token").into_response(),
+    };
+
+    // A revoked (logged out) token is simply dead
+    if token.revoked_at.is_some() && token.used_at.is_none() {
+        return (StatusCode::UNAUTHORIZED, "Invalid refresh token").into_response();
+    }
+
+    if token.expires_at <= chrono::Utc::now().naive_utc() {
+        return (StatusCode::UNAUTHORIZED, "Refresh token has expired").into_response();
+    }
+
+    // Presenting a token that was already rotated means it leaked: kill the whole
+    // family and every access token the user currently holds.
+    let rotated = token.used_at.is_none() && match mark_used(&state, &token).await {
+        Ok(rotated) => rotated,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    if !rotated {
+        tracing::warn!("Refresh token reuse detected for user {} (family {})", token.user_id, token.family_id);
+        let revoked = match revoke_family(&state, &token.family_id).await {
+            Ok(()) => bump_token_version(&state, &token.user_id).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = revoked {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+        return (StatusCode::UNAUTHORIZED, "Invalid refresh token").into_response();
+    }
+
+    let user = match find_user(&state, &token.user_id).await {
+        Some(u) if u.is_active => u,
+        _ => return (StatusCode::UNAUTHORIZED, "Account is deactivated or no longer exists").into_response(),
+    };
+
+    match issue_tokens(&state, &user, Some(&token.family_id)).await {
+        Ok(tokens) => (StatusCode::OK, Json(auth_response(&state, tokens, user))).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+// Revokes the presented access token and, if given, its refresh token family.
+// With `all: true` every session of the user is signed out.
+pub async fn logout(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    payload: Option<Json<LogoutDto>>,
+) -> impl IntoResponse {
+    if !user.is_session() {
+        return (StatusCode::BAD_REQUEST, "API tokens are revoked via /api/tokens").into_response();
+    }
+
+    let payload = payload.map(|Json(p)| p).unwrap_or(LogoutDto { refresh_token: None, all: None });
+
+    if payload.all.unwrap_or(false) {
+        if let Err(e) = revoke_user_sessions(&state, &user.sub).await {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+        return StatusCode::NO_CONTENT.into_response();
+    }
+
+    if let Some(raw) = &payload.refresh_token {
+        match find_refresh_token(&state, raw).await {
+            Some(t) if t.user_id == user.sub => {
+                if let Err(e) = revoke_family(&state, &t.family_id).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+                }
+            },
+            _ => return (StatusCode::BAD_REQUEST, "Invalid refresh token").into_response(),
+        }
+    }
+
+    revoke_access_token(&state, &user).await;
+
+    StatusCode::NO_CONTENT.into_response()
 }
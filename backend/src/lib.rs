@@ -0,0 +1,142 @@
+use axum::{
+    http::{header, HeaderName, HeaderValue},
+    routing::{delete, get, head, patch, post, put},
+    Router,
+};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+use tower_http::trace::TraceLayer;
+
+pub mod config;
+pub mod state;
+pub mod services;
+pub mod middleware;
+pub mod handlers;
+pub mod models;
+// mod db; // Handlers use state.db directly for now
+
+use crate::state::AppState;
+use crate::handlers::{user, password, mfa, oidc, jwks, token, folder, file, content, multipart, tus, trash, copy, permission, share, audit, storage};
+
+/// Every route of the API, ready to serve. The binary and the integration tests
+/// both build the app through this.
+pub fn router(state: AppState) -> Router {
+    let cors = cors(&state.config.app_url);
+
+    Router::new()
+        // Auth Routes
+        .route("/api/auth/login", post(user::login))
+        .route("/api/auth/refresh", post(user::refresh))
+        .route("/api/auth/logout", post(user::logout))
+        .route("/api/auth/password", post(password::change_password))
+        .route("/api/auth/password/forgot", post(password::forgot_password))
+        .route("/api/auth/password/reset", post(password::reset_password))
+
+        .route("/.well-known/jwks.json", get(jwks::jwks))
+
+        // OpenID Connect Routes
+        .route("/api/auth/oidc/login", get(oidc::oidc_login))
+        .route("/api/auth/oidc/callback", get(oidc::oidc_callback))
+        .route("/api/auth/oidc/link", post(oidc::oidc_link))
+        .route("/api/auth/oidc/identities", get(oidc::list_identities))
+        .route("/api/auth/oidc/identities/:id", delete(oidc::unlink_identity))
+
+        // Personal Access Token Routes
+        .route("/api/tokens", get(token::list_api_tokens).post(token::create_api_token))
+        .route("/api/tokens/:id", delete(token::revoke_api_token))
+
+        // Two-Factor Authentication Routes
+        .route("/api/auth/mfa/verify", post(mfa::verify_mfa))
+        .route("/api/auth/mfa/enroll", post(mfa::enroll_mfa))
+        .route("/api/auth/mfa/confirm", post(mfa::confirm_mfa))
+        .route("/api/auth/mfa/disable", post(mfa::disable_mfa))
+        .route("/api/auth/mfa/recovery-codes", post(mfa::regenerate_recovery_codes))
+        .route("/api/mfa-policies", get(mfa::list_mfa_policies))
+        .route("/api/mfa-policies/:role", put(mfa::update_mfa_policy))
+
+        // User Management Routes (admin only)
+        .route("/api/users", get(user::list_users).post(user::create_user))
+        .route("/api/users/:id", get(user::get_user).patch(user::update_user).delete(user::delete_user))
+        .route("/api/users/:id/mfa", delete(mfa::reset_user_mfa))
+        .route("/api/users/:id/unlock", post(user::unlock_user))
+
+        // Audit Log Routes (admin only)
+        .route("/api/audit-events", get(audit::list_audit_events))
+        .route("/api/audit-events/export", get(audit::export_audit_events))
+        
+        // Folder Routes
+        .route("/api/folders", post(folder::create_folder))
+        .route("/api/folders/:id", get(folder::list_folder).patch(folder::update_folder).delete(folder::delete_folder))
+        .route("/api/folders/:id/copy", post(copy::copy_folder))
+        .route("/api/folders/:id/permissions", get(permission::list_permissions).post(permission::grant_permission))
+        .route("/api/folders/:id/permissions/:user_id", patch(permission::update_permission).delete(permission::revoke_permission))
+        .route("/api/copy-jobs/:id", get(copy::get_copy_job))
+        
+        // File Routes
+        .route("/api/files/upload", post(file::upload_file))
+        .route("/api/files/:id", patch(file::update_file).delete(file::delete_file))
+        .route("/api/files/:id/download", get(file::download_file))
+        .route("/api/files/:id/content", get(content::file_content))
+        .route("/api/files/:id/copy", post(copy::copy_file))
+        .route("/api/files/:id/complete", post(file::complete_upload))
+        .route("/api/files/:id/multipart", post(multipart::initiate_multipart).delete(multipart::abort_multipart))
+        .route("/api/files/:id/multipart/parts", get(multipart::list_uploaded_parts).post(multipart::presign_parts))
+        .route("/api/files/:id/multipart/complete", post(multipart::complete_multipart))
+
+        // Share Link Routes
+        .route("/api/shares", get(share::list_share_links).post(share::create_share_link))
+        .route("/api/shares/:id", delete(share::revoke_share_link))
+        .route("/s/:token", get(share::open_share_link))
+
+        // Trash Routes
+        .route("/api/trash", get(trash::list_trash))
+        .route("/api/trash/:id/restore", post(trash::restore_item))
+
+        // tus Resumable Upload Routes
+        .route("/api/tus", post(tus::create_upload).options(tus::options))
+        .route("/api/tus/:id", head(tus::head_upload).patch(tus::patch_upload).delete(tus::terminate_upload))
+
+        // Signed URLs of the local storage backend
+        .route("/api/storage/objects/*key", get(storage::download_object).put(storage::upload_object))
+        .route("/api/storage/uploads/:upload_id/:part_number", put(storage::upload_part))
+
+        // Middleware
+        .layer(TraceLayer::new_for_http())
+        .layer(cors)
+        .with_state(state)
+}
+
+// Browsers may call the API from the frontend (APP_URL) only. It may send credentials
+// because linking an OIDC account has to keep the binding cookie it gets back; every
+// other request authenticates with a bearer token.
+fn cors(app_url: &str) -> CorsLayer {
+    let origin = app_url.splitn(4, '/').take(3).collect::<Vec<_>>().join("/");
+    let allow_origin = match HeaderValue::from_str(&origin) {
+        Ok(origin) => AllowOrigin::list([origin]),
+        Err(_) => {
+            tracing::warn!("APP_URL '{}' is not a valid origin, cross-origin requests are refused", app_url);
+            AllowOrigin::list([])
+        },
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_credentials(true)
+        .allow_methods(AllowMethods::mirror_request())
+        .allow_headers(AllowHeaders::mirror_request())
+        .expose_headers([
+            header::LOCATION,
+            header::ETAG,
+            header::LAST_MODIFIED,
+            header::ACCEPT_RANGES,
+            header::CONTENT_RANGE,
+            header::CONTENT_DISPOSITION,
+            header::RETRY_AFTER,
+            HeaderName::from_static("tus-resumable"),
+            HeaderName::from_static("tus-version"),
+            HeaderName::from_static("tus-extension"),
+            HeaderName::from_static("tus-checksum-algorithm"),
+            HeaderName::from_static("upload-offset"),
+            HeaderName::from_static("upload-length"),
+            HeaderName::from_static("upload-metadata"),
+        ])
+}
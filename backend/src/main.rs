@@ -1,24 +1,10 @@
-use axum::{
-    routing::{get, post},
-    Router,
-};
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tower_http::cors::{CorsLayer, Any};
-use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-mod config;
-mod state;
-mod services;
-mod middleware;
-mod handlers;
-mod models;
-// mod db; // Handlers use state.db directly for now
-
-use crate::config::Config;
-use crate::state::AppState;
-use crate::handlers::{user, folder, file};
+use backend::config::Config;
+use backend::services;
+use backend::state::AppState;
 
 #[tokio::main]
 async fn main() {
@@ -37,25 +23,17 @@ async fn main() {
 
     // 3. Initialize App State (DB, Redis, S3)
     let state = AppState::new(Arc::new(config)).await;
+    tracing::info!("Storage backend: {}", state.storage.name());
 
-    // 4. Define Routes
-    let app = Router::new()
-        // Auth Routes
-        // .route("/api/auth/register", post(user::register))
-        .route("/api/auth/login", post(user::login))
-        
-        // Folder Routes
-        .route("/api/folders", post(folder::create_folder))
-        .route("/api/folders/:id", get(folder::list_folder))
-        
-        // File Routes
-        .route("/api/files/upload", post(file::upload_file))
-        .route("/api/files/:id/download", get(file::download_file))
+    if let Err(e) = services::copy::fail_interrupted_jobs(&state).await {
+        tracing::warn!("Failed to mark interrupted copy jobs: {:?}", e);
+    }
 
-        // Middleware
-        .layer(TraceLayer::new_for_http())
-        .layer(CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any))
-        .with_state(state);
+    // Background: permanently delete expired trash
+    tokio::spawn(services::trash::run_purge_task(state.clone()));
+
+    // 4. Define Routes
+    let app = backend::router(state);
 
     // 5. Run Server
     let addr_str = format!("{}:{}", host, port);
@@ -64,5 +42,5 @@ async fn main() {
     tracing::info!("Server listening on {}", addr);
     
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 }
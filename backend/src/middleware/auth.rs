@@ -3,12 +3,93 @@ use axum::{
     extract::{FromRequestParts, FromRef},
     http::{header, request::Parts, StatusCode},
 };
+use chrono::NaiveDateTime;
 use crate::state::AppState;
 use crate::services::auth::{verify_jwt, Claims};
+use crate::services::session::hash_token;
 
+// Personal access tokens look like "fer_<random>"; anything else is treated as a JWT
+pub const API_TOKEN_PREFIX: &str = "fer_";
 
 pub struct AuthUser(pub Claims);
 
+// Tokens outlive admin changes, so the account is re-checked on every request:
+// revoked tokens, deactivated or deleted users are rejected and role changes apply immediately.
+async fn current_claims(state: &AppState, mut claims: Claims) -> Option<Claims> {
+    // Single logged-out tokens are only listed in Redis; it being down must not lock
+    // everyone out, and signing out everywhere is still enforced by the DB check below
+    match state.cache.is_token_revoked(&claims.jti).await {
+        Ok(true) => return None,
+        Ok(false) => {},
+        Err(e) => tracing::warn!("Token revocation check failed: {:?}", e),
+    }
+
+    let account: Option<(String, i32)> = sqlx::query_as("SELECT CAST(role AS CHAR), token_version FROM users WHERE id = ? AND is_active = TRUE")
+        .bind(&claims.sub)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+
+    let (role, token_version) = account?;
+    if claims.ver < i64::from(token_version) {
+        return None;
+    }
+
+    claims.role = role;
+    Some(claims)
+}
+
+// Resolves a personal access token into claims carrying its scope
+async fn api_token_claims(state: &AppState, token: &str) -> Option<Claims> {
+    let row: Option<(String, String, String, Option<NaiveDateTime>, String)> = sqlx::query_as(
+        "SELECT t.id, t.user_id, CAST(t.scope AS CHAR), t.expires_at, CAST(u.role AS CHAR)
+         FROM api_tokens t JOIN users u ON u.id = t.user_id
+         WHERE t.token_hash = ? AND u.is_active = TRUE",
+    )
+    .bind(hash_token(token))
+    .fetch_optional(&state.db)
+    .await
+    .unwrap_or(None);
+
+    let (id, user_id, scope, expires_at, role) = row?;
+    let now = chrono::Utc::now().naive_utc();
+    if matches!(expires_at, Some(t) if t <= now) {
+        return None;
+    }
+
+    // At most one write a minute per token
+    let _ = sqlx::query("UPDATE api_tokens SET last_used_at = ? WHERE id = ? AND (last_used_at IS NULL OR last_used_at < ?)")
+        .bind(now)
+        .bind(&id)
+        .bind(now - chrono::Duration::minutes(1))
+        .execute(&state.db)
+        .await;
+
+    Some(Claims {
+        sub: user_id,
+        role,
+        exp: expires_at.map(|t| t.and_utc().timestamp() as usize).unwrap_or(usize::MAX),
+        iat: 0,
+        jti: id,
+        ver: 0,
+        scope: Some(scope),
+    })
+}
+
+async fn authenticate(state: &AppState, token: &str) -> Result<Claims, (StatusCode, &'static str)> {
+    if token.starts_with(API_TOKEN_PREFIX) {
+        return api_token_claims(state, token)
+            .await
+            .ok_or((StatusCode::UNAUTHORIZED, "Invalid, expired or revoked API token"));
+    }
+
+    let claims = verify_jwt(token, &state.jwt_keys).map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token"))?;
+
+    current_claims(state, claims)
+        .await
+        .ok_or((StatusCode::UNAUTHORIZED, "Token revoked or account deactivated"))
+}
+
 #[async_trait]
 impl<S> FromRequestParts<S> for AuthUser
 where
@@ -29,12 +110,7 @@ where
         };
 
         let app_state = AppState::from_ref(state);
-        let secret = &app_state.config.jwt_secret;
-
-        match verify_jwt(token, secret) {
-            Ok(claims) => Ok(AuthUser(claims)),
-            Err(_) => Err((StatusCode::UNAUTHORIZED, "Invalid token")),
-        }
+        authenticate(&app_state, token).await.map(AuthUser)
     }
 }
 
@@ -59,12 +135,8 @@ where
             _ => return Ok(OptionalAuthUser(None)), // No token
         };
 
+        // Token present but invalid is still an error
         let app_state = AppState::from_ref(state);
-        let secret = &app_state.config.jwt_secret;
-
-        match verify_jwt(token, secret) {
-            Ok(claims) => Ok(OptionalAuthUser(Some(claims))),
-            Err(_) => Err((StatusCode::UNAUTHORIZED, "Invalid token")), // Token present but invalid
-        }
+        authenticate(&app_state, token).await.map(|claims| OptionalAuthUser(Some(claims)))
     }
 }
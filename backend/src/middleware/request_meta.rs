@@ -0,0 +1,54 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, FromRef},
+    http::{header, request::Parts},
+};
+use crate::state::AppState;
+
+/// Where a request came from, for throttling and the audit log. X-Forwarded-For is
+/// only believed with TRUST_PROXY set, otherwise anyone could pick their own address,
+/// and only its rightmost entry: that one is appended by our proxy, anything left of
+/// it is whatever the client sent.
+pub struct RequestMeta {
+    pub ip: String,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestMeta
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let app_state = AppState::from_ref(state);
+
+        let forwarded = app_state.config.trust_proxy
+            .then(|| parts.headers.get("x-forwarded-for"))
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .map(|ip| ip.trim().chars().take(45).collect::<String>())
+            .filter(|ip| !ip.is_empty());
+
+        let ip = forwarded.unwrap_or_else(|| {
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+                .unwrap_or_else(|| "unknown".to_string())
+        });
+
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|ua| ua.chars().take(512).collect());
+
+        Ok(RequestMeta { ip, user_agent })
+    }
+}
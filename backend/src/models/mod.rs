@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use chrono::NaiveDateTime;
@@ -9,7 +10,14 @@ pub struct User {
     pub email: String,
     #[serde(skip_serializing)] 
     pub password_hash: String,
-    pub role: String, // 'admin', 'staff', 'student'
+    pub role: String, // 'admin', 'osis', 'media_guru'
+    pub is_active: bool,
+    pub totp_enabled: bool,
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>, // Set on enrollment, active once totp_enabled
+    pub ldap_uid: Option<String>,    // Directory login name for LDAP-managed accounts
+    #[serde(skip_serializing, default)]
+    pub token_version: i32,          // Bumped to revoke every access token issued so far
     pub created_at: Option<NaiveDateTime>,
 }
 
@@ -21,19 +29,30 @@ pub struct Folder {
     pub owner_id: String,
     pub is_public: bool, // 0 or 1 in MySQL TINYINT(1) -> bool in sqlx
     pub created_at: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
 pub struct File {
     pub id: String,
     pub name: String,
-    pub folder_id: String,
+    pub folder_id: Option<String>, // None = owner's root
     pub owner_id: String,
     pub storage_key: String,
     pub size: i64,
     pub mime_type: Option<String>,
     pub is_public: bool,
+    pub status: String, // 'pending', 'committed', 'failed'
+    #[serde(skip_serializing, default)]
+    pub upload_id: Option<String>, // S3 multipart upload id while a multipart upload is in progress
     pub created_at: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -41,17 +60,118 @@ pub struct FolderPermission {
     pub id: String,
     pub folder_id: String,
     pub user_id: String,
-    pub permission: String, // 'viewer', 'editor'
+    pub permission: String, // 'viewer', 'editor', 'none' (blocks inherited access)
+    pub created_at: Option<NaiveDateTime>,
+}
+
+// Permission entry joined with the grantee's user info, for the sharing UI
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct FolderPermissionEntry {
+    pub user_id: String,
+    pub name: String,
+    pub email: String,
+    pub permission: String,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ShareLink {
+    pub id: String,
+    pub token: String,
+    pub file_id: Option<String>,
+    pub folder_id: Option<String>,
+    pub created_by: String,
+    pub expires_at: Option<NaiveDateTime>,
+    pub max_downloads: Option<i32>,
+    pub download_count: i32,
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+// Stored hashed; each login starts a family, each refresh rotates within it
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct RefreshToken {
+    pub id: String,
+    pub user_id: String,
+    pub family_id: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,    // Set once rotated; presenting it again is reuse
+    pub revoked_at: Option<NaiveDateTime>,
     pub created_at: Option<NaiveDateTime>,
 }
 
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub token_prefix: String,
+    pub scope: String, // 'read', 'upload', 'admin'
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct OidcIdentity {
+    pub id: String,
+    pub user_id: String,
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub last_login_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct AuditEntry {
+    pub id: String,
+    pub created_at: NaiveDateTime,
+    pub actor_id: Option<String>,
+    pub action: String,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub outcome: String, // 'success', 'failure', 'denied'
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Option<serde_json::Value>,
+}
+
 // DTOs (Data Transfer Objects)
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateUserDto {
     pub name: String,
     pub email: String,
     pub password: String,
+    pub role: Option<String>, // Defaults to 'osis'
+}
+
+// Admin PATCH body: omitted fields are left unchanged
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateUserDto {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub password: Option<String>,
     pub role: Option<String>,
+    pub is_active: Option<bool>,
+    pub ldap_uid: Option<String>, // Links the account to a directory login, "" unlinks it
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserListResponse {
+    pub users: Vec<User>,
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditListResponse {
+    pub events: Vec<AuditEntry>,
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -63,9 +183,115 @@ pub struct LoginDto {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthResponse {
     pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64, // Access token lifetime in seconds
     pub user: User,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshTokenDto {
+    pub refresh_token: String,
+}
+
+// Returned by login instead of tokens when a second factor is needed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaChallengeResponse {
+    pub mfa_required: bool,
+    pub enrollment_required: bool, // Role requires 2FA but the user hasn't set it up yet
+    pub mfa_token: String,
+    pub expires_in: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaVerifyDto {
+    pub mfa_token: String,
+    pub code: String, // TOTP code or a recovery code
+}
+
+// Enrollment works with a normal access token, or with the challenge token from login
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaEnrollDto {
+    pub mfa_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaEnrollResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaConfirmDto {
+    pub code: String,
+    pub mfa_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaConfirmResponse {
+    pub recovery_codes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<AuthResponse>, // When enrolling during login
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaDisableDto {
+    pub password: String,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaCodeDto {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct MfaPolicy {
+    pub role: String,
+    pub required: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateMfaPolicyDto {
+    pub required: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiTokenDto {
+    pub name: String,
+    pub scope: String,
+    pub expires_in_days: Option<i64>, // Omitted = never expires
+}
+
+// The plaintext token is only returned once, on creation
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiTokenResponse {
+    pub token: String,
+    pub api_token: ApiToken,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordDto {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForgotPasswordDto {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResetPasswordDto {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogoutDto {
+    pub refresh_token: Option<String>,
+    pub all: Option<bool>, // Sign out every session of the user
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateFolderDto {
     pub name: String,
@@ -73,6 +299,48 @@ pub struct CreateFolderDto {
     pub is_public: Option<bool>,
 }
 
+// PATCH bodies: omitted fields are left unchanged, "root" moves to the owner's root
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateFolderDto {
+    pub name: Option<String>,
+    pub parent_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateFileDto {
+    pub name: Option<String>,
+    pub folder_id: Option<String>,
+}
+
+// Share with a user, identified by either id or email
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GrantPermissionDto {
+    pub user_id: Option<String>,
+    pub email: Option<String>,
+    pub permission: String, // 'viewer', 'editor', 'none' (blocks inherited access)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdatePermissionDto {
+    pub permission: String,
+}
+
+// Exactly one of file_id / folder_id
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateShareLinkDto {
+    pub file_id: Option<String>,
+    pub folder_id: Option<String>,
+    pub expires_in: Option<i64>, // Seconds from now; omitted = never expires
+    pub max_downloads: Option<i32>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CopyRequest {
+    pub destination_id: Option<String>, // Omitted -> same parent, "root" -> caller's root
+    pub name: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileUploadRequest {
     pub name: String,
@@ -86,4 +354,77 @@ pub struct FileUploadResponse {
     pub file_id: String,
     pub presigned_url: String,
     pub storage_key: String,
+    pub upload_method: String, // "PUT" the bytes, or "POST" a form of `fields` with the file last
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<BTreeMap<String, String>>,
+}
+
+// Multipart Upload DTOs
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MultipartInitResponse {
+    pub file_id: String,
+    pub upload_id: String,
+    pub storage_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PresignPartsRequest {
+    pub part_numbers: Vec<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PresignedPart {
+    pub part_number: i32,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadedPartDto {
+    pub part_number: i32,
+    pub etag: String,
+    pub size: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompletedPartDto {
+    pub part_number: i32,
+    pub etag: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompleteMultipartRequest {
+    pub parts: Vec<CompletedPartDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct TusUpload {
+    pub file_id: String,
+    pub upload_length: i64,
+    pub upload_offset: i64,
+    pub next_part_number: i32,
+    pub tail_size: i64, // Bytes parked in the tail object, not yet part of an S3 part
+    pub created_at: Option<NaiveDateTime>,
+}
+
+// Trash DTOs
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashResponse {
+    pub folders: Vec<Folder>,
+    pub files: Vec<File>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct CopyJob {
+    pub id: String,
+    pub user_id: String,
+    pub source_folder_id: String,
+    pub destination_id: Option<String>,
+    pub result_folder_id: Option<String>,
+    pub status: String, // 'queued', 'running', 'completed', 'failed'
+    pub total_files: i32,
+    pub copied_files: i32,
+    pub copied_bytes: i64,
+    pub error: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
 }
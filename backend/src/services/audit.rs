@@ -0,0 +1,68 @@
+use sqlx::query;
+use uuid::Uuid;
+
+use crate::middleware::request_meta::RequestMeta;
+use crate::state::AppState;
+
+// Append-only trail in audit_events. Writing never fails the request it describes:
+// errors are logged and the action goes ahead.
+
+pub const SUCCESS: &str = "success";
+pub const FAILURE: &str = "failure"; // Wrong credentials, invalid input, server error
+pub const DENIED: &str = "denied";   // Authenticated but not allowed, or throttled
+
+pub struct AuditEvent {
+    action: &'static str,
+    outcome: &'static str,
+    actor_id: Option<String>,
+    target_type: Option<&'static str>,
+    target_id: Option<String>,
+    details: Option<serde_json::Value>,
+}
+
+impl AuditEvent {
+    pub fn new(action: &'static str, outcome: &'static str) -> Self {
+        Self { action, outcome, actor_id: None, target_type: None, target_id: None, details: None }
+    }
+
+    pub fn actor(mut self, user_id: &str) -> Self {
+        self.actor_id = Some(user_id.to_string());
+        self
+    }
+
+    pub fn target(mut self, target_type: &'static str, target_id: &str) -> Self {
+        self.target_type = Some(target_type);
+        self.target_id = Some(target_id.to_string());
+        self
+    }
+
+    pub fn details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+pub async fn record(state: &AppState, meta: &RequestMeta, event: AuditEvent) {
+    let result = query(
+        "INSERT INTO audit_events (id, actor_id, action, target_type, target_id, outcome, ip, user_agent, details)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&event.actor_id)
+    .bind(event.action)
+    .bind(event.target_type)
+    .bind(&event.target_id)
+    .bind(event.outcome)
+    .bind(&meta.ip)
+    .bind(&meta.user_agent)
+    .bind(&event.details)
+    .execute(&state.db)
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!(
+            "Failed to write audit event {} ({}) by {:?} on {:?}: {:?}",
+            event.action, event.outcome, event.actor_id, event.target_id, e,
+        );
+    }
+}
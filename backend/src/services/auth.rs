@@ -1,18 +1,189 @@
-use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey};
+use std::collections::HashMap;
+use std::fs;
+use jsonwebtoken::{encode, decode, decode_header, Algorithm, Header, Validation, EncodingKey, DecodingKey};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters,
+    OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rsa::{pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey}, pkcs8::{DecodePrivateKey, DecodePublicKey}, traits::PublicKeyParts, RsaPrivateKey, RsaPublicKey};
+use ring::signature::{Ed25519KeyPair, KeyPair};
 use serde::{Deserialize, Serialize};
 use chrono::{Utc, Duration};
 use crate::config::Config;
-use anyhow::{Result, Context};
+use anyhow::{anyhow, bail, Result, Context};
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: String, // user_id
     pub role: String,
     pub exp: usize,
+    pub iat: usize,
+    pub jti: String, // Unique id, used to revoke this token
+    pub ver: i64,    // User's token version at issue time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>, // Set for personal access tokens, None = interactive session
 }
 
-pub fn create_jwt(user_id: &str, role: &str, config: &Config) -> Result<String> {
-    let expiration = Utc::now()
+// Personal access token scopes, each one including those before it: 'read' browses and
+// downloads, 'upload' also creates and manages content (including renaming, moving,
+// deleting and sharing it), 'admin' is needed for the admin-only endpoints
+pub const SCOPES: [&str; 3] = ["read", "upload", "admin"];
+
+impl Claims {
+    /// Whether the credential may perform an action needing `scope`. Sessions may do anything.
+    pub fn allows(&self, scope: &str) -> bool {
+        let rank = |s: &str| SCOPES.iter().position(|x| *x == s);
+        match &self.scope {
+            None => true,
+            Some(granted) => matches!((rank(granted), rank(scope)), (Some(g), Some(n)) if g >= n),
+        }
+    }
+
+    /// Account and credential management (passwords, 2FA, tokens) needs an interactive session.
+    pub fn is_session(&self) -> bool {
+        self.scope.is_none()
+    }
+}
+
+/// Access token signing and verification keys.
+///
+/// HS256 uses JWT_SECRET. RS256 and EdDSA load every `<kid>.pem` in JWT_KEYS_DIR
+/// (PKCS#8/PKCS#1 private keys, or public keys for verification only). JWT_SIGNING_KID
+/// picks the key that signs; all of them verify and are published as the JWKS, so a
+/// key can be rotated by adding the new one, switching the kid, and deleting the old
+/// file once its tokens have expired.
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    signing_key: EncodingKey,
+    signing_kid: Option<String>,
+    verification_keys: HashMap<String, DecodingKey>, // By kid, "" for HS256
+    jwks: JwkSet,                                    // Public keys, empty for HS256
+}
+
+impl JwtKeys {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        match config.jwt_algorithm.as_str() {
+            "HS256" => {
+                if config.jwt_secret.is_empty() {
+                    bail!("JWT_SECRET must be set for HS256");
+                }
+                Ok(Self {
+                    algorithm: Algorithm::HS256,
+                    signing_key: EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+                    signing_kid: None,
+                    verification_keys: HashMap::from([(String::new(), DecodingKey::from_secret(config.jwt_secret.as_bytes()))]),
+                    jwks: JwkSet { keys: Vec::new() },
+                })
+            },
+            "RS256" => Self::load_dir(config, Algorithm::RS256),
+            "EdDSA" => Self::load_dir(config, Algorithm::EdDSA),
+            other => bail!("Unsupported JWT_ALGORITHM '{}' (expected HS256, RS256 or EdDSA)", other),
+        }
+    }
+
+    fn load_dir(config: &Config, algorithm: Algorithm) -> Result<Self> {
+        let dir = config.jwt_keys_dir.as_deref().context("JWT_KEYS_DIR must be set for asymmetric signing")?;
+        let signing_kid = config.jwt_signing_kid.clone().context("JWT_SIGNING_KID must be set for asymmetric signing")?;
+
+        let mut signing_key = None;
+        let mut verification_keys = HashMap::new();
+        let mut jwks = JwkSet { keys: Vec::new() };
+
+        let mut paths: Vec<_> = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read JWT_KEYS_DIR {}", dir))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "pem"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let kid = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+            let pem = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+            let (params, private) = public_params(algorithm, &pem).with_context(|| format!("Invalid key {}", path.display()))?;
+
+            if kid == signing_kid {
+                if !private {
+                    bail!("Signing key {} is a public key", path.display());
+                }
+                signing_key = Some(match algorithm {
+                    Algorithm::RS256 => EncodingKey::from_rsa_pem(pem.as_bytes())?,
+                    _ => EncodingKey::from_ed_pem(pem.as_bytes())?,
+                });
+            }
+
+            let jwk = Jwk {
+                common: CommonParameters {
+                    public_key_use: Some(PublicKeyUse::Signature),
+                    key_algorithm: Some(if algorithm == Algorithm::RS256 { KeyAlgorithm::RS256 } else { KeyAlgorithm::EdDSA }),
+                    key_id: Some(kid.clone()),
+                    ..Default::default()
+                },
+                algorithm: params,
+            };
+            verification_keys.insert(kid, DecodingKey::from_jwk(&jwk)?);
+            jwks.keys.push(jwk);
+        }
+
+        let signing_key = signing_key.ok_or_else(|| anyhow!("No key file {}.pem in {}", signing_kid, dir))?;
+
+        Ok(Self { algorithm, signing_key, signing_kid: Some(signing_kid), verification_keys, jwks })
+    }
+
+    /// Public half of the verification keys, served at /.well-known/jwks.json.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+// JWK parameters of the key's public half, and whether the PEM held a private key
+fn public_params(algorithm: Algorithm, pem: &str) -> Result<(AlgorithmParameters, bool)> {
+    if algorithm == Algorithm::RS256 {
+        let (public, private) = match RsaPrivateKey::from_pkcs8_pem(pem).or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem)) {
+            Ok(key) => (key.to_public_key(), true),
+            Err(_) => (
+                RsaPublicKey::from_public_key_pem(pem)
+                    .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+                    .map_err(|_| anyhow!("Not an RSA key"))?,
+                false,
+            ),
+        };
+        let params = AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: URL_SAFE_NO_PAD.encode(public.n().to_bytes_be()),
+            e: URL_SAFE_NO_PAD.encode(public.e().to_bytes_be()),
+        });
+        return Ok((params, private));
+    }
+
+    let block = ::pem::parse(pem)?;
+    let (x, private) = match block.tag() {
+        "PRIVATE KEY" => {
+            let pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(block.contents()).map_err(|_| anyhow!("Not an Ed25519 key"))?;
+            (pair.public_key().as_ref().to_vec(), true)
+        },
+        // SubjectPublicKeyInfo for Ed25519 is a fixed 12-byte prefix and the 32-byte key
+        "PUBLIC KEY" if block.contents().len() == 44 && block.contents().starts_with(&ED25519_SPKI_PREFIX) => {
+            (block.contents()[12..].to_vec(), false)
+        },
+        _ => bail!("Not an Ed25519 key"),
+    };
+
+    let params = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+        key_type: OctetKeyPairType::OctetKeyPair,
+        curve: EllipticCurve::Ed25519,
+        x: URL_SAFE_NO_PAD.encode(x),
+    });
+    Ok((params, private))
+}
+
+const ED25519_SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+pub fn create_jwt(user_id: &str, role: &str, version: i64, config: &Config, keys: &JwtKeys) -> Result<String> {
+    let now = Utc::now();
+    let expiration = now
         .checked_add_signed(Duration::seconds(config.jwt_expiration))
         .expect("valid timestamp")
         .timestamp();
@@ -21,23 +192,34 @@ pub fn create_jwt(user_id: &str, role: &str, config: &Config) -> Result<String>
         sub: user_id.to_owned(),
         role: role.to_owned(),
         exp: expiration as usize,
+        iat: now.timestamp() as usize,
+        jti: Uuid::new_v4().to_string(),
+        ver: version,
+        scope: None,
     };
 
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
-    ).context("Failed to encode JWT")?;
+    let mut header = Header::new(keys.algorithm);
+    header.kid = keys.signing_kid.clone();
+
+    let token = encode(&header, &claims, &keys.signing_key).context("Failed to encode JWT")?;
 
     Ok(token)
 }
 
-pub fn verify_jwt(token: &str, secret: &str) -> Result<Claims> {
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::default(),
-    ).context("Failed to decode JWT")?;
-    
+pub fn verify_jwt(token: &str, keys: &JwtKeys) -> Result<Claims> {
+    let header = decode_header(token).context("Failed to decode JWT header")?;
+
+    // HS256 has one key; asymmetric tokens must name theirs
+    let kid = match keys.algorithm {
+        Algorithm::HS256 => String::new(),
+        _ => header.kid.context("JWT has no kid")?,
+    };
+    let key = keys.verification_keys.get(&kid).context("Unknown JWT kid")?;
+
+    // Only the configured algorithm is accepted, whatever the header claims
+    let token_data = decode::<Claims>(token, key, &Validation::new(keys.algorithm))
+        .context("Failed to decode JWT")?;
+
     Ok(token_data.claims)
 }
+
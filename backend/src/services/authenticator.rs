@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use bcrypt::{hash, verify, DEFAULT_COST};
+use ldap3::{ldap_escape, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use sqlx::{query, query_as, query_scalar, MySqlPool};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::handlers::user::ROLES;
+use crate::models::User;
+
+const INVALID_CREDENTIALS: u32 = 49; // LDAP result code
+
+/// Checks a login name and password. Selected by AUTH_BACKENDS; login tries each
+/// backend in order and the first one that accepts the credentials wins.
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// `Ok(None)` means this backend doesn't accept the credentials, `Err` that it
+    /// couldn't tell (e.g. directory unreachable).
+    async fn authenticate(&self, db: &MySqlPool, login: &str, password: &str) -> Result<Option<User>>;
+}
+
+pub fn from_config(config: &Config) -> Result<Vec<Arc<dyn Authenticator>>> {
+    let mut backends: Vec<Arc<dyn Authenticator>> = Vec::new();
+
+    for name in &config.auth_backends {
+        match name.as_str() {
+            "local" => backends.push(Arc::new(LocalAuthenticator)),
+            "ldap" => backends.push(Arc::new(LdapAuthenticator::new(config)?)),
+            other => bail!("Unknown auth backend '{}' (expected 'local' or 'ldap')", other),
+        }
+    }
+
+    if backends.is_empty() {
+        bail!("AUTH_BACKENDS must name at least one backend");
+    }
+
+    Ok(backends)
+}
+
+/// Email + bcrypt hash from the users table. Directory-managed accounts are skipped,
+/// their password lives in the directory.
+pub struct LocalAuthenticator;
+
+#[async_trait]
+impl Authenticator for LocalAuthenticator {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn authenticate(&self, db: &MySqlPool, login: &str, password: &str) -> Result<Option<User>> {
+        let user: Option<User> = query_as("SELECT * FROM users WHERE email = ? AND ldap_uid IS NULL")
+            .bind(login.trim().to_lowercase())
+            .fetch_optional(db)
+            .await?;
+
+        // Unknown emails and password-less accounts still pay for a bcrypt check,
+        // so response time doesn't tell which accounts exist
+        match user {
+            Some(user) if !user.password_hash.is_empty() => {
+                Ok(verify(password, &user.password_hash).unwrap_or(false).then_some(user))
+            },
+            _ => {
+                let _ = verify(password, dummy_hash());
+                Ok(None)
+            },
+        }
+    }
+}
+
+fn dummy_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash("ferrum-dummy-password", DEFAULT_COST).expect("Failed to hash dummy password"))
+}
+
+/// Simple bind against LDAP / Active Directory: finds the user's entry (optionally
+/// with a service account), binds as that entry with the given password, then
+/// creates or refreshes the local account from the entry's attributes.
+pub struct LdapAuthenticator {
+    url: String,
+    starttls: bool,
+    bind_dn: Option<String>,
+    bind_password: Option<String>,
+    base_dn: String,
+    user_filter: String,
+    uid_attribute: String,
+    name_attribute: String,
+    email_attribute: String,
+    group_attribute: String,
+    role_mapping: Vec<(String, String)>,
+    default_role: String,
+    sync_roles: bool,
+}
+
+// What the directory says about a user after a successful bind
+struct DirectoryUser {
+    uid: String,
+    name: String,
+    email: String,
+    role: String,
+}
+
+impl LdapAuthenticator {
+    fn new(config: &Config) -> Result<Self> {
+        if config.ldap_base_dn.is_empty() {
+            bail!("LDAP_BASE_DN must be set when the ldap backend is enabled");
+        }
+        if !config.ldap_user_filter.contains("{login}") {
+            bail!("LDAP_USER_FILTER must contain {{login}}");
+        }
+        if let Some((_, role)) = config.ldap_role_mapping.iter().find(|(_, role)| !ROLES.contains(&role.as_str())) {
+            bail!("LDAP_ROLE_MAPPING names unknown role '{}'", role);
+        }
+        if !ROLES.contains(&config.ldap_default_role.as_str()) {
+            bail!("LDAP_DEFAULT_ROLE '{}' is not a role", config.ldap_default_role);
+        }
+
+        Ok(Self {
+            url: config.ldap_url.clone(),
+            starttls: config.ldap_starttls,
+            bind_dn: config.ldap_bind_dn.clone(),
+            bind_password: config.ldap_bind_password.clone(),
+            base_dn: config.ldap_base_dn.clone(),
+            user_filter: config.ldap_user_filter.clone(),
+            uid_attribute: config.ldap_uid_attribute.clone(),
+            name_attribute: config.ldap_name_attribute.clone(),
+            email_attribute: config.ldap_email_attribute.clone(),
+            group_attribute: config.ldap_group_attribute.clone(),
+            role_mapping: config.ldap_role_mapping.clone(),
+            default_role: config.ldap_default_role.clone(),
+            sync_roles: config.ldap_sync_roles,
+        })
+    }
+
+    async fn bind(&self, login: &str, password: &str) -> Result<Option<DirectoryUser>> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(Duration::from_secs(5))
+            .set_starttls(self.starttls);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.url)
+            .await
+            .context("LDAP connection failed")?;
+        ldap3::drive!(conn);
+
+        // 1. Find the entry as the service account (or anonymously)
+        if let (Some(dn), Some(pw)) = (&self.bind_dn, &self.bind_password) {
+            ldap.simple_bind(dn, pw).await?.success().context("LDAP service account bind failed")?;
+        }
+
+        let filter = self.user_filter.replace("{login}", &ldap_escape(login));
+        let attributes = [
+            self.uid_attribute.as_str(),
+            self.name_attribute.as_str(),
+            self.email_attribute.as_str(),
+            self.group_attribute.as_str(),
+        ];
+        let (entries, _) = ldap
+            .search(&self.base_dn, Scope::Subtree, &filter, attributes)
+            .await?
+            .success()
+            .context("LDAP user search failed")?;
+
+        // Unknown or ambiguous login: not ours to accept
+        if entries.len() != 1 {
+            let _ = ldap.unbind().await;
+            return Ok(None);
+        }
+        let entry = SearchEntry::construct(entries.into_iter().next().unwrap());
+
+        // 2. Prove the password by binding as the user
+        let result = ldap.simple_bind(&entry.dn, password).await?;
+        let _ = ldap.unbind().await;
+        if result.rc == INVALID_CREDENTIALS {
+            return Ok(None);
+        }
+        result.success().context("LDAP user bind failed")?;
+
+        // 3. Map attributes
+        let attrs = &entry.attrs;
+        let uid = first_value(attrs, &self.uid_attribute).unwrap_or_else(|| login.to_string());
+        let email = match first_value(attrs, &self.email_attribute) {
+            Some(email) => email.trim().to_lowercase(),
+            None => bail!("LDAP entry {} has no {} attribute", entry.dn, self.email_attribute),
+        };
+        let name = first_value(attrs, &self.name_attribute).unwrap_or_else(|| uid.clone());
+        let groups = values(attrs, &self.group_attribute);
+
+        Ok(Some(DirectoryUser { uid, name, email, role: self.map_role(&groups) }))
+    }
+
+    /// First mapping whose group matches one of the user's group DNs, either by the
+    /// full DN or its leading CN (case-insensitive), otherwise LDAP_DEFAULT_ROLE.
+    fn map_role(&self, groups: &[String]) -> String {
+        let groups: Vec<(String, String)> = groups
+            .iter()
+            .map(|dn| {
+                let dn = dn.to_lowercase();
+                let cn = dn
+                    .split(',')
+                    .next()
+                    .and_then(|rdn| rdn.trim().strip_prefix("cn="))
+                    .unwrap_or_default()
+                    .to_string();
+                (dn, cn)
+            })
+            .collect();
+
+        self.role_mapping
+            .iter()
+            .find(|(group, _)| groups.iter().any(|(dn, cn)| dn == group || cn == group))
+            .map(|(_, role)| role.clone())
+            .unwrap_or_else(|| self.default_role.clone())
+    }
+
+    // Finds the local account linked to this directory login, else creates one.
+    // Name and email always follow the directory. An unlinked account is never
+    // taken over by email: an admin has to link it (ldap_uid on PATCH /api/users/:id),
+    // otherwise anyone controlling a directory entry could claim a local admin.
+    // `None` when the email belongs to such an unlinked account.
+    async fn sync_user(&self, db: &MySqlPool, entry: DirectoryUser) -> Result<Option<User>> {
+        let existing: Option<User> = query_as("SELECT * FROM users WHERE ldap_uid = ?")
+            .bind(&entry.uid)
+            .fetch_optional(db)
+            .await?;
+
+        if existing.is_none() {
+            let unlinked: Option<String> = query_scalar("SELECT id FROM users WHERE email = ?")
+                .bind(&entry.email)
+                .fetch_optional(db)
+                .await?;
+            if let Some(user_id) = unlinked {
+                tracing::warn!(
+                    "LDAP login {} has the email of account {}, which isn't linked to it; an admin has to link them",
+                    entry.uid, user_id
+                );
+                return Ok(None);
+            }
+        }
+
+        let user_id = match existing {
+            Some(user) => {
+                let role = if self.sync_roles { entry.role } else { user.role };
+                query("UPDATE users SET name = ?, email = ?, role = ? WHERE id = ?")
+                    .bind(&entry.name)
+                    .bind(&entry.email)
+                    .bind(&role)
+                    .bind(&user.id)
+                    .execute(db)
+                    .await
+                    .context("Failed to sync LDAP user")?;
+                user.id
+            },
+            None => {
+                // Empty hash: the local password check never succeeds for this account
+                let user_id = Uuid::new_v4().to_string();
+                query("INSERT INTO users (id, name, email, password_hash, role, ldap_uid) VALUES (?, ?, ?, '', ?, ?)")
+                    .bind(&user_id)
+                    .bind(&entry.name)
+                    .bind(&entry.email)
+                    .bind(&entry.role)
+                    .bind(&entry.uid)
+                    .execute(db)
+                    .await
+                    .context("Failed to create LDAP user")?;
+                user_id
+            },
+        };
+
+        let user: User = query_as("SELECT * FROM users WHERE id = ?")
+            .bind(&user_id)
+            .fetch_one(db)
+            .await?;
+        Ok(Some(user))
+    }
+}
+
+#[async_trait]
+impl Authenticator for LdapAuthenticator {
+    fn name(&self) -> &'static str {
+        "ldap"
+    }
+
+    async fn authenticate(&self, db: &MySqlPool, login: &str, password: &str) -> Result<Option<User>> {
+        // An empty password makes a simple bind anonymous, which "succeeds"
+        if password.is_empty() || login.trim().is_empty() {
+            return Ok(None);
+        }
+
+        match self.bind(login.trim(), password).await? {
+            Some(entry) => self.sync_user(db, entry).await,
+            None => Ok(None),
+        }
+    }
+}
+
+// Attribute names come back in the server's spelling, so look them up case-insensitively
+fn values(attrs: &HashMap<String, Vec<String>>, name: &str) -> Vec<String> {
+    attrs
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, values)| values.clone())
+        .unwrap_or_default()
+}
+
+fn first_value(attrs: &HashMap<String, Vec<String>>, name: &str) -> Option<String> {
+    values(attrs, name).into_iter().next().filter(|v| !v.is_empty())
+}
@@ -0,0 +1,118 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::{Result, Context};
+use sqlx::{MySql, QueryBuilder};
+
+use crate::handlers::tus::tail_key;
+use crate::models::{File, Folder};
+use crate::state::AppState;
+
+// Permanent deletion. Objects are removed from the bucket BEFORE the metadata,
+// so a failed S3 call leaves the rows in place and the delete can simply be retried.
+
+/// Permanently deletes a folder, every descendant folder and all their files.
+pub async fn purge_folder_tree(state: &AppState, folder: &Folder) -> Result<()> {
+    let folders: Vec<Folder> = sqlx::query_as(
+        "WITH RECURSIVE tree AS (
+            SELECT id FROM folders WHERE id = ?
+            UNION ALL
+            SELECT f.id FROM folders f JOIN tree t ON f.parent_id = t.id
+        )
+        SELECT folders.* FROM folders JOIN tree ON folders.id = tree.id",
+    )
+    .bind(&folder.id)
+    .fetch_all(&state.db)
+    .await
+    .context("Failed to collect descendant folders")?;
+
+    let files: Vec<File> = sqlx::query_as(
+        "WITH RECURSIVE tree AS (
+            SELECT id FROM folders WHERE id = ?
+            UNION ALL
+            SELECT f.id FROM folders f JOIN tree t ON f.parent_id = t.id
+        )
+        SELECT files.* FROM files JOIN tree ON files.folder_id = tree.id",
+    )
+    .bind(&folder.id)
+    .fetch_all(&state.db)
+    .await
+    .context("Failed to collect descendant files")?;
+
+    remove_objects(state, &files).await?;
+
+    // ON DELETE CASCADE takes care of subfolders, files and permissions
+    sqlx::query("DELETE FROM folders WHERE id = ?")
+        .bind(&folder.id)
+        .execute(&state.db)
+        .await
+        .context("Failed to delete folder")?;
+
+    release_usage(state, &files).await;
+
+    let folder_ids: Vec<String> = folders.into_iter().map(|f| f.id).collect();
+    let file_ids: Vec<String> = files.into_iter().map(|f| f.id).collect();
+    let _ = state.cache.purge_cached_entries(&folder_ids, &file_ids).await;
+    let parent = folder.parent_id.as_deref().unwrap_or("root");
+    let _ = state.cache.invalidate_folder_listing(parent).await;
+
+    Ok(())
+}
+
+/// Permanently deletes individual files.
+pub async fn purge_files(state: &AppState, files: &[File]) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    remove_objects(state, files).await?;
+
+    let mut builder: QueryBuilder<MySql> = QueryBuilder::new("DELETE FROM files WHERE id IN (");
+    let mut ids = builder.separated(", ");
+    for file in files {
+        ids.push_bind(&file.id);
+    }
+    ids.push_unseparated(")");
+    builder
+        .build()
+        .execute(&state.db)
+        .await
+        .context("Failed to delete file metadata")?;
+
+    release_usage(state, files).await;
+
+    let file_ids: Vec<String> = files.iter().map(|f| f.id.clone()).collect();
+    let _ = state.cache.purge_cached_entries(&[], &file_ids).await;
+    let parents: HashSet<&str> = files.iter().map(|f| f.folder_id.as_deref().unwrap_or("root")).collect();
+    for parent in parents {
+        let _ = state.cache.invalidate_folder_listing(parent).await;
+    }
+
+    Ok(())
+}
+
+async fn remove_objects(state: &AppState, files: &[File]) -> Result<()> {
+    let mut keys = Vec::with_capacity(files.len());
+
+    for file in files {
+        keys.push(file.storage_key.clone());
+
+        // Unfinished uploads also hold parts (and possibly a tus tail) in storage
+        if let Some(upload_id) = &file.upload_id {
+            let _ = state.storage.abort_multipart(&file.storage_key, upload_id).await;
+            keys.push(tail_key(&file.storage_key));
+        }
+    }
+
+    state.storage.delete_many(&keys).await
+}
+
+// Only committed files were ever counted towards usage
+async fn release_usage(state: &AppState, files: &[File]) {
+    let mut per_owner: HashMap<&str, i64> = HashMap::new();
+    for file in files.iter().filter(|f| f.status == "committed") {
+        *per_owner.entry(file.owner_id.as_str()).or_default() += file.size;
+    }
+
+    for (owner, size) in per_owner {
+        let _ = state.cache.decrement_usage(owner, size).await;
+    }
+}
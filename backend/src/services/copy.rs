@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use anyhow::{Result, Context};
+use sqlx::query;
+use uuid::Uuid;
+
+use crate::models::{File, Folder};
+use crate::state::AppState;
+use crate::handlers::folder::can_view_folder;
+use crate::services::auth::Claims;
+
+/// Server-side copy of a committed file into `dest_folder_id` (None = root), owned and
+/// charged to `user_id`. Returns the new row.
+pub async fn copy_file(
+    state: &AppState,
+    file: &File,
+    dest_folder_id: Option<&str>,
+    name: &str,
+    user_id: &str,
+) -> Result<File> {
+    let file_id = Uuid::new_v4().to_string();
+    let storage_key = format!("{}/{}", dest_folder_id.unwrap_or("root"), file_id);
+    state.storage.copy(&file.storage_key, &storage_key, file.size).await?;
+
+    let result = query("INSERT INTO files (id, name, folder_id, owner_id, storage_key, size, mime_type, is_public, status) VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'committed')")
+        .bind(&file_id)
+        .bind(name)
+        .bind(dest_folder_id)
+        .bind(user_id)
+        .bind(&storage_key)
+        .bind(file.size)
+        .bind(&file.mime_type)
+        .bind(false)
+        .execute(&state.db)
+        .await;
+
+    if let Err(e) = result {
+        let _ = state.storage.delete(&storage_key).await;
+        return Err(e).context("Failed to insert copied file");
+    }
+
+    let _ = state.cache.increment_usage(user_id, file.size).await;
+
+    Ok(File {
+        id: file_id,
+        name: name.to_string(),
+        folder_id: dest_folder_id.map(|s| s.to_string()),
+        owner_id: user_id.to_string(),
+        storage_key,
+        size: file.size,
+        mime_type: file.mime_type.clone(),
+        is_public: false,
+        status: "committed".to_string(),
+        upload_id: None,
+        created_at: Some(chrono::Utc::now().naive_utc()),
+        deleted_at: None,
+        deleted_by: None,
+    })
+}
+
+/// Background body of a folder copy job. Progress and the outcome are written to `copy_jobs`.
+pub async fn run_folder_copy(
+    state: AppState,
+    job_id: String,
+    source: Folder,
+    dest_folder_id: Option<String>,
+    name: String,
+    user: Claims,
+) {
+    let _ = query("UPDATE copy_jobs SET status = 'running' WHERE id = ?")
+        .bind(&job_id)
+        .execute(&state.db)
+        .await;
+
+    let mut copied = Copied::default();
+    let outcome = copy_tree(&state, &job_id, &source, dest_folder_id.as_deref(), &name, &user, &mut copied).await;
+
+    let result = match &outcome {
+        Ok(_) => query("UPDATE copy_jobs SET status = 'completed', finished_at = NOW() WHERE id = ?")
+            .bind(&job_id)
+            .execute(&state.db)
+            .await,
+        Err(e) => {
+            tracing::warn!("Copy job {} failed: {:?}", job_id, e);
+            // A half-finished tree is worse than none; if it can't be removed, say so
+            let error = match discard_partial(&state, &job_id, &user.sub, &copied).await {
+                Ok(()) => format!("{}; the partial copy was removed", e),
+                Err(cleanup) => {
+                    tracing::error!("Failed to remove partial copy of job {}: {:?}", job_id, cleanup);
+                    format!("{}; a partial copy was left in the result folder", e)
+                },
+            };
+            query("UPDATE copy_jobs SET status = 'failed', error = ?, finished_at = NOW() WHERE id = ?")
+                .bind(error)
+                .bind(&job_id)
+                .execute(&state.db)
+                .await
+        }
+    };
+
+    let _ = state.cache.invalidate_folder_listing(dest_folder_id.as_deref().unwrap_or("root")).await;
+
+    if let Err(e) = result {
+        tracing::warn!("Failed to record outcome of copy job {}: {:?}", job_id, e);
+    }
+}
+
+/// Jobs run in-process, so anything still queued/running at startup was cut off by a restart.
+pub async fn fail_interrupted_jobs(state: &AppState) -> Result<()> {
+    query("UPDATE copy_jobs SET status = 'failed', error = 'Interrupted by server restart', finished_at = NOW() WHERE status IN ('queued', 'running')")
+        .execute(&state.db)
+        .await?;
+    Ok(())
+}
+
+/// Total size of the committed files a folder copy would duplicate, for the quota check.
+pub async fn tree_size(state: &AppState, folder_id: &str) -> Result<i64> {
+    let size: i64 = sqlx::query_scalar(
+        "WITH RECURSIVE tree AS (
+            SELECT id FROM folders WHERE id = ?
+            UNION ALL
+            SELECT f.id FROM folders f JOIN tree t ON f.parent_id = t.id WHERE f.deleted_at IS NULL
+        )
+        SELECT CAST(COALESCE(SUM(files.size), 0) AS SIGNED) FROM files JOIN tree ON files.folder_id = tree.id
+        WHERE files.status = 'committed' AND files.deleted_at IS NULL",
+    )
+    .bind(folder_id)
+    .fetch_one(&state.db)
+    .await?;
+    Ok(size)
+}
+
+// What a job has created so far, so a failure can undo it
+#[derive(Default)]
+struct Copied {
+    root_id: Option<String>,
+    files: Vec<File>,
+}
+
+async fn copy_tree(
+    state: &AppState,
+    job_id: &str,
+    source: &Folder,
+    dest_folder_id: Option<&str>,
+    name: &str,
+    user: &Claims,
+    copied: &mut Copied,
+) -> Result<()> {
+    // Snapshot the tree before creating anything, so copying into itself can't recurse
+    let folders: Vec<Folder> = sqlx::query_as(
+        "WITH RECURSIVE tree AS (
+            SELECT id FROM folders WHERE id = ?
+            UNION ALL
+            SELECT f.id FROM folders f JOIN tree t ON f.parent_id = t.id WHERE f.deleted_at IS NULL
+        )
+        SELECT folders.* FROM folders JOIN tree ON folders.id = tree.id",
+    )
+    .bind(&source.id)
+    .fetch_all(&state.db)
+    .await
+    .context("Failed to collect folders to copy")?;
+
+    let files: Vec<File> = sqlx::query_as(
+        "WITH RECURSIVE tree AS (
+            SELECT id FROM folders WHERE id = ?
+            UNION ALL
+            SELECT f.id FROM folders f JOIN tree t ON f.parent_id = t.id WHERE f.deleted_at IS NULL
+        )
+        SELECT files.* FROM files JOIN tree ON files.folder_id = tree.id
+        WHERE files.status = 'committed' AND files.deleted_at IS NULL",
+    )
+    .bind(&source.id)
+    .fetch_all(&state.db)
+    .await
+    .context("Failed to collect files to copy")?;
+
+    // Recreate the folder structure top-down: old id -> new id
+    let mut children: HashMap<&str, Vec<&Folder>> = HashMap::new();
+    for folder in &folders {
+        if let Some(parent) = folder.parent_id.as_deref() {
+            children.entry(parent).or_default().push(folder);
+        }
+    }
+
+    let root_id = Uuid::new_v4().to_string();
+    insert_folder(state, &root_id, name, dest_folder_id, &user.sub).await?;
+    copied.root_id = Some(root_id.clone());
+    query("UPDATE copy_jobs SET result_folder_id = ? WHERE id = ?")
+        .bind(&root_id)
+        .bind(job_id)
+        .execute(&state.db)
+        .await?;
+
+    let mut mapping: HashMap<String, String> = HashMap::new();
+    mapping.insert(source.id.clone(), root_id);
+
+    let mut queue = vec![source.id.as_str()];
+    while let Some(old_parent) = queue.pop() {
+        let new_parent = mapping[old_parent].clone();
+        for child in children.get(old_parent).map(|c| c.as_slice()).unwrap_or_default() {
+            // A subfolder the caller can't see (say a 'none' override) stays behind with everything below it
+            if !can_view_folder(state, child, Some(user)).await {
+                continue;
+            }
+            let new_id = Uuid::new_v4().to_string();
+            insert_folder(state, &new_id, &child.name, Some(&new_parent), &user.sub).await?;
+            mapping.insert(child.id.clone(), new_id);
+            queue.push(&child.id);
+        }
+    }
+
+    let files: Vec<(&File, &String)> = files
+        .iter()
+        .filter_map(|file| Some((file, mapping.get(file.folder_id.as_deref()?)?)))
+        .collect();
+
+    query("UPDATE copy_jobs SET total_files = ? WHERE id = ?")
+        .bind(files.len() as i32)
+        .bind(job_id)
+        .execute(&state.db)
+        .await?;
+
+    for (file, new_folder) in files {
+        let copy = copy_file(state, file, Some(new_folder), &file.name, &user.sub).await?;
+        copied.files.push(copy);
+
+        query("UPDATE copy_jobs SET copied_files = copied_files + 1, copied_bytes = copied_bytes + ? WHERE id = ?")
+            .bind(file.size)
+            .bind(job_id)
+            .execute(&state.db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// Removing the top folder cascades to the folders and file rows below it
+async fn discard_partial(state: &AppState, job_id: &str, user_id: &str, copied: &Copied) -> Result<()> {
+    for file in &copied.files {
+        if let Err(e) = state.storage.delete(&file.storage_key).await {
+            tracing::warn!("Failed to delete copied object {}: {:?}", file.storage_key, e);
+        }
+    }
+    let bytes: i64 = copied.files.iter().map(|f| f.size).sum();
+    let _ = state.cache.decrement_usage(user_id, bytes).await;
+
+    if let Some(root_id) = &copied.root_id {
+        query("DELETE FROM folders WHERE id = ?")
+            .bind(root_id)
+            .execute(&state.db)
+            .await?;
+    }
+
+    query("UPDATE copy_jobs SET result_folder_id = NULL WHERE id = ?")
+        .bind(job_id)
+        .execute(&state.db)
+        .await?;
+    Ok(())
+}
+
+async fn insert_folder(state: &AppState, id: &str, name: &str, parent_id: Option<&str>, owner_id: &str) -> Result<()> {
+    query("INSERT INTO folders (id, name, parent_id, owner_id, is_public) VALUES (?, ?, ?, ?, ?)")
+        .bind(id)
+        .bind(name)
+        .bind(parent_id)
+        .bind(owner_id)
+        .bind(false)
+        .execute(&state.db)
+        .await
+        .context("Failed to create folder copy")?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Where clients fetch file contents from. With DOWNLOAD_MODE=presigned that is a
+//! presigned storage URL; with DOWNLOAD_MODE=proxy it is /api/files/:id/content, signed
+//! like the local backend's URLs so a browser can open it (or seek in a `<video>`)
+//! without the bearer token, and the storage host never has to be public.
+
+use std::time::Duration;
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::config::Config;
+use crate::models::File;
+use crate::services::storage::ResponseOverrides;
+use crate::state::AppState;
+
+/// Whether a browser should show the file or save it.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    Inline,
+    Attachment,
+}
+
+impl Disposition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Disposition::Inline => "inline",
+            Disposition::Attachment => "attachment",
+        }
+    }
+}
+
+/// Content-Disposition carrying the original file name: an ASCII `filename` for old
+/// clients plus the exact name RFC 5987-encoded in `filename*` (RFC 6266).
+pub fn content_disposition(disposition: Disposition, name: &str) -> String {
+    let fallback: String = name
+        .chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' { c } else { '_' })
+        .collect();
+
+    format!("{}; filename=\"{}\"; filename*=UTF-8''{}", disposition.as_str(), fallback, urlencoding::encode(name))
+}
+
+pub fn is_proxy(config: &Config) -> bool {
+    config.download_mode == "proxy"
+}
+
+/// URL handed out by download endpoints, answering with the file's name and type
+/// rather than its storage key. `presign_ttl` only applies to presigned URLs; proxy
+/// links last DOWNLOAD_LINK_TTL so playback outlives a short presign window.
+pub async fn download_url(state: &AppState, file: &File, disposition: Disposition, presign_ttl: Duration) -> Result<String> {
+    if is_proxy(&state.config) {
+        return Ok(content_url(&state.config, &file.id, disposition));
+    }
+
+    let overrides = ResponseOverrides {
+        content_type: file.mime_type.clone().filter(|m| !m.is_empty()),
+        content_disposition: Some(content_disposition(disposition, &file.name)),
+    };
+    state.storage.presign_get(&file.storage_key, &overrides, presign_ttl).await
+}
+
+// Only the file id is signed; the disposition is the holder's choice
+pub fn content_url(config: &Config, file_id: &str, disposition: Disposition) -> String {
+    let expires = chrono::Utc::now().timestamp() + config.download_link_ttl;
+    let signature = hex::encode(mac(config, file_id, expires).finalize().into_bytes());
+
+    format!(
+        "{}/api/files/{}/content?expires={}&signature={}&disposition={}",
+        config.storage_public_url.trim_end_matches('/'),
+        urlencoding::encode(file_id),
+        expires,
+        signature,
+        disposition.as_str(),
+    )
+}
+
+/// Checks the query of a link made by `content_url`.
+pub fn verify(config: &Config, file_id: &str, expires: i64, signature: &str) -> bool {
+    if config.storage_signing_secret.is_empty() || expires < chrono::Utc::now().timestamp() {
+        return false;
+    }
+    let signature = match hex::decode(signature) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    mac(config, file_id, expires).verify_slice(&signature).is_ok()
+}
+
+// Same message layout as LocalStorage; its resources start with objects/ or uploads/,
+// so a signature for one can never be replayed against the other
+fn mac(config: &Config, file_id: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(config.storage_signing_secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("GET\nfiles/{}/content\n\n{}", file_id, expires).as_bytes());
+    mac
+}
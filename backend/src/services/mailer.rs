@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use anyhow::{Result, Context, bail};
+use async_trait::async_trait;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use uuid::Uuid;
+
+use crate::config::Config;
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String, // Plain text
+}
+
+/// Outgoing mail. Selected by the MAILER setting; handlers only see `state.mailer`.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<()>;
+}
+
+pub fn from_config(config: &Config) -> Result<Arc<dyn Mailer>> {
+    let from: Mailbox = config.mail_from.parse().context("Invalid MAIL_FROM")?;
+
+    match config.mailer.as_str() {
+        "smtp" => Ok(Arc::new(SmtpMailer::new(config, from)?)),
+        "log" => Ok(Arc::new(LogMailer { from, outbox: config.mail_outbox_dir.as_ref().map(PathBuf::from) })),
+        other => bail!("Unknown MAILER '{}' (expected 'smtp' or 'log')", other),
+    }
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    fn new(config: &Config, from: Mailbox) -> Result<Self> {
+        let host = config.smtp_host.as_str();
+        let mut builder = match config.smtp_tls.as_str() {
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            other => bail!("Unknown SMTP_TLS '{}' (expected 'starttls', 'tls' or 'none')", other),
+        }
+        .port(config.smtp_port);
+
+        if let (Some(user), Some(pass)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(user.clone(), pass.clone()));
+        }
+
+        Ok(Self { transport: builder.build(), from })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<()> {
+        let message = build_message(&self.from, email)?;
+        self.transport.send(message).await.context("SMTP send failed")?;
+        Ok(())
+    }
+}
+
+/// For local development: writes each message as an .eml file to MAIL_OUTBOX_DIR,
+/// or just logs it when no directory is configured.
+pub struct LogMailer {
+    from: Mailbox,
+    outbox: Option<PathBuf>,
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: &Email) -> Result<()> {
+        let message = build_message(&self.from, email)?;
+
+        match &self.outbox {
+            Some(dir) => {
+                tokio::fs::create_dir_all(dir).await.context("Failed to create mail outbox")?;
+                let path = dir.join(format!("{}-{}.eml", chrono::Utc::now().format("%Y%m%dT%H%M%S"), Uuid::new_v4()));
+                tokio::fs::write(&path, message.formatted()).await.context("Failed to write mail to outbox")?;
+                tracing::info!("Mail to {} written to {}", email.to, path.display());
+            },
+            None => tracing::info!("Mail to {} ({}):\n{}", email.to, email.subject, email.body),
+        }
+
+        Ok(())
+    }
+}
+
+fn build_message(from: &Mailbox, email: &Email) -> Result<Message> {
+    Message::builder()
+        .from(from.clone())
+        .to(email.to.parse().context("Invalid recipient address")?)
+        .subject(&email.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(email.body.clone())
+        .context("Failed to build message")
+}
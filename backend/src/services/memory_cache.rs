@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::models::{File, Folder};
+use crate::services::redis_cache::{Cache, CachedPermission, MAX_LOGIN_BACKOFF};
+
+/// `Cache` without Redis, for tests: a HashMap with the same keys, TTLs and
+/// counters `RedisCache` uses, so the handlers see the same behaviour.
+#[derive(Default)]
+pub struct MemoryCache {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+struct Entry {
+    value: Value,
+    expires_at: Option<Instant>,
+}
+
+#[derive(Clone)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Sorted(HashMap<String, i64>), // Like a ZSET: member -> score
+    File(Box<File>),
+    Folder(Box<Folder>),
+}
+
+// The subset of Redis commands the cache operations need
+struct Store<'a>(std::sync::MutexGuard<'a, HashMap<String, Entry>>);
+
+impl Store<'_> {
+    fn get(&mut self, key: &str) -> Option<Value> {
+        let expired = self.0.get(key)?.expires_at.is_some_and(|at| at <= Instant::now());
+        if expired {
+            self.0.remove(key);
+            return None;
+        }
+        self.0.get(key).map(|e| e.value.clone())
+    }
+
+    fn exists(&mut self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    fn set(&mut self, key: String, value: Value, ttl: Option<u64>) {
+        let expires_at = ttl.map(|secs| Instant::now() + Duration::from_secs(secs));
+        self.0.insert(key, Entry { value, expires_at });
+    }
+
+    fn del(&mut self, key: &str) {
+        self.0.remove(key);
+    }
+
+    // Keeps the existing expiry, as INCR does
+    fn incr(&mut self, key: &str, by: i64) -> i64 {
+        let current = match self.get(key) {
+            Some(Value::Int(n)) => n,
+            Some(Value::Str(s)) => s.parse().unwrap_or(0),
+            _ => 0,
+        };
+        let expires_at = self.0.get(key).and_then(|e| e.expires_at);
+        self.0.insert(key.to_string(), Entry { value: Value::Int(current + by), expires_at });
+        current + by
+    }
+
+    fn expire(&mut self, key: &str, secs: i64) {
+        if self.exists(key) {
+            if let Some(entry) = self.0.get_mut(key) {
+                entry.expires_at = Some(Instant::now() + Duration::from_secs(secs.max(0) as u64));
+            }
+        }
+    }
+
+    // Seconds left, 0 for missing keys or keys without expiry
+    fn ttl(&mut self, key: &str) -> i64 {
+        if !self.exists(key) {
+            return 0;
+        }
+        self.0.get(key)
+            .and_then(|e| e.expires_at)
+            .map(|at| at.saturating_duration_since(Instant::now()).as_secs_f64().ceil() as i64)
+            .unwrap_or(0)
+    }
+
+    fn zadd(&mut self, key: &str, member: &str, score: i64) {
+        let mut members = match self.get(key) {
+            Some(Value::Sorted(m)) => m,
+            _ => HashMap::new(),
+        };
+        members.insert(member.to_string(), score);
+        let expires_at = self.0.get(key).and_then(|e| e.expires_at);
+        self.0.insert(key.to_string(), Entry { value: Value::Sorted(members), expires_at });
+    }
+
+    // Members by score, then name, as ZRANGE returns them
+    fn zrange(&mut self, key: &str) -> Vec<String> {
+        let mut members: Vec<(i64, String)> = match self.get(key) {
+            Some(Value::Sorted(m)) => m.into_iter().map(|(member, score)| (score, member)).collect(),
+            _ => Vec::new(),
+        };
+        members.sort();
+        members.into_iter().map(|(_, member)| member).collect()
+    }
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn store(&self) -> Store<'_> {
+        Store(self.entries.lock().unwrap())
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn get_cached_folder_files(&self, folder_id: &str) -> Result<Option<Vec<File>>> {
+        let mut store = self.store();
+        if store.exists(&format!("folder:{}:empty", folder_id)) {
+            return Ok(Some(Vec::new()));
+        }
+
+        let ids = store.zrange(&format!("folder:{}:children", folder_id));
+        if ids.is_empty() {
+            return Ok(None);
+        }
+
+        // Same fields RedisCache keeps in its meta hashes
+        let files = ids
+            .iter()
+            .filter_map(|id| match store.get(&format!("file:{}:meta", id)) {
+                Some(Value::File(file)) => Some(File {
+                    folder_id: if folder_id == "root" { None } else { Some(folder_id.to_string()) },
+                    upload_id: None,
+                    created_at: None,
+                    deleted_at: None,
+                    deleted_by: None,
+                    ..*file
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Ok(Some(files))
+    }
+
+    async fn cache_folder_files(&self, folder_id: &str, files: &[File]) -> Result<()> {
+        let mut store = self.store();
+        let files: Vec<&File> = files.iter().filter(|f| f.status == "committed").collect();
+
+        if files.is_empty() {
+            store.set(format!("folder:{}:empty", folder_id), Value::Str("1".to_string()), Some(3600));
+            return Ok(());
+        }
+
+        let zkey = format!("folder:{}:children", folder_id);
+        for file in files {
+            let score = file.created_at.map(|t| t.and_utc().timestamp()).unwrap_or(0);
+            store.zadd(&zkey, &file.id, score);
+            store.set(format!("file:{}:meta", file.id), Value::File(Box::new(file.clone())), Some(3600));
+        }
+        store.expire(&zkey, 3600);
+        Ok(())
+    }
+
+    async fn get_cached_subfolders(&self, folder_id: &str) -> Result<Option<Vec<Folder>>> {
+        let mut store = self.store();
+        if store.exists(&format!("folder:{}:subfolders:empty", folder_id)) {
+            return Ok(Some(Vec::new()));
+        }
+
+        let ids = store.zrange(&format!("folder:{}:subfolders", folder_id));
+        if ids.is_empty() {
+            return Ok(None);
+        }
+
+        let folders = ids
+            .iter()
+            .filter_map(|id| match store.get(&format!("folder:{}:meta", id)) {
+                Some(Value::Folder(folder)) => Some(Folder {
+                    parent_id: Some(folder_id.to_string()),
+                    created_at: None,
+                    deleted_at: None,
+                    deleted_by: None,
+                    ..*folder
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Ok(Some(folders))
+    }
+
+    async fn cache_subfolders(&self, parent_id: &str, folders: &[Folder]) -> Result<()> {
+        let mut store = self.store();
+        if folders.is_empty() {
+            store.set(format!("folder:{}:subfolders:empty", parent_id), Value::Str("1".to_string()), Some(3600));
+            return Ok(());
+        }
+
+        let zkey = format!("folder:{}:subfolders", parent_id);
+        for folder in folders {
+            let score = folder.created_at.map(|t| t.and_utc().timestamp()).unwrap_or(0);
+            store.zadd(&zkey, &folder.id, score);
+            store.set(format!("folder:{}:meta", folder.id), Value::Folder(Box::new(folder.clone())), Some(3600));
+        }
+        store.expire(&zkey, 3600);
+        Ok(())
+    }
+
+    async fn invalidate_folder_listing(&self, folder_id: &str) -> Result<()> {
+        let mut store = self.store();
+        for suffix in ["children", "empty", "subfolders", "subfolders:empty"] {
+            store.del(&format!("folder:{}:{}", folder_id, suffix));
+        }
+        Ok(())
+    }
+
+    async fn invalidate_folder_meta(&self, folder_id: &str) -> Result<()> {
+        self.store().del(&format!("folder:{}:meta", folder_id));
+        Ok(())
+    }
+
+    async fn invalidate_file_meta(&self, file_id: &str) -> Result<()> {
+        self.store().del(&format!("file:{}:meta", file_id));
+        Ok(())
+    }
+
+    async fn purge_cached_entries(&self, folder_ids: &[String], file_ids: &[String]) -> Result<()> {
+        let mut store = self.store();
+        for id in folder_ids {
+            for suffix in ["meta", "children", "empty", "subfolders", "subfolders:empty"] {
+                store.del(&format!("folder:{}:{}", id, suffix));
+            }
+        }
+        for id in file_ids {
+            store.del(&format!("file:{}:meta", id));
+        }
+        Ok(())
+    }
+
+    async fn check_permission(&self, folder_id: &str, user_id: &str) -> Result<CachedPermission> {
+        let mut store = self.store();
+        let mut epoch_of = |key: &str| match store.get(key) {
+            Some(Value::Int(n)) => n,
+            _ => 0,
+        };
+        let epoch = format!("{}.{}", epoch_of("acl:epoch"), epoch_of(&format!("acl:epoch:{}", user_id)));
+
+        let role = match store.get(&format!("perm:{}:{}", folder_id, user_id)) {
+            Some(Value::Str(v)) => v.split_once(':').and_then(|(stamp, role)| {
+                (stamp == epoch).then(|| role.to_string())
+            }),
+            _ => None,
+        };
+
+        Ok(CachedPermission { role, epoch })
+    }
+
+    async fn cache_permission(&self, folder_id: &str, user_id: &str, role: &str, epoch: &str) -> Result<()> {
+        let value = Value::Str(format!("{}:{}", epoch, role));
+        self.store().set(format!("perm:{}:{}", folder_id, user_id), value, Some(900));
+        Ok(())
+    }
+
+    async fn bump_acl_epoch(&self) -> Result<()> {
+        self.store().incr("acl:epoch", 1);
+        Ok(())
+    }
+
+    async fn bump_user_acl_epoch(&self, user_id: &str) -> Result<()> {
+        self.store().incr(&format!("acl:epoch:{}", user_id), 1);
+        Ok(())
+    }
+
+    async fn increment_usage(&self, user_id: &str, size: i64) -> Result<()> {
+        self.store().incr(&format!("user:{}:usage", user_id), size);
+        Ok(())
+    }
+
+    async fn decrement_usage(&self, user_id: &str, size: i64) -> Result<()> {
+        self.store().incr(&format!("user:{}:usage", user_id), -size);
+        Ok(())
+    }
+
+    async fn check_rate_limit(&self, user_id: &str, action: &str, limit: i64, window: i64) -> Result<bool> {
+        let mut store = self.store();
+        let key = format!("ratelimit:{}:{}", user_id, action);
+        let count = store.incr(&key, 1);
+        if count == 1 {
+            store.expire(&key, window);
+        }
+        Ok(count <= limit)
+    }
+
+    async fn revoke_jti(&self, jti: &str, ttl: u64) -> Result<()> {
+        self.store().set(format!("revoked:{}", jti), Value::Str("1".to_string()), Some(ttl.max(1)));
+        Ok(())
+    }
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool> {
+        Ok(self.store().exists(&format!("revoked:{}", jti)))
+    }
+
+    async fn store_mfa_challenge(&self, token_hash: &str, value: &str, ttl: u64) -> Result<()> {
+        self.store().set(format!("mfa:{}", token_hash), Value::Str(value.to_string()), Some(ttl));
+        Ok(())
+    }
+
+    async fn get_mfa_challenge(&self, token_hash: &str) -> Result<Option<String>> {
+        match self.store().get(&format!("mfa:{}", token_hash)) {
+            Some(Value::Str(v)) => Ok(Some(v)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn delete_mfa_challenge(&self, token_hash: &str) -> Result<()> {
+        self.store().del(&format!("mfa:{}", token_hash));
+        Ok(())
+    }
+
+    async fn store_oidc_login(&self, state: &str, value: &str, ttl: u64) -> Result<()> {
+        self.store().set(format!("oidc:{}", state), Value::Str(value.to_string()), Some(ttl));
+        Ok(())
+    }
+
+    async fn take_oidc_login(&self, state: &str) -> Result<Option<String>> {
+        let mut store = self.store();
+        let key = format!("oidc:{}", state);
+        let value = store.get(&key);
+        store.del(&key);
+        match value {
+            Some(Value::Str(v)) => Ok(Some(v)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn login_retry_after(&self, subjects: &[&str]) -> Result<Option<i64>> {
+        let mut store = self.store();
+        let wait = subjects
+            .iter()
+            .map(|subject| store.ttl(&format!("login:{}:blocked", subject)))
+            .max()
+            .unwrap_or(0);
+        Ok(if wait > 0 { Some(wait) } else { None })
+    }
+
+    async fn record_login_failure(&self, subject: &str, backoff_after: i64, lockout_after: i64, lockout_secs: i64) -> Result<bool> {
+        let mut store = self.store();
+        let failures_key = format!("login:{}:failures", subject);
+        let blocked_key = format!("login:{}:blocked", subject);
+
+        let count = store.incr(&failures_key, 1);
+        store.expire(&failures_key, lockout_secs);
+
+        if lockout_after > 0 && count >= lockout_after {
+            store.set(blocked_key, Value::Str("locked".to_string()), Some(lockout_secs as u64));
+            store.del(&failures_key);
+            return Ok(true);
+        }
+
+        if count >= backoff_after {
+            let delay = (1i64 << (count - backoff_after).min(6)).min(MAX_LOGIN_BACKOFF);
+            store.set(blocked_key, Value::Str("backoff".to_string()), Some(delay as u64));
+        }
+
+        Ok(false)
+    }
+
+    async fn clear_login_failures(&self, subject: &str) -> Result<()> {
+        let mut store = self.store();
+        store.del(&format!("login:{}:failures", subject));
+        store.del(&format!("login:{}:blocked", subject));
+        Ok(())
+    }
+}
@@ -1,3 +1,16 @@
-pub mod minio;
+pub mod storage;
 pub mod redis_cache;
+pub mod memory_cache;
 pub mod auth;
+pub mod cleanup;
+pub mod trash;
+pub mod copy;
+pub mod quota;
+pub mod users;
+pub mod session;
+pub mod mailer;
+pub mod totp;
+pub mod oidc;
+pub mod authenticator;
+pub mod audit;
+pub mod download;
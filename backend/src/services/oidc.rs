@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+// OpenID Connect authorization code flow with PKCE (RFC 7636). Discovery and the
+// provider's signing keys are fetched per login, so key rotation needs no restart.
+
+fn http() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to build HTTP client")
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+// What the login request stored under its `state` parameter until the callback
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingLogin {
+    pub code_verifier: String,
+    pub nonce: String,
+    pub link_user_id: Option<String>, // Set when a signed-in user links their account
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: Option<bool>,
+    pub name: Option<String>,
+    pub nonce: Option<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl IdTokenClaims {
+    /// Group names from the configured claim (array of strings, or a single string).
+    pub fn groups(&self, claim: &str) -> Vec<String> {
+        match self.extra.get(claim) {
+            Some(serde_json::Value::Array(items)) => items.iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+            Some(serde_json::Value::String(group)) => vec![group.clone()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+pub async fn discover(issuer: &str) -> Result<ProviderMetadata> {
+    let url = format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'));
+    let metadata: ProviderMetadata = http()
+        .get(&url)
+        .send()
+        .await
+        .context("OIDC discovery request failed")?
+        .error_for_status()
+        .context("OIDC discovery returned an error")?
+        .json()
+        .await
+        .context("Invalid OIDC discovery document")?;
+
+    if metadata.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+        bail!("Discovery document is for issuer {}, expected {}", metadata.issuer, issuer);
+    }
+
+    Ok(metadata)
+}
+
+/// S256 code challenge for a PKCE verifier.
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+pub fn authorization_url(metadata: &ProviderMetadata, config: &Config, state: &str, nonce: &str, code_challenge: &str) -> String {
+    let params = [
+        ("response_type", "code"),
+        ("client_id", config.oidc_client_id.as_str()),
+        ("redirect_uri", config.oidc_redirect_url.as_str()),
+        ("scope", config.oidc_scopes.as_str()),
+        ("state", state),
+        ("nonce", nonce),
+        ("code_challenge", code_challenge),
+        ("code_challenge_method", "S256"),
+    ];
+
+    let query: Vec<String> = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, urlencoding::encode(v)))
+        .collect();
+
+    let separator = if metadata.authorization_endpoint.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", metadata.authorization_endpoint, separator, query.join("&"))
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+/// Redeems the authorization code and returns the raw ID token.
+pub async fn exchange_code(metadata: &ProviderMetadata, config: &Config, code: &str, code_verifier: &str) -> Result<String> {
+    let form = [
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", config.oidc_redirect_url.as_str()),
+        ("client_id", config.oidc_client_id.as_str()),
+        ("client_secret", config.oidc_client_secret.as_str()),
+        ("code_verifier", code_verifier),
+    ];
+
+    let response: TokenResponse = http()
+        .post(&metadata.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .context("Token request failed")?
+        .error_for_status()
+        .context("Token endpoint rejected the code")?
+        .json()
+        .await
+        .context("Invalid token response")?;
+
+    response.id_token.ok_or_else(|| anyhow!("Token response has no id_token"))
+}
+
+/// Verifies the ID token signature against the provider's JWKS, plus issuer, audience,
+/// expiry and the nonce we sent.
+pub async fn validate_id_token(metadata: &ProviderMetadata, config: &Config, id_token: &str, nonce: &str) -> Result<IdTokenClaims> {
+    let header = decode_header(id_token).context("Malformed ID token")?;
+
+    // The client secret is the only shared key, never accept HMAC-signed ID tokens
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        bail!("Unsupported ID token algorithm {:?}", header.alg);
+    }
+
+    let jwks: JwkSet = http()
+        .get(&metadata.jwks_uri)
+        .send()
+        .await
+        .context("JWKS request failed")?
+        .error_for_status()
+        .context("JWKS endpoint returned an error")?
+        .json()
+        .await
+        .context("Invalid JWKS document")?;
+
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+    .ok_or_else(|| anyhow!("No matching signing key for ID token"))?;
+
+    let key = DecodingKey::from_jwk(jwk).context("Unusable signing key")?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[config.oidc_client_id.as_str()]);
+    validation.set_issuer(&[metadata.issuer.as_str()]);
+
+    let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+        .context("ID token validation failed")?
+        .claims;
+
+    if claims.nonce.as_deref() != Some(nonce) {
+        bail!("ID token nonce mismatch");
+    }
+
+    Ok(claims)
+}
+
+// A login's `state` is also tied to the browser that started it: an HttpOnly cookie holds
+// this hash, and the callback only accepts a state the browser presents the hash of.
+// Otherwise anyone could hand a victim the callback URL of their own login (login CSRF)
+// or of a link they started, getting the victim's identity linked to their account.
+pub const BINDING_COOKIE: &str = "ferrum_oidc";
+
+pub fn browser_binding(state: &str, link_user_id: Option<&str>) -> String {
+    hex::encode(Sha256::digest(format!("{}\n{}", state, link_user_id.unwrap_or_default())))
+}
+
+/// Set-Cookie value carrying `binding`; an empty binding with no lifetime clears it.
+pub fn binding_cookie(config: &Config, binding: &str, max_age: u64) -> String {
+    // Only scoped to our own routes; Lax still sends it on the provider's redirect back
+    let secure = if config.oidc_redirect_url.starts_with("https://") { "; Secure" } else { "" };
+    format!("{}={}; Path=/api/auth/oidc; Max-Age={}; HttpOnly; SameSite=Lax{}", BINDING_COOKIE, binding, max_age, secure)
+}
+
+/// The binding in a request's Cookie header, if any.
+pub fn presented_binding(cookie_header: &str) -> Option<&str> {
+    cookie_header
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == BINDING_COOKIE)
+        .map(|(_, value)| value)
+}
+
+/// Whether a new account may be provisioned for an identity. The provider has to vouch
+/// for the address, unless OIDC_REQUIRE_VERIFIED_EMAIL is off for providers that never
+/// send the claim; an explicit `false` is refused either way.
+pub fn may_provision(require_verified: bool, email_verified: Option<bool>) -> bool {
+    match email_verified {
+        Some(verified) => verified,
+        None => !require_verified,
+    }
+}
+
+pub fn domain_allowed(config: &Config, email: &str) -> bool {
+    if config.oidc_allowed_domains.is_empty() {
+        return true;
+    }
+    email
+        .rsplit_once('@')
+        .map(|(_, domain)| config.oidc_allowed_domains.contains(&domain.to_lowercase()))
+        .unwrap_or(false)
+}
+
+/// Role for the given groups: first matching mapping, otherwise the configured default.
+pub fn map_role(config: &Config, groups: &[String]) -> String {
+    config
+        .oidc_role_mapping
+        .iter()
+        .find(|(group, _)| groups.iter().any(|g| g == group))
+        .map(|(_, role)| role.clone())
+        .unwrap_or_else(|| config.oidc_default_role.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provisioning_needs_a_verified_email_by_default() {
+        assert!(may_provision(true, Some(true)));
+        assert!(!may_provision(true, None));
+        assert!(!may_provision(true, Some(false)));
+
+        assert!(may_provision(false, None));
+        assert!(!may_provision(false, Some(false)));
+    }
+
+    #[test]
+    fn binding_covers_the_link_initiator() {
+        let login = browser_binding("s-1", None);
+        assert_ne!(login, browser_binding("s-2", None));
+        assert_ne!(login, browser_binding("s-1", Some("u-1")));
+        assert_ne!(browser_binding("s-1", Some("u-1")), browser_binding("s-1", Some("u-2")));
+    }
+
+    #[test]
+    fn binding_is_read_from_the_cookie_header() {
+        assert_eq!(presented_binding("a=1; ferrum_oidc=abc; b=2"), Some("abc"));
+        assert_eq!(presented_binding("ferrum_oidc_old=abc"), None);
+        assert_eq!(presented_binding(""), None);
+    }
+}
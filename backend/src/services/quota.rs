@@ -0,0 +1,23 @@
+use anyhow::Result;
+use sqlx::query_scalar;
+
+use crate::state::AppState;
+
+// USER_QUOTA_BYTES caps what each user may store (0 = unlimited). Usage is summed
+// from the files table rather than the Redis counter, which a flush would reset.
+// Pending uploads count, as do trashed files until they are purged.
+
+/// Whether `user_id` can take on `additional` bytes without going over the quota.
+pub async fn has_room(state: &AppState, user_id: &str, additional: i64) -> Result<bool> {
+    let quota = state.config.user_quota_bytes;
+    if quota <= 0 {
+        return Ok(true);
+    }
+
+    let used: i64 = query_scalar("SELECT CAST(COALESCE(SUM(size), 0) AS SIGNED) FROM files WHERE owner_id = ? AND status IN ('pending', 'committed')")
+        .bind(user_id)
+        .fetch_one(&state.db)
+        .await?;
+
+    Ok(used.saturating_add(additional) <= quota)
+}
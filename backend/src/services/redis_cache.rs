@@ -1,263 +1,540 @@
+use async_trait::async_trait;
 use redis::{Client, AsyncCommands};
 
 use crate::models::{File, Folder};
 use anyhow::{Result, Context};
-use thiserror::Error;
-
-#[derive(Error, Debug)]
-pub enum CacheError {
-    #[error("Redis connection failed")]
-    ConnectionFailed,
-    #[error("Cache set failed")]
-    SetFailed,
-    #[error("Cache get failed")]
-    GetFailed,
-    #[error("Cache key not found")]
-    NotFound,
-}
 
 const PREFIX: &str = "ferrum"; // Updated to match design
 
 // Public High-level Methods
 
-// 1. Folder Listing specific (ZSET + HASH)
-// This returns a list of File objects if cache hit, None if miss.
-pub async fn get_cached_folder_files(client: &Client, folder_id: &str) -> Result<Option<Vec<File>>> {
-    let mut con = client.get_multiplexed_async_connection().await
-        .context("Failed to get Redis connection")?;
+/// Caching, revocation and throttling state shared across instances. Redis in
+/// production, `MemoryCache` in tests; handlers only see `state.cache`.
+#[async_trait]
+pub trait Cache: Send + Sync {
+    // 1. Folder Listings
+    // Committed files and subfolders of a folder. `None` is a miss, an empty Vec a
+    // folder known to be empty.
+
+    async fn get_cached_folder_files(&self, folder_id: &str) -> Result<Option<Vec<File>>>;
+
+    async fn cache_folder_files(&self, folder_id: &str, files: &[File]) -> Result<()>;
+
+    async fn get_cached_subfolders(&self, folder_id: &str) -> Result<Option<Vec<Folder>>>;
+
+    async fn cache_subfolders(&self, parent_id: &str, folders: &[Folder]) -> Result<()>;
+
+    async fn invalidate_folder_listing(&self, folder_id: &str) -> Result<()>;
+
+    /// Per-entry metadata is shared by every listing that contains the entry, so it
+    /// must be dropped on rename/move or it keeps serving the old values.
+    async fn invalidate_folder_meta(&self, folder_id: &str) -> Result<()>;
+
+    async fn invalidate_file_meta(&self, file_id: &str) -> Result<()>;
+
+    /// Drops the metadata of deleted entries, plus the listings of any deleted folders.
+    async fn purge_cached_entries(&self, folder_ids: &[String], file_ids: &[String]) -> Result<()>;
+
+    // 2. Permission Cache
+    // Values are the *effective* role (inherited from ancestors, "none" = no access),
+    // stamped with the epochs they were computed under: a global one and the user's.
+    // A change voids a whole subtree of entries, so instead of hunting those down we
+    // bump an epoch and stale ones stop matching. That also covers a role computed
+    // before the change but written after it: it carries the old stamp.
+    // A grant only changes what its grantee can reach, so it bumps that user's epoch.
+    // Moves change inherited roles for everyone on the subtree, and the entries can't
+    // be found without scanning keys; they bump the global one. They're rare next to
+    // the lookups the cache serves.
+
+    async fn check_permission(&self, folder_id: &str, user_id: &str) -> Result<CachedPermission>;
+
+    async fn cache_permission(&self, folder_id: &str, user_id: &str, role: &str, epoch: &str) -> Result<()>;
+
+    /// Invalidates every cached role at once; used when the folder hierarchy changes.
+    async fn bump_acl_epoch(&self) -> Result<()>;
+
+    /// Invalidates one user's cached roles; used when their grants or ownership change.
+    async fn bump_user_acl_epoch(&self, user_id: &str) -> Result<()>;
+
+    // 3. Counters
+
+    async fn increment_usage(&self, user_id: &str, size: i64) -> Result<()>;
+
+    async fn decrement_usage(&self, user_id: &str, size: i64) -> Result<()>;
+
+    /// Fixed window: true while `user_id` has done `action` at most `limit` times
+    /// in the current `window` seconds.
+    async fn check_rate_limit(&self, user_id: &str, action: &str, limit: i64, window: i64) -> Result<bool>;
+
+    // 4. Session Revocation
+    // Access tokens are short-lived JWTs, so revocation entries only need to outlive them.
+    // A single token is revoked by its jti. Revoking all of a user's tokens at once bumps
+    // users.token_version in MySQL instead, so it survives losing this cache.
+
+    async fn revoke_jti(&self, jti: &str, ttl: u64) -> Result<()>;
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool>;
+
+    // 5. MFA Login Challenges
+    // Issued by login when a second factor is needed: "{purpose}:{user_id}" under the hashed token.
+
+    async fn store_mfa_challenge(&self, token_hash: &str, value: &str, ttl: u64) -> Result<()>;
+
+    async fn get_mfa_challenge(&self, token_hash: &str) -> Result<Option<String>>;
+
+    async fn delete_mfa_challenge(&self, token_hash: &str) -> Result<()>;
+
+    // 6. OIDC Login State
+    // PKCE verifier and nonce of a login in progress, keyed by the OAuth `state` parameter.
+    // Read once: the callback takes the entry so a state value can't be replayed.
+
+    async fn store_oidc_login(&self, state: &str, value: &str, ttl: u64) -> Result<()>;
+
+    async fn take_oidc_login(&self, state: &str) -> Result<Option<String>>;
+
+    // 7. Login Throttling
+    // Failed logins are counted per subject ("acct:{login}" or "ip:{address}"). Past
+    // `backoff_after` failures every further one blocks the subject for 2^n seconds
+    // (capped), at `lockout_after` it is locked for `lockout_secs`. Unknown logins are
+    // counted like real ones so the responses don't reveal which accounts exist.
+
+    /// Seconds until the most restricted of `subjects` may try again, None if all are free.
+    async fn login_retry_after(&self, subjects: &[&str]) -> Result<Option<i64>>;
+
+    /// Counts a failure and applies backoff or lockout. Returns true if this locked the subject.
+    async fn record_login_failure(&self, subject: &str, backoff_after: i64, lockout_after: i64, lockout_secs: i64) -> Result<bool>;
+
+    /// Forgets failures and lifts any block, after a successful login or an admin unlock.
+    async fn clear_login_failures(&self, subject: &str) -> Result<()>;
+}
+
+pub struct CachedPermission {
+    pub role: Option<String>, // None = cache miss
+    pub epoch: String,        // Stamp to write a freshly computed role with
+}
 
-    let zkey = format!("{}:folder:{}:children", PREFIX, folder_id);
-    let empty_key = format!("{}:folder:{}:empty", PREFIX, folder_id);
+pub(crate) const MAX_LOGIN_BACKOFF: i64 = 60;
 
-    // Check if "empty" marker exists
-    let is_empty: bool = con.exists(&empty_key).await.unwrap_or(false);
-    if is_empty {
-        return Ok(Some(Vec::new()));
+pub struct RedisCache {
+    client: Client,
+}
+
+impl RedisCache {
+    pub fn new(client: Client) -> Self {
+        Self { client }
     }
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    // 1. Folder Listing specific (ZSET + HASH)
+    // This returns a list of File objects if cache hit, None if miss.
+    async fn get_cached_folder_files(&self, folder_id: &str) -> Result<Option<Vec<File>>> {
+        let mut con = self.client.get_multiplexed_async_connection().await
+            .context("Failed to get Redis connection")?;
+
+        let zkey = format!("{}:folder:{}:children", PREFIX, folder_id);
+        let empty_key = format!("{}:folder:{}:empty", PREFIX, folder_id);
+
+        // Check if "empty" marker exists
+        let is_empty: bool = con.exists(&empty_key).await.unwrap_or(false);
+        if is_empty {
+            return Ok(Some(Vec::new()));
+        }
     
-    // Check if key exists first to distinguish empty folder vs cache miss
-    let exists: bool = con.exists(&zkey).await?;
-    if !exists {
-        return Ok(None);
-    }
+        // Check if key exists first to distinguish empty folder vs cache miss
+        let exists: bool = con.exists(&zkey).await?;
+        if !exists {
+            return Ok(None);
+        }
 
-    // Get all file IDs from ZSET
-    let file_ids: Vec<String> = con.zrange(&zkey, 0, -1).await?;
+        // Get all file IDs from ZSET
+        let file_ids: Vec<String> = con.zrange(&zkey, 0, -1).await?;
     
-    if file_ids.is_empty() {
-        // Should have been caught by empty_key technically, but if not:
-        return Ok(None); // Treat as miss to be safe
-    }
+        if file_ids.is_empty() {
+            // Should have been caught by empty_key technically, but if not:
+            return Ok(None); // Treat as miss to be safe
+        }
 
-    // Pipeline to fetch all metadata
-    let mut pipe = redis::pipe();
-    for fid in &file_ids {
-        pipe.hgetall(format!("{}:file:{}:meta", PREFIX, fid));
-    }
+        // Pipeline to fetch all metadata
+        let mut pipe = redis::pipe();
+        for fid in &file_ids {
+            pipe.hgetall(format!("{}:file:{}:meta", PREFIX, fid));
+        }
     
-    let results: Vec<std::collections::HashMap<String, String>> = pipe.query_async(&mut con).await?;
+        let results: Vec<std::collections::HashMap<String, String>> = pipe.query_async(&mut con).await?;
     
-    let mut files = Vec::new();
-    for map in results {
-        if map.is_empty() {
-             continue;
-        }
+        let mut files = Vec::new();
+        for map in results {
+            if map.is_empty() {
+                 continue;
+            }
         
-        let file = File {
-            id: map.get("id").cloned().unwrap_or_default(),
-            name: map.get("name").cloned().unwrap_or_default(),
-            folder_id: folder_id.to_string(),
-            owner_id: map.get("owner_id").cloned().unwrap_or_default(),
-            storage_key: map.get("storage_key").cloned().unwrap_or_default(), // Added storage_key
-            size: map.get("size").and_then(|s| s.parse().ok()).unwrap_or(0),
-            mime_type: map.get("mime").cloned(),
-            is_public: map.get("is_public").map(|s| s == "1").unwrap_or(false),
-            // CreatedAt is tricky with string storage, skipping for now or parsing if stored as int
-            created_at: None, 
-        };
-        files.push(file);
+            let file = File {
+                id: map.get("id").cloned().unwrap_or_default(),
+                name: map.get("name").cloned().unwrap_or_default(),
+                folder_id: if folder_id == "root" { None } else { Some(folder_id.to_string()) },
+                owner_id: map.get("owner_id").cloned().unwrap_or_default(),
+                storage_key: map.get("storage_key").cloned().unwrap_or_default(), // Added storage_key
+                size: map.get("size").and_then(|s| s.parse().ok()).unwrap_or(0),
+                mime_type: map.get("mime").cloned(),
+                is_public: map.get("is_public").map(|s| s == "1").unwrap_or(false),
+                status: map.get("status").cloned().unwrap_or_else(|| "committed".to_string()),
+                upload_id: None,
+                // CreatedAt is tricky with string storage, skipping for now or parsing if stored as int
+                created_at: None, 
+                deleted_at: None,
+                deleted_by: None,
+            };
+            files.push(file);
+        }
+
+        Ok(Some(files))
     }
 
-    Ok(Some(files))
-}
+    async fn cache_folder_files(&self, folder_id: &str, files: &[File]) -> Result<()> {
+        let mut con = self.client.get_multiplexed_async_connection().await
+            .context("Failed to connect for caching")?;
 
-pub async fn cache_folder_files(client: &Client, folder_id: &str, files: &[File]) -> Result<()> {
-    let mut con = client.get_multiplexed_async_connection().await
-        .context("Failed to connect for caching")?;
+        // Listings only ever show committed files; pending/failed uploads never enter the cache.
+        let files: Vec<&File> = files.iter().filter(|f| f.status == "committed").collect();
 
-    let zkey = format!("{}:folder:{}:children", PREFIX, folder_id);
-    let empty_key = format!("{}:folder:{}:empty", PREFIX, folder_id);
+        let zkey = format!("{}:folder:{}:children", PREFIX, folder_id);
+        let empty_key = format!("{}:folder:{}:empty", PREFIX, folder_id);
     
-    if files.is_empty() {
-        let _: () = con.set_ex(empty_key, "1", 3600).await?;
-        return Ok(());
-    }
+        if files.is_empty() {
+            let _: () = con.set_ex(empty_key, "1", 3600).await?;
+            return Ok(());
+        }
 
-    let mut pipe = redis::pipe();
-    pipe.atomic(); 
+        let mut pipe = redis::pipe();
+        pipe.atomic(); 
     
-    // Clear old list if we are overwriting
-    // pipe.del(&zkey); // atomic pipe will handle sequence
+        // Clear old list if we are overwriting
+        // pipe.del(&zkey); // atomic pipe will handle sequence
     
-    for file in files {
-        // Add to ZSET (Score = created_at timestamp or 0)
-        let score = file.created_at.map(|t| t.and_utc().timestamp()).unwrap_or(0);
-        pipe.zadd(&zkey, &file.id, score);
+        for file in &files {
+            // Add to ZSET (Score = created_at timestamp or 0)
+            let score = file.created_at.map(|t| t.and_utc().timestamp()).unwrap_or(0);
+            pipe.zadd(&zkey, &file.id, score);
         
-        // Add Metadata to HASH
-        let meta_key = format!("{}:file:{}:meta", PREFIX, file.id);
+            // Add Metadata to HASH
+            let meta_key = format!("{}:file:{}:meta", PREFIX, file.id);
         
-        let d = vec![
-            ("id", file.id.clone()),
-            ("name", file.name.clone()),
-            ("owner_id", file.owner_id.clone()),
-            ("storage_key", file.storage_key.clone()),
-            ("size", file.size.to_string()),
-            ("mime", file.mime_type.clone().unwrap_or_default()),
-            ("is_public", if file.is_public { "1".to_string() } else { "0".to_string() }),
-        ];
+            let d = vec![
+                ("id", file.id.clone()),
+                ("name", file.name.clone()),
+                ("owner_id", file.owner_id.clone()),
+                ("storage_key", file.storage_key.clone()),
+                ("size", file.size.to_string()),
+                ("mime", file.mime_type.clone().unwrap_or_default()),
+                ("is_public", if file.is_public { "1".to_string() } else { "0".to_string() }),
+                ("status", file.status.clone()),
+            ];
         
-        pipe.hset_multiple(&meta_key, &d);
-        pipe.expire(&meta_key, 3600); // 1 hour TTL for file meta
-    }
+            pipe.hset_multiple(&meta_key, &d);
+            pipe.expire(&meta_key, 3600); // 1 hour TTL for file meta
+        }
     
-    // Set TTL for the folder list itself
-    pipe.expire(&zkey, 3600);
+        // Set TTL for the folder list itself
+        pipe.expire(&zkey, 3600);
     
-    pipe.query_async::<_, ()>(&mut con).await?;
+        pipe.query_async::<_, ()>(&mut con).await?;
 
-    Ok(())
-}
+        Ok(())
+    }
 
-// 2. Permission Cache
-pub async fn check_permission(client: &Client, folder_id: &str, user_id: &str) -> Result<Option<String>> {
-    let key = format!("{}:perm:{}:{}", PREFIX, folder_id, user_id);
-    get_string(client, &key).await
-}
+    // 2. Permission Cache
+    // Stored as "{global epoch}.{user epoch}:{role}"; the epochs live in their own keys.
 
-pub async fn cache_permission(client: &Client, folder_id: &str, user_id: &str, role: &str) -> Result<()> {
-    let key = format!("{}:perm:{}:{}", PREFIX, folder_id, user_id);
-    set_string(client, &key, role, 900).await
-}
+    async fn check_permission(&self, folder_id: &str, user_id: &str) -> Result<CachedPermission> {
+        let mut con = self.client.get_multiplexed_async_connection().await
+            .context("Redis conn failed")?;
+        let key = format!("{}:perm:{}:{}", PREFIX, folder_id, user_id);
+        let epoch_key = format!("{}:acl:epoch", PREFIX);
+        let user_epoch_key = format!("{}:acl:epoch:{}", PREFIX, user_id);
 
-// Subfolders Cache
-pub async fn get_cached_subfolders(client: &Client, folder_id: &str) -> Result<Option<Vec<Folder>>> {
-    let mut con = client.get_multiplexed_async_connection().await
-        .context("Failed to get Redis connection")?;
+        let (value, global, user): (Option<String>, Option<i64>, Option<i64>) = redis::pipe()
+            .get(&key)
+            .get(&epoch_key)
+            .get(&user_epoch_key)
+            .query_async(&mut con)
+            .await?;
+        let epoch = format!("{}.{}", global.unwrap_or(0), user.unwrap_or(0));
 
-    let zkey = format!("{}:folder:{}:subfolders", PREFIX, folder_id);
-    let empty_key = format!("{}:folder:{}:subfolders:empty", PREFIX, folder_id);
+        let role = value.and_then(|v| {
+            let (stamp, role) = v.split_once(':')?;
+            (stamp == epoch).then(|| role.to_string())
+        });
 
-    let is_empty: bool = con.exists(&empty_key).await.unwrap_or(false);
-    if is_empty {
-        return Ok(Some(Vec::new()));
+        Ok(CachedPermission { role, epoch })
     }
-    
-    let exists: bool = con.exists(&zkey).await?;
-    if !exists {
-        return Ok(None);
+
+    async fn cache_permission(&self, folder_id: &str, user_id: &str, role: &str, epoch: &str) -> Result<()> {
+        let key = format!("{}:perm:{}:{}", PREFIX, folder_id, user_id);
+        set_string(&self.client, &key, &format!("{}:{}", epoch, role), 900).await
     }
 
-    let folder_ids: Vec<String> = con.zrange(&zkey, 0, -1).await?;
-    if folder_ids.is_empty() {
-        return Ok(None);
+    async fn bump_acl_epoch(&self) -> Result<()> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let _: () = con.incr(format!("{}:acl:epoch", PREFIX), 1).await?;
+        Ok(())
     }
 
-    let mut pipe = redis::pipe();
-    for fid in &folder_ids {
-        pipe.hgetall(format!("{}:folder:{}:meta", PREFIX, fid));
+    // Never expires: going back to an earlier value could revive stale entries
+    async fn bump_user_acl_epoch(&self, user_id: &str) -> Result<()> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let _: () = con.incr(format!("{}:acl:epoch:{}", PREFIX, user_id), 1).await?;
+        Ok(())
     }
+
+    // Subfolders Cache
+    async fn get_cached_subfolders(&self, folder_id: &str) -> Result<Option<Vec<Folder>>> {
+        let mut con = self.client.get_multiplexed_async_connection().await
+            .context("Failed to get Redis connection")?;
+
+        let zkey = format!("{}:folder:{}:subfolders", PREFIX, folder_id);
+        let empty_key = format!("{}:folder:{}:subfolders:empty", PREFIX, folder_id);
+
+        let is_empty: bool = con.exists(&empty_key).await.unwrap_or(false);
+        if is_empty {
+            return Ok(Some(Vec::new()));
+        }
+    
+        let exists: bool = con.exists(&zkey).await?;
+        if !exists {
+            return Ok(None);
+        }
+
+        let folder_ids: Vec<String> = con.zrange(&zkey, 0, -1).await?;
+        if folder_ids.is_empty() {
+            return Ok(None);
+        }
+
+        let mut pipe = redis::pipe();
+        for fid in &folder_ids {
+            pipe.hgetall(format!("{}:folder:{}:meta", PREFIX, fid));
+        }
     
-    let results: Vec<std::collections::HashMap<String, String>> = pipe.query_async(&mut con).await?;
+        let results: Vec<std::collections::HashMap<String, String>> = pipe.query_async(&mut con).await?;
     
-    let mut folders = Vec::new();
-    for map in results {
-        if map.is_empty() { continue; }
+        let mut folders = Vec::new();
+        for map in results {
+            if map.is_empty() { continue; }
         
-        folders.push(Folder {
-            id: map.get("id").cloned().unwrap_or_default(),
-            name: map.get("name").cloned().unwrap_or_default(),
-            parent_id: Some(folder_id.to_string()),
-            owner_id: map.get("owner_id").cloned().unwrap_or_default(),
-            is_public: map.get("is_public").map(|s| s == "1").unwrap_or(false),
-            created_at: None,
-        });
-    }
+            folders.push(Folder {
+                id: map.get("id").cloned().unwrap_or_default(),
+                name: map.get("name").cloned().unwrap_or_default(),
+                parent_id: Some(folder_id.to_string()),
+                owner_id: map.get("owner_id").cloned().unwrap_or_default(),
+                is_public: map.get("is_public").map(|s| s == "1").unwrap_or(false),
+                created_at: None,
+                deleted_at: None,
+                deleted_by: None,
+            });
+        }
 
-    Ok(Some(folders))
-}
+        Ok(Some(folders))
+    }
 
-pub async fn cache_subfolders(client: &Client, parent_id: &str, folders: &[Folder]) -> Result<()> {
-    let mut con = client.get_multiplexed_async_connection().await?;
-    let zkey = format!("{}:folder:{}:subfolders", PREFIX, parent_id);
-    let empty_key = format!("{}:folder:{}:subfolders:empty", PREFIX, parent_id);
+    async fn cache_subfolders(&self, parent_id: &str, folders: &[Folder]) -> Result<()> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let zkey = format!("{}:folder:{}:subfolders", PREFIX, parent_id);
+        let empty_key = format!("{}:folder:{}:subfolders:empty", PREFIX, parent_id);
     
-    if folders.is_empty() {
-        let _: () = con.set_ex(empty_key, "1", 3600).await?;
-        return Ok(());
-    }
+        if folders.is_empty() {
+            let _: () = con.set_ex(empty_key, "1", 3600).await?;
+            return Ok(());
+        }
 
-    let mut pipe = redis::pipe();
-    pipe.atomic(); 
+        let mut pipe = redis::pipe();
+        pipe.atomic(); 
     
-    for folder in folders {
-        let score = folder.created_at.map(|t| t.and_utc().timestamp()).unwrap_or(0);
-        pipe.zadd(&zkey, &folder.id, score);
+        for folder in folders {
+            let score = folder.created_at.map(|t| t.and_utc().timestamp()).unwrap_or(0);
+            pipe.zadd(&zkey, &folder.id, score);
         
-        let meta_key = format!("{}:folder:{}:meta", PREFIX, folder.id);
-        pipe.hset_multiple(&meta_key, &[
-            ("id", folder.id.clone()),
-            ("name", folder.name.clone()),
-            ("owner_id", folder.owner_id.clone()),
-            ("is_public", if folder.is_public { "1".to_string() } else { "0".to_string() }),
-        ]);
-        pipe.expire(&meta_key, 3600);
+            let meta_key = format!("{}:folder:{}:meta", PREFIX, folder.id);
+            pipe.hset_multiple(&meta_key, &[
+                ("id", folder.id.clone()),
+                ("name", folder.name.clone()),
+                ("owner_id", folder.owner_id.clone()),
+                ("is_public", if folder.is_public { "1".to_string() } else { "0".to_string() }),
+            ]);
+            pipe.expire(&meta_key, 3600);
+        }
+        pipe.expire(&zkey, 3600);
+        pipe.query_async::<_, ()>(&mut con).await?;
+        Ok(())
     }
-    pipe.expire(&zkey, 3600);
-    pipe.query_async::<_, ()>(&mut con).await?;
-    Ok(())
-}
 
-pub async fn invalidate_folder_listing(client: &Client, folder_id: &str) -> Result<()> {
-    let zkey_files = format!("{}:folder:{}:children", PREFIX, folder_id);
-    let empty_key_files = format!("{}:folder:{}:empty", PREFIX, folder_id);
-    let zkey_subs = format!("{}:folder:{}:subfolders", PREFIX, folder_id);
-    let empty_key_subs = format!("{}:folder:{}:subfolders:empty", PREFIX, folder_id);
+    async fn invalidate_folder_listing(&self, folder_id: &str) -> Result<()> {
+        let zkey_files = format!("{}:folder:{}:children", PREFIX, folder_id);
+        let empty_key_files = format!("{}:folder:{}:empty", PREFIX, folder_id);
+        let zkey_subs = format!("{}:folder:{}:subfolders", PREFIX, folder_id);
+        let empty_key_subs = format!("{}:folder:{}:subfolders:empty", PREFIX, folder_id);
     
-    let mut con = client.get_multiplexed_async_connection().await?;
-    // Ignoring errors on del
-    let _: () = con.del(&[zkey_files, empty_key_files, zkey_subs, empty_key_subs]).await.unwrap_or(());
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        // Ignoring errors on del
+        let _: () = con.del(&[zkey_files, empty_key_files, zkey_subs, empty_key_subs]).await.unwrap_or(());
     
-    Ok(())
-}
+        Ok(())
+    }
 
-pub async fn increment_usage(client: &Client, user_id: &str, size: i64) -> Result<()> {
-    let mut con = client.get_multiplexed_async_connection().await?;
-    let key = format!("{}:user:{}:usage", PREFIX, user_id);
-    let _: () = con.incr(key, size).await?;
-    Ok(())
-}
+    async fn invalidate_folder_meta(&self, folder_id: &str) -> Result<()> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let _: () = con.del(format!("{}:folder:{}:meta", PREFIX, folder_id)).await?;
+        Ok(())
+    }
 
-pub async fn check_rate_limit(client: &Client, user_id: &str, action: &str, limit: i64, window: i64) -> Result<bool> {
-    let mut con = client.get_multiplexed_async_connection().await?;
-    let key = format!("{}:ratelimit:{}:{}", PREFIX, user_id, action);
-    
-    let count: i64 = con.incr(&key, 1).await?;
-    if count == 1 {
-        let _: () = con.expire(&key, window).await?;
+    async fn invalidate_file_meta(&self, file_id: &str) -> Result<()> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let _: () = con.del(format!("{}:file:{}:meta", PREFIX, file_id)).await?;
+        Ok(())
+    }
+
+    async fn increment_usage(&self, user_id: &str, size: i64) -> Result<()> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let key = format!("{}:user:{}:usage", PREFIX, user_id);
+        let _: () = con.incr(key, size).await?;
+        Ok(())
+    }
+
+    async fn decrement_usage(&self, user_id: &str, size: i64) -> Result<()> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let key = format!("{}:user:{}:usage", PREFIX, user_id);
+        let _: () = con.decr(key, size).await?;
+        Ok(())
+    }
+
+    async fn purge_cached_entries(&self, folder_ids: &[String], file_ids: &[String]) -> Result<()> {
+        if folder_ids.is_empty() && file_ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut keys = Vec::with_capacity(folder_ids.len() * 5 + file_ids.len());
+        for id in folder_ids {
+            keys.push(format!("{}:folder:{}:meta", PREFIX, id));
+            keys.push(format!("{}:folder:{}:children", PREFIX, id));
+            keys.push(format!("{}:folder:{}:empty", PREFIX, id));
+            keys.push(format!("{}:folder:{}:subfolders", PREFIX, id));
+            keys.push(format!("{}:folder:{}:subfolders:empty", PREFIX, id));
+        }
+        for id in file_ids {
+            keys.push(format!("{}:file:{}:meta", PREFIX, id));
+        }
+
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let _: () = con.del(keys).await?;
+        Ok(())
     }
+
+    async fn check_rate_limit(&self, user_id: &str, action: &str, limit: i64, window: i64) -> Result<bool> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let key = format!("{}:ratelimit:{}:{}", PREFIX, user_id, action);
     
-    Ok(count <= limit)
-}
+        let count: i64 = con.incr(&key, 1).await?;
+        if count == 1 {
+            let _: () = con.expire(&key, window).await?;
+        }
+    
+        Ok(count <= limit)
+    }
 
-// Private Helpers
-async fn get_string(client: &Client, key: &str) -> Result<Option<String>> {
-    let mut con = client.get_multiplexed_async_connection().await
-        .context("Redis conn failed")?;
-    let res: Option<String> = con.get(key).await.unwrap_or(None);
-    Ok(res)
+    // 3. Session Revocation
+
+    async fn revoke_jti(&self, jti: &str, ttl: u64) -> Result<()> {
+        let key = format!("{}:revoked:{}", PREFIX, jti);
+        set_string(&self.client, &key, "1", ttl.max(1)).await
+    }
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool> {
+        let mut con = self.client.get_multiplexed_async_connection().await
+            .context("Redis conn failed")?;
+        let revoked: bool = con.exists(format!("{}:revoked:{}", PREFIX, jti)).await?;
+        Ok(revoked)
+    }
+
+    // 4. MFA Login Challenges
+
+    async fn store_mfa_challenge(&self, token_hash: &str, value: &str, ttl: u64) -> Result<()> {
+        let key = format!("{}:mfa:{}", PREFIX, token_hash);
+        set_string(&self.client, &key, value, ttl).await
+    }
+
+    async fn get_mfa_challenge(&self, token_hash: &str) -> Result<Option<String>> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let value: Option<String> = con.get(format!("{}:mfa:{}", PREFIX, token_hash)).await?;
+        Ok(value)
+    }
+
+    async fn delete_mfa_challenge(&self, token_hash: &str) -> Result<()> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let _: () = con.del(format!("{}:mfa:{}", PREFIX, token_hash)).await?;
+        Ok(())
+    }
+
+    // 5. OIDC Login State (GETDEL so a state value can't be replayed)
+
+    async fn store_oidc_login(&self, state: &str, value: &str, ttl: u64) -> Result<()> {
+        let key = format!("{}:oidc:{}", PREFIX, state);
+        set_string(&self.client, &key, value, ttl).await
+    }
+
+    async fn take_oidc_login(&self, state: &str) -> Result<Option<String>> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let value: Option<String> = con.get_del(format!("{}:oidc:{}", PREFIX, state)).await?;
+        Ok(value)
+    }
+
+    // 6. Login Throttling
+    // "...:failures" counts within a sliding window, "...:blocked" expires when the
+    // subject may try again.
+
+    async fn login_retry_after(&self, subjects: &[&str]) -> Result<Option<i64>> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let mut wait = 0;
+        for subject in subjects {
+            let ttl: i64 = con.ttl(format!("{}:login:{}:blocked", PREFIX, subject)).await?;
+            wait = wait.max(ttl);
+        }
+        Ok(if wait > 0 { Some(wait) } else { None })
+    }
+
+    async fn record_login_failure(&self, subject: &str, backoff_after: i64, lockout_after: i64, lockout_secs: i64) -> Result<bool> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let failures_key = format!("{}:login:{}:failures", PREFIX, subject);
+        let blocked_key = format!("{}:login:{}:blocked", PREFIX, subject);
+
+        // Sliding window: keeps counting while failures keep coming
+        let count: i64 = con.incr(&failures_key, 1).await?;
+        let _: () = con.expire(&failures_key, lockout_secs).await?;
+
+        if lockout_after > 0 && count >= lockout_after {
+            let _: () = con.set_ex(&blocked_key, "locked", lockout_secs as u64).await?;
+            let _: () = con.del(&failures_key).await?;
+            return Ok(true);
+        }
+
+        if count >= backoff_after {
+            let delay = (1i64 << (count - backoff_after).min(6)).min(MAX_LOGIN_BACKOFF);
+            let _: () = con.set_ex(&blocked_key, "backoff", delay as u64).await?;
+        }
+
+        Ok(false)
+    }
+
+    async fn clear_login_failures(&self, subject: &str) -> Result<()> {
+        let mut con = self.client.get_multiplexed_async_connection().await?;
+        let _: () = con.del(&[
+            format!("{}:login:{}:failures", PREFIX, subject),
+            format!("{}:login:{}:blocked", PREFIX, subject),
+        ]).await?;
+        Ok(())
+    }
 }
 
+// Private Helpers
 async fn set_string(client: &Client, key: &str, value: &str, ttl: u64) -> Result<()> {
     let mut con = client.get_multiplexed_async_connection().await
         .context("Redis conn failed")?;
@@ -0,0 +1,120 @@
+use anyhow::{Result, Context};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::models::{RefreshToken, User};
+use crate::services::auth::{create_jwt, Claims};
+use crate::state::AppState;
+
+pub struct IssuedTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+/// Opaque bearer secret (refresh, reset and share tokens): 32 random bytes, URL-safe.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// Tokens are stored as SHA-256 only
+pub fn hash_token(raw: &str) -> String {
+    hex::encode(Sha256::digest(raw.as_bytes()))
+}
+
+/// Issues an access token plus a refresh token. `family_id` is None on login (new family)
+/// and the rotated token's family on refresh.
+pub async fn issue_tokens(state: &AppState, user: &User, family_id: Option<&str>) -> Result<IssuedTokens> {
+    let access_token = create_jwt(&user.id, &user.role, i64::from(user.token_version), &state.config, &state.jwt_keys)?;
+
+    let refresh_token = random_token();
+
+    let family_id = family_id.map(str::to_string).unwrap_or_else(|| Uuid::new_v4().to_string());
+    let expires_at = Utc::now().naive_utc() + chrono::Duration::seconds(state.config.refresh_token_ttl);
+
+    sqlx::query("INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(&user.id)
+        .bind(&family_id)
+        .bind(hash_token(&refresh_token))
+        .bind(expires_at)
+        .execute(&state.db)
+        .await
+        .context("Failed to store refresh token")?;
+
+    Ok(IssuedTokens { access_token, refresh_token })
+}
+
+pub async fn find_refresh_token(state: &AppState, raw: &str) -> Option<RefreshToken> {
+    sqlx::query_as("SELECT * FROM refresh_tokens WHERE token_hash = ?")
+        .bind(hash_token(raw))
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+}
+
+/// Marks a token as rotated. Returns false if it was already used or revoked
+/// (a concurrent refresh won the race, or the token is being replayed).
+pub async fn mark_used(state: &AppState, token: &RefreshToken) -> Result<bool> {
+    let result = sqlx::query("UPDATE refresh_tokens SET used_at = ? WHERE id = ? AND used_at IS NULL AND revoked_at IS NULL")
+        .bind(Utc::now().naive_utc())
+        .bind(&token.id)
+        .execute(&state.db)
+        .await
+        .context("Failed to rotate refresh token")?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn revoke_family(state: &AppState, family_id: &str) -> Result<()> {
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = ? WHERE family_id = ? AND revoked_at IS NULL")
+        .bind(Utc::now().naive_utc())
+        .bind(family_id)
+        .execute(&state.db)
+        .await
+        .context("Failed to revoke token family")?;
+
+    Ok(())
+}
+
+/// Signs the user out everywhere: all refresh tokens, every access token issued so far
+/// and all personal access tokens, which whoever had the password could have created.
+pub async fn revoke_user_sessions(state: &AppState, user_id: &str) -> Result<()> {
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = ? WHERE user_id = ? AND revoked_at IS NULL")
+        .bind(Utc::now().naive_utc())
+        .bind(user_id)
+        .execute(&state.db)
+        .await
+        .context("Failed to revoke refresh tokens")?;
+
+    sqlx::query("DELETE FROM api_tokens WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&state.db)
+        .await
+        .context("Failed to revoke API tokens")?;
+
+    bump_token_version(state, user_id).await
+}
+
+/// Invalidates every access token issued to the user so far.
+pub async fn bump_token_version(state: &AppState, user_id: &str) -> Result<()> {
+    sqlx::query("UPDATE users SET token_version = token_version + 1 WHERE id = ?")
+        .bind(user_id)
+        .execute(&state.db)
+        .await
+        .context("Failed to bump token version")?;
+
+    Ok(())
+}
+
+/// Revokes a single access token until it would have expired anyway.
+pub async fn revoke_access_token(state: &AppState, claims: &Claims) {
+    let remaining = (claims.exp as i64 - Utc::now().timestamp()).max(1) as u64;
+    if let Err(e) = state.cache.revoke_jti(&claims.jti, remaining).await {
+        tracing::warn!("Failed to revoke access token {}: {:?}", claims.jti, e);
+    }
+}
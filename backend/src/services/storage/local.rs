@@ -0,0 +1,439 @@
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use futures_util::{StreamExt, TryStreamExt};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::config::Config;
+use super::{ByteRange, ByteStream, ObjectBody, ObjectInfo, ObjectMetadata, PresignedPost, ResponseOverrides, StorageBackend, UploadedPart};
+
+/// Files on a local disk under LOCAL_STORAGE_PATH, for small installs without MinIO.
+/// Presigned URLs point at our own /api/storage routes and carry an HMAC over the
+/// method, resource, content type and expiry, so they work like S3's.
+///
+/// Layout: `objects/<key>` holds the bytes, `meta/<key>` the content type,
+/// `uploads/<upload_id>/` the parts of a multipart upload and `tmp/` half-written
+/// files, which are renamed into place once complete.
+pub struct LocalStorage {
+    root: PathBuf,
+    public_url: String,
+    secret: Vec<u8>,
+}
+
+impl LocalStorage {
+    pub fn new(config: &Config) -> Result<Self> {
+        if config.storage_signing_secret.is_empty() {
+            bail!("STORAGE_SIGNING_SECRET must be set for the local storage backend");
+        }
+
+        let root = PathBuf::from(&config.local_storage_path);
+        for dir in ["objects", "meta", "uploads", "tmp"] {
+            std::fs::create_dir_all(root.join(dir))
+                .with_context(|| format!("Failed to create {}", root.join(dir).display()))?;
+        }
+
+        Ok(Self {
+            root,
+            public_url: config.storage_public_url.trim_end_matches('/').to_string(),
+            secret: config.storage_signing_secret.as_bytes().to_vec(),
+        })
+    }
+
+    /// Checks a signed URL's query parameters for `method` on `resource`
+    /// (`objects/<key>` or `uploads/<upload_id>/<part_number>`). `content_type` is the
+    /// upload's type for PUT and the response's for GET.
+    pub fn verify(&self, method: &str, resource: &str, content_type: Option<&str>, content_disposition: Option<&str>, expires: i64, signature: &str) -> bool {
+        if expires < chrono::Utc::now().timestamp() {
+            return false;
+        }
+        let signature = match hex::decode(signature) {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+        self.mac(method, resource, content_type, content_disposition, expires).verify_slice(&signature).is_ok()
+    }
+
+    fn mac(&self, method: &str, resource: &str, content_type: Option<&str>, content_disposition: Option<&str>, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(format!("{}\n{}\n{}\n{}", method, resource, content_type.unwrap_or(""), expires).as_bytes());
+        if let Some(cd) = content_disposition {
+            mac.update(format!("\n{}", cd).as_bytes());
+        }
+        mac
+    }
+
+    fn signed_url(&self, method: &str, resource: &str, content_type: Option<&str>, content_disposition: Option<&str>, expires_in: Duration) -> String {
+        let expires = chrono::Utc::now().timestamp() + expires_in.as_secs() as i64;
+        let signature = hex::encode(self.mac(method, resource, content_type, content_disposition, expires).finalize().into_bytes());
+
+        let path: Vec<String> = resource.split('/').map(|s| urlencoding::encode(s).into_owned()).collect();
+        let mut url = format!("{}/api/storage/{}?expires={}&signature={}", self.public_url, path.join("/"), expires, signature);
+        if let Some(ct) = content_type {
+            url.push_str(&format!("&content_type={}", urlencoding::encode(ct)));
+        }
+        if let Some(cd) = content_disposition {
+            url.push_str(&format!("&content_disposition={}", urlencoding::encode(cd)));
+        }
+        url
+    }
+
+    // Keys come from our own DB, but are still checked so one can never leave the root
+    fn object_path(&self, dir: &str, key: &str) -> Result<PathBuf> {
+        let valid = !key.is_empty()
+            && !key.contains('\\')
+            && Path::new(key).components().all(|c| matches!(c, Component::Normal(_)));
+        if !valid {
+            bail!("Invalid storage key '{}'", key);
+        }
+        Ok(self.root.join(dir).join(key))
+    }
+
+    fn upload_dir(&self, upload_id: &str) -> Result<PathBuf> {
+        let id = Uuid::parse_str(upload_id).context("Invalid upload id")?;
+        Ok(self.root.join("uploads").join(id.to_string()))
+    }
+
+    fn part_path(&self, upload_id: &str, part_number: i32) -> Result<PathBuf> {
+        if !(1..=10_000).contains(&part_number) {
+            bail!("Part number {} out of range", part_number);
+        }
+        Ok(self.upload_dir(upload_id)?.join(part_number.to_string()))
+    }
+
+    fn tmp_path(&self) -> PathBuf {
+        self.root.join("tmp").join(Uuid::new_v4().to_string())
+    }
+
+    // Write to tmp/, then rename, so readers never see a partial file
+    async fn write_atomic(&self, path: &Path, mut stream: ByteStream) -> Result<(i64, String)> {
+        let tmp = self.tmp_path();
+        let result = async {
+            let mut file = fs::File::create(&tmp).await?;
+            let mut md5 = md5::Context::new();
+            let mut size = 0i64;
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk?;
+                md5.consume(&chunk);
+                size += chunk.len() as i64;
+                file.write_all(&chunk).await?;
+            }
+            file.sync_all().await?;
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::rename(&tmp, path).await?;
+            Ok::<_, std::io::Error>((size, format!("\"{:x}\"", md5.compute())))
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp).await;
+        }
+        result.context("Failed to write object")
+    }
+
+    async fn write_meta(&self, key: &str, content_type: Option<&str>) -> Result<()> {
+        let path = self.object_path("meta", key)?;
+        match content_type {
+            Some(ct) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                fs::write(&path, ct).await?;
+            },
+            None => remove_if_exists(&path).await?,
+        }
+        Ok(())
+    }
+
+    /// Streams a PUT body into an object, for the signed upload URL.
+    pub async fn write_object(&self, key: &str, content_type: Option<&str>, stream: ByteStream) -> Result<()> {
+        let path = self.object_path("objects", key)?;
+        self.write_atomic(&path, stream).await?;
+        self.write_meta(key, content_type).await
+    }
+
+    /// Streams a PUT body into one part, for the signed part URL. Returns the ETag.
+    pub async fn write_part(&self, upload_id: &str, part_number: i32, stream: ByteStream) -> Result<String> {
+        let path = self.part_path(upload_id, part_number)?;
+        if fs::metadata(self.upload_dir(upload_id)?).await.is_err() {
+            bail!("No such multipart upload");
+        }
+
+        let (_, etag) = self.write_atomic(&path, stream).await?;
+        fs::write(path.with_extension("etag"), &etag).await?;
+        Ok(etag)
+    }
+
+    // The key the upload was created for; parts can't be completed into another object
+    async fn check_upload(&self, key: &str, upload_id: &str) -> Result<PathBuf> {
+        let dir = self.upload_dir(upload_id)?;
+        let owner = fs::read_to_string(dir.join("key")).await.context("No such multipart upload")?;
+        if owner != key {
+            bail!("Multipart upload belongs to another key");
+        }
+        Ok(dir)
+    }
+}
+
+async fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(path).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn single_chunk(data: Vec<u8>) -> ByteStream {
+    futures_util::stream::once(async move { Ok(data.into()) }).boxed()
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: Option<&str>) -> Result<()> {
+        self.write_object(key, content_type, single_chunk(data)).await
+    }
+
+    async fn get(&self, key: &str, range: Option<ByteRange>) -> Result<Option<ObjectBody>> {
+        let path = self.object_path("objects", key)?;
+        let mut file = match fs::File::open(&path).await {
+            Ok(f) => f,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to open object"),
+        };
+        let total_size = file.metadata().await?.len();
+
+        let (start, end) = match range {
+            Some(r) => {
+                if r.start >= total_size {
+                    bail!("Range start {} is past the end of the object ({} bytes)", r.start, total_size);
+                }
+                (r.start, r.end.unwrap_or(u64::MAX).min(total_size - 1))
+            },
+            None => (0, total_size.saturating_sub(1)),
+        };
+        let content_length = if total_size == 0 { 0 } else { end - start + 1 };
+
+        file.seek(SeekFrom::Start(start)).await?;
+        let stream = ReaderStream::new(file.take(content_length)).boxed();
+        let content_type = fs::read_to_string(self.object_path("meta", key)?).await.ok();
+
+        Ok(Some(ObjectBody {
+            content_length: content_length as i64,
+            content_type,
+            stream,
+        }))
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<ObjectMetadata>> {
+        let path = self.object_path("objects", key)?;
+        let metadata = match fs::metadata(&path).await {
+            Ok(m) => m,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to stat object"),
+        };
+
+        Ok(Some(ObjectMetadata {
+            size: metadata.len() as i64,
+            content_type: fs::read_to_string(self.object_path("meta", key)?).await.ok(),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let path = self.object_path("objects", key)?;
+        remove_if_exists(&path).await.context("Failed to delete object")?;
+        remove_if_exists(&self.object_path("meta", key)?).await?;
+
+        // Drop the folder directory once its last file is gone (fails while it isn't)
+        if let Some(parent) = path.parent().filter(|p| *p != self.root.join("objects")) {
+            let _ = fs::remove_dir(parent).await;
+        }
+        Ok(())
+    }
+
+    async fn delete_many(&self, keys: &[String]) -> Result<()> {
+        for key in keys {
+            self.delete(key).await?;
+        }
+        Ok(())
+    }
+
+    async fn copy(&self, source_key: &str, dest_key: &str, _size: i64) -> Result<()> {
+        let source = self.object_path("objects", source_key)?;
+        let dest = self.object_path("objects", dest_key)?;
+
+        let tmp = self.tmp_path();
+        fs::copy(&source, &tmp).await.context("Failed to copy object")?;
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(&tmp, &dest).await.context("Failed to copy object")?;
+
+        let content_type = fs::read_to_string(self.object_path("meta", source_key)?).await.ok();
+        self.write_meta(dest_key, content_type.as_deref()).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        let objects = self.root.join("objects");
+
+        // Only walk the deepest directory the prefix names, then match the rest by string
+        let start = match prefix.rsplit_once('/') {
+            Some((dir, _)) => self.object_path("objects", dir)?,
+            None => objects.clone(),
+        };
+
+        let mut found = Vec::new();
+        let mut pending = vec![start];
+        while let Some(dir) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(e) => e,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).context("Failed to list objects"),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let metadata = entry.metadata().await?;
+                if metadata.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+                let key = path.strip_prefix(&objects)?.to_string_lossy().replace('\\', "/");
+                if key.starts_with(prefix) {
+                    found.push(ObjectInfo { key, size: metadata.len() as i64 });
+                }
+            }
+        }
+
+        found.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(found)
+    }
+
+    async fn presign_put(&self, key: &str, content_type: Option<&str>, expires_in: Duration) -> Result<String> {
+        self.object_path("objects", key)?;
+        Ok(self.signed_url("PUT", &format!("objects/{}", key), content_type, None, expires_in))
+    }
+
+    async fn presign_get(&self, key: &str, overrides: &ResponseOverrides, expires_in: Duration) -> Result<String> {
+        self.object_path("objects", key)?;
+        Ok(self.signed_url(
+            "GET",
+            &format!("objects/{}", key),
+            overrides.content_type.as_deref(),
+            overrides.content_disposition.as_deref(),
+            expires_in,
+        ))
+    }
+
+    // Signed PUTs only; AppState refuses UPLOAD_MODE=post with this backend
+    async fn presign_post(&self, _key: &str, _content_type: &str, _size: i64, _expires_in: Duration) -> Result<PresignedPost> {
+        bail!("POST policy uploads need the s3 storage backend")
+    }
+
+    async fn create_multipart(&self, key: &str, content_type: Option<&str>) -> Result<String> {
+        self.object_path("objects", key)?;
+
+        let upload_id = Uuid::new_v4().to_string();
+        let dir = self.upload_dir(&upload_id)?;
+        fs::create_dir_all(&dir).await.context("Failed to create multipart upload")?;
+        fs::write(dir.join("key"), key).await?;
+        if let Some(ct) = content_type {
+            fs::write(dir.join("content-type"), ct).await?;
+        }
+
+        Ok(upload_id)
+    }
+
+    async fn upload_part(&self, key: &str, upload_id: &str, part_number: i32, data: Vec<u8>) -> Result<String> {
+        self.check_upload(key, upload_id).await?;
+        self.write_part(upload_id, part_number, single_chunk(data)).await
+    }
+
+    async fn presign_upload_part(&self, key: &str, upload_id: &str, part_number: i32, expires_in: Duration) -> Result<String> {
+        self.check_upload(key, upload_id).await?;
+        self.part_path(upload_id, part_number)?;
+        Ok(self.signed_url("PUT", &format!("uploads/{}/{}", upload_id, part_number), None, None, expires_in))
+    }
+
+    async fn list_parts(&self, key: &str, upload_id: &str) -> Result<Vec<UploadedPart>> {
+        let dir = self.check_upload(key, upload_id).await?;
+
+        let mut parts = Vec::new();
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let part_number: i32 = match entry.file_name().to_str().and_then(|n| n.parse().ok()) {
+                Some(n) => n,
+                None => continue, // key, content-type, *.etag
+            };
+            let path = entry.path();
+            parts.push(UploadedPart {
+                part_number,
+                etag: fs::read_to_string(path.with_extension("etag")).await.unwrap_or_default(),
+                size: entry.metadata().await?.len() as i64,
+            });
+        }
+
+        parts.sort_by_key(|p| p.part_number);
+        Ok(parts)
+    }
+
+    async fn complete_multipart(&self, key: &str, upload_id: &str, parts: &[(i32, String)]) -> Result<()> {
+        let dir = self.check_upload(key, upload_id).await?;
+
+        // Same checks S3 makes before assembling anything
+        let mut previous = 0;
+        for (part_number, etag) in parts {
+            if *part_number <= previous {
+                bail!("Parts must be in ascending order");
+            }
+            previous = *part_number;
+
+            let path = self.part_path(upload_id, *part_number)?;
+            let stored = fs::read_to_string(path.with_extension("etag"))
+                .await
+                .with_context(|| format!("Part {} has not been uploaded", part_number))?;
+            if stored.trim_matches('"') != etag.trim_matches('"') {
+                bail!("ETag mismatch for part {}", part_number);
+            }
+        }
+
+        let files: Vec<PathBuf> = parts
+            .iter()
+            .map(|(n, _)| self.part_path(upload_id, *n))
+            .collect::<Result<_>>()?;
+        let stream = futures_util::stream::iter(files)
+            .then(|path| async move { fs::File::open(path).await.map(ReaderStream::new) })
+            .try_flatten()
+            .boxed();
+
+        let path = self.object_path("objects", key)?;
+        self.write_atomic(&path, stream).await?;
+
+        let content_type = fs::read_to_string(dir.join("content-type")).await.ok();
+        self.write_meta(key, content_type.as_deref()).await?;
+        fs::remove_dir_all(&dir).await.context("Failed to clean up multipart upload")?;
+        Ok(())
+    }
+
+    async fn abort_multipart(&self, key: &str, upload_id: &str) -> Result<()> {
+        let dir = match self.check_upload(key, upload_id).await {
+            Ok(dir) => dir,
+            Err(_) => return Ok(()), // Already completed or aborted
+        };
+        fs::remove_dir_all(&dir).await.context("Failed to abort multipart upload")
+    }
+
+    fn as_local(&self) -> Option<&LocalStorage> {
+        Some(self)
+    }
+}
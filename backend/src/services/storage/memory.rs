@@ -0,0 +1,211 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use uuid::Uuid;
+
+use super::{ByteRange, ObjectBody, ObjectInfo, ObjectMetadata, PresignedPost, ResponseOverrides, StorageBackend, UploadedPart};
+
+/// Objects in a HashMap, for tests. Presigned URLs use a `memory://` scheme that
+/// nothing can fetch; tests put and read objects through the trait instead.
+#[derive(Default)]
+pub struct MemoryStorage {
+    objects: Mutex<HashMap<String, StoredObject>>,
+    uploads: Mutex<HashMap<String, MultipartUpload>>,
+}
+
+#[derive(Clone)]
+struct StoredObject {
+    data: Vec<u8>,
+    content_type: Option<String>,
+}
+
+struct MultipartUpload {
+    key: String,
+    content_type: Option<String>,
+    parts: BTreeMap<i32, (String, Vec<u8>)>, // part number -> (ETag, bytes)
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn presigned(method: &str, key: &str, expires_in: Duration) -> String {
+        format!("memory://{}?method={}&expires_in={}", key, method, expires_in.as_secs())
+    }
+
+    fn upload<T>(&self, key: &str, upload_id: &str, f: impl FnOnce(&mut MultipartUpload) -> T) -> Result<T> {
+        let mut uploads = self.uploads.lock().unwrap();
+        match uploads.get_mut(upload_id) {
+            Some(upload) if upload.key == key => Ok(f(upload)),
+            Some(_) => bail!("Multipart upload belongs to another key"),
+            None => bail!("No such multipart upload"),
+        }
+    }
+}
+
+fn etag(data: &[u8]) -> String {
+    format!("\"{:x}\"", md5::compute(data))
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: Option<&str>) -> Result<()> {
+        let object = StoredObject { data, content_type: content_type.map(str::to_string) };
+        self.objects.lock().unwrap().insert(key.to_string(), object);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, range: Option<ByteRange>) -> Result<Option<ObjectBody>> {
+        let object = match self.objects.lock().unwrap().get(key) {
+            Some(o) => o.clone(),
+            None => return Ok(None),
+        };
+
+        let len = object.data.len() as u64;
+        let data = match range {
+            Some(r) if r.start >= len => bail!("Range start {} is past the end of the object ({} bytes)", r.start, len),
+            Some(r) => object.data[r.start as usize..=r.end.unwrap_or(u64::MAX).min(len - 1) as usize].to_vec(),
+            None => object.data,
+        };
+
+        Ok(Some(ObjectBody {
+            content_length: data.len() as i64,
+            content_type: object.content_type,
+            stream: futures_util::stream::once(async move { Ok(data.into()) }).boxed(),
+        }))
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<ObjectMetadata>> {
+        Ok(self.objects.lock().unwrap().get(key).map(|o| ObjectMetadata {
+            size: o.data.len() as i64,
+            content_type: o.content_type.clone(),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.objects.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn delete_many(&self, keys: &[String]) -> Result<()> {
+        let mut objects = self.objects.lock().unwrap();
+        for key in keys {
+            objects.remove(key);
+        }
+        Ok(())
+    }
+
+    async fn copy(&self, source_key: &str, dest_key: &str, _size: i64) -> Result<()> {
+        let mut objects = self.objects.lock().unwrap();
+        let object = objects.get(source_key).cloned().context("Copy source does not exist")?;
+        objects.insert(dest_key.to_string(), object);
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        let mut found: Vec<ObjectInfo> = self.objects.lock().unwrap()
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, o)| ObjectInfo { key: key.clone(), size: o.data.len() as i64 })
+            .collect();
+        found.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(found)
+    }
+
+    async fn presign_put(&self, key: &str, _content_type: Option<&str>, expires_in: Duration) -> Result<String> {
+        Ok(Self::presigned("PUT", key, expires_in))
+    }
+
+    async fn presign_get(&self, key: &str, overrides: &ResponseOverrides, expires_in: Duration) -> Result<String> {
+        let mut url = Self::presigned("GET", key, expires_in);
+        if let Some(ct) = &overrides.content_type {
+            url.push_str(&format!("&response-content-type={}", urlencoding::encode(ct)));
+        }
+        if let Some(cd) = &overrides.content_disposition {
+            url.push_str(&format!("&response-content-disposition={}", urlencoding::encode(cd)));
+        }
+        Ok(url)
+    }
+
+    // The policy is left readable (and unsigned) so tests can check its conditions
+    async fn presign_post(&self, key: &str, content_type: &str, size: i64, expires_in: Duration) -> Result<PresignedPost> {
+        let policy = serde_json::json!({
+            "conditions": [
+                { "key": key },
+                { "Content-Type": content_type },
+                ["content-length-range", size, size],
+            ],
+        });
+
+        Ok(PresignedPost {
+            url: Self::presigned("POST", key, expires_in),
+            fields: BTreeMap::from([
+                ("key".to_string(), key.to_string()),
+                ("Content-Type".to_string(), content_type.to_string()),
+                ("policy".to_string(), policy.to_string()),
+            ]),
+        })
+    }
+
+    async fn create_multipart(&self, key: &str, content_type: Option<&str>) -> Result<String> {
+        let upload_id = Uuid::new_v4().to_string();
+        self.uploads.lock().unwrap().insert(upload_id.clone(), MultipartUpload {
+            key: key.to_string(),
+            content_type: content_type.map(str::to_string),
+            parts: BTreeMap::new(),
+        });
+        Ok(upload_id)
+    }
+
+    async fn upload_part(&self, key: &str, upload_id: &str, part_number: i32, data: Vec<u8>) -> Result<String> {
+        let etag = etag(&data);
+        self.upload(key, upload_id, |upload| {
+            upload.parts.insert(part_number, (etag.clone(), data));
+        })?;
+        Ok(etag)
+    }
+
+    async fn presign_upload_part(&self, key: &str, upload_id: &str, part_number: i32, expires_in: Duration) -> Result<String> {
+        self.upload(key, upload_id, |_| ())?;
+        Ok(format!("{}&upload_id={}&part_number={}", Self::presigned("PUT", key, expires_in), upload_id, part_number))
+    }
+
+    async fn list_parts(&self, key: &str, upload_id: &str) -> Result<Vec<UploadedPart>> {
+        self.upload(key, upload_id, |upload| {
+            upload.parts
+                .iter()
+                .map(|(number, (etag, data))| UploadedPart { part_number: *number, etag: etag.clone(), size: data.len() as i64 })
+                .collect()
+        })
+    }
+
+    async fn complete_multipart(&self, key: &str, upload_id: &str, parts: &[(i32, String)]) -> Result<()> {
+        let object = self.upload(key, upload_id, |upload| {
+            let mut data = Vec::new();
+            for (number, etag) in parts {
+                match upload.parts.get(number) {
+                    Some((stored, bytes)) if stored.trim_matches('"') == etag.trim_matches('"') => data.extend_from_slice(bytes),
+                    _ => bail!("Part {} is missing or has a different ETag", number),
+                }
+            }
+            Ok(StoredObject { data, content_type: upload.content_type.clone() })
+        })??;
+
+        self.uploads.lock().unwrap().remove(upload_id);
+        self.objects.lock().unwrap().insert(key.to_string(), object);
+        Ok(())
+    }
+
+    async fn abort_multipart(&self, _key: &str, upload_id: &str) -> Result<()> {
+        self.uploads.lock().unwrap().remove(upload_id);
+        Ok(())
+    }
+}
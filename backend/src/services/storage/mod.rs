@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use futures_util::TryStreamExt;
+use serde::Serialize;
+
+use crate::config::Config;
+
+pub mod local;
+pub mod memory;
+pub mod s3;
+
+pub use local::LocalStorage;
+pub use memory::MemoryStorage;
+pub use s3::S3Storage;
+
+pub struct ObjectMetadata {
+    pub size: i64,
+    pub content_type: Option<String>,
+}
+
+/// One entry of `list`.
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: i64,
+}
+
+pub struct UploadedPart {
+    pub part_number: i32,
+    pub etag: String,
+    pub size: i64,
+}
+
+/// Inclusive byte range, like an HTTP `Range: bytes=start-end` header.
+/// `end: None` reads to the end of the object.
+#[derive(Clone, Copy, Debug)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl ByteRange {
+    pub fn header_value(&self) -> String {
+        match self.end {
+            Some(end) => format!("bytes={}-{}", self.start, end),
+            None => format!("bytes={}-", self.start),
+        }
+    }
+}
+
+/// Headers a presigned GET makes the store answer with instead of the stored ones,
+/// like S3's `response-content-type` and `response-content-disposition`.
+#[derive(Clone, Debug, Default)]
+pub struct ResponseOverrides {
+    pub content_type: Option<String>,
+    pub content_disposition: Option<String>,
+}
+
+/// A browser-based POST upload: a multipart form of `fields` followed by the
+/// object itself as the last field, `file`. The store rejects anything that breaks
+/// the signed policy (other key, other Content-Type, other size).
+#[derive(Debug, Serialize)]
+pub struct PresignedPost {
+    pub url: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+pub type ByteStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+pub struct ObjectBody {
+    pub content_length: i64, // Bytes in `stream`
+    pub content_type: Option<String>,
+    pub stream: ByteStream,
+}
+
+impl ObjectBody {
+    pub async fn bytes(self) -> Result<Vec<u8>> {
+        let chunks: Vec<Bytes> = self.stream.try_collect().await?;
+        Ok(chunks.concat())
+    }
+}
+
+/// Where file contents live. Selected by STORAGE_BACKEND; handlers only see
+/// `state.storage`. Keys are relative paths like `<folder_id>/<file_id>`.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: Option<&str>) -> Result<()>;
+
+    /// `Ok(None)` if the object doesn't exist. A range past the end is an error.
+    async fn get(&self, key: &str, range: Option<ByteRange>) -> Result<Option<ObjectBody>>;
+
+    /// Returns `None` if the object does not exist (yet).
+    async fn head(&self, key: &str) -> Result<Option<ObjectMetadata>>;
+
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Deletes many objects at once. Missing keys are not an error.
+    async fn delete_many(&self, keys: &[String]) -> Result<()>;
+
+    /// Copy within the store; no bytes go through this process.
+    async fn copy(&self, source_key: &str, dest_key: &str, size: i64) -> Result<()>;
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>>;
+
+    /// URL the client PUTs the object to, with the given Content-Type.
+    async fn presign_put(&self, key: &str, content_type: Option<&str>, expires_in: Duration) -> Result<String>;
+
+    async fn presign_get(&self, key: &str, overrides: &ResponseOverrides, expires_in: Duration) -> Result<String>;
+
+    /// POST policy for exactly `key`, `content_type` and `size` bytes.
+    async fn presign_post(&self, key: &str, content_type: &str, size: i64, expires_in: Duration) -> Result<PresignedPost>;
+
+    // Multipart uploads: parts are numbered 1..=10000 and assembled in order on completion
+
+    async fn create_multipart(&self, key: &str, content_type: Option<&str>) -> Result<String>;
+
+    /// Returns the part's ETag.
+    async fn upload_part(&self, key: &str, upload_id: &str, part_number: i32, data: Vec<u8>) -> Result<String>;
+
+    /// URL the client PUTs one part to; the response carries the part's ETag header.
+    async fn presign_upload_part(&self, key: &str, upload_id: &str, part_number: i32, expires_in: Duration) -> Result<String>;
+
+    async fn list_parts(&self, key: &str, upload_id: &str) -> Result<Vec<UploadedPart>>;
+
+    /// `parts` are (part number, ETag) in ascending order.
+    async fn complete_multipart(&self, key: &str, upload_id: &str, parts: &[(i32, String)]) -> Result<()>;
+
+    async fn abort_multipart(&self, key: &str, upload_id: &str) -> Result<()>;
+
+    /// The disk backend serves its signed URLs through our own routes, which need
+    /// the concrete type.
+    fn as_local(&self) -> Option<&LocalStorage> {
+        None
+    }
+}
+
+pub async fn from_config(config: &Config) -> Result<Arc<dyn StorageBackend>> {
+    match config.storage_backend.as_str() {
+        "s3" => Ok(Arc::new(S3Storage::new(config).await?)),
+        "local" => Ok(Arc::new(LocalStorage::new(config)?)),
+        other => bail!("Unknown STORAGE_BACKEND '{}' (expected 's3' or 'local')", other),
+    }
+}
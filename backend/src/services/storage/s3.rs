@@ -0,0 +1,474 @@
+use aws_sdk_s3::Client;
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::ByteStream as S3ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart, Delete, ObjectIdentifier};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures_util::{stream, StreamExt};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use anyhow::{bail, Result, Context};
+
+use crate::config::Config;
+use super::{ByteRange, ObjectBody, ObjectInfo, ObjectMetadata, PresignedPost, ResponseOverrides, StorageBackend, UploadedPart};
+
+// DeleteObjects accepts at most 1000 keys per request
+const DELETE_BATCH_SIZE: usize = 1000;
+
+// CopyObject is limited to 5 GiB; bigger objects are copied part by part
+const MAX_SINGLE_COPY: i64 = 5 * 1024 * 1024 * 1024;
+const COPY_PART_SIZE: i64 = 512 * 1024 * 1024;
+
+/// MinIO or any S3-compatible store. Uploads and downloads go straight between the
+/// client and the bucket through presigned URLs.
+pub struct S3Storage {
+    client: Client,
+    bucket: String,
+    // The SDK can't presign POST policies, so those are signed by hand
+    endpoint: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Storage {
+    pub async fn new(config: &Config) -> Result<Self> {
+        if config.s3_endpoint.is_empty() || config.s3_bucket.is_empty() {
+            bail!("S3_ENDPOINT and S3_BUCKET must be set for the s3 storage backend");
+        }
+
+        let s3_config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .endpoint_url(&config.s3_endpoint)
+            .region(aws_sdk_s3::config::Region::new(config.s3_region.clone()))
+            .credentials_provider(aws_sdk_s3::config::Credentials::new(
+                &config.s3_access_key,
+                &config.s3_secret_key,
+                None,
+                None,
+                "static",
+            ))
+            .load()
+            .await;
+
+        Ok(Self {
+            client: Client::new(&s3_config),
+            bucket: config.s3_bucket.clone(),
+            endpoint: config.s3_endpoint.trim_end_matches('/').to_string(),
+            region: config.s3_region.clone(),
+            access_key: config.s3_access_key.clone(),
+            secret_key: config.s3_secret_key.clone(),
+        })
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[async_trait]
+impl StorageBackend for S3Storage {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: Option<&str>) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_content_type(content_type.map(str::to_string))
+            .body(S3ByteStream::from(data))
+            .send()
+            .await
+            .context("Failed to put object")?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, range: Option<ByteRange>) -> Result<Option<ObjectBody>> {
+        let output = match self.client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_range(range.map(|r| r.header_value()))
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) if e.as_service_error().map(|se| se.is_no_such_key()).unwrap_or(false) => return Ok(None),
+            Err(e) => return Err(e).context("Failed to get object"),
+        };
+
+        let content_length = output.content_length().unwrap_or(0);
+        let content_type = output.content_type().map(|ct| ct.to_string());
+
+        let stream = stream::unfold(output.body, |mut body| async move {
+            body.next().await.map(|chunk| (chunk.map_err(std::io::Error::other), body))
+        });
+
+        Ok(Some(ObjectBody { content_length, content_type, stream: stream.boxed() }))
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<ObjectMetadata>> {
+        match self.client.head_object().bucket(&self.bucket).key(key).send().await {
+            Ok(output) => Ok(Some(ObjectMetadata {
+                size: output.content_length().unwrap_or(0),
+                content_type: output.content_type().map(|ct| ct.to_string()),
+            })),
+            Err(e) if e.as_service_error().map(|se| se.is_not_found()).unwrap_or(false) => Ok(None),
+            Err(e) => Err(e).context("Failed to HEAD object"),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .context("Failed to delete object")?;
+
+        Ok(())
+    }
+
+    async fn delete_many(&self, keys: &[String]) -> Result<()> {
+        for batch in keys.chunks(DELETE_BATCH_SIZE) {
+            let objects = batch
+                .iter()
+                .map(|key| ObjectIdentifier::builder().key(key).build())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .context("Failed to build object identifiers")?;
+
+            let delete = Delete::builder()
+                .set_objects(Some(objects))
+                .quiet(true)
+                .build()
+                .context("Failed to build delete request")?;
+
+            let output = self.client
+                .delete_objects()
+                .bucket(&self.bucket)
+                .delete(delete)
+                .send()
+                .await
+                .context("Failed to delete objects")?;
+
+            if let Some(err) = output.errors().first() {
+                anyhow::bail!(
+                    "Failed to delete {} object(s), first: {} ({})",
+                    output.errors().len(),
+                    err.key().unwrap_or_default(),
+                    err.message().unwrap_or_default(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn copy(&self, source_key: &str, dest_key: &str, size: i64) -> Result<()> {
+        let copy_source = format!("{}/{}", self.bucket, urlencoding::encode(source_key));
+
+        if size <= MAX_SINGLE_COPY {
+            self.client
+                .copy_object()
+                .bucket(&self.bucket)
+                .key(dest_key)
+                .copy_source(&copy_source)
+                .send()
+                .await
+                .context("Failed to copy object")?;
+            return Ok(());
+        }
+
+        let head = self.head(source_key)
+            .await?
+            .context("Copy source does not exist")?;
+        let upload_id = self.create_multipart(dest_key, head.content_type.as_deref()).await?;
+
+        let mut parts = Vec::new();
+        let mut start = 0;
+        let mut part_number = 1;
+        while start < size {
+            let end = (start + COPY_PART_SIZE).min(size) - 1;
+            let result = self.client
+                .upload_part_copy()
+                .bucket(&self.bucket)
+                .key(dest_key)
+                .upload_id(&upload_id)
+                .part_number(part_number)
+                .copy_source(&copy_source)
+                .copy_source_range(format!("bytes={}-{}", start, end))
+                .send()
+                .await;
+
+            let etag = match result {
+                Ok(output) => output.copy_part_result().and_then(|r| r.e_tag()).unwrap_or_default().to_string(),
+                Err(e) => {
+                    let _ = self.abort_multipart(dest_key, &upload_id).await;
+                    return Err(e).context("Failed to copy object part");
+                }
+            };
+
+            parts.push((part_number, etag));
+            start = end + 1;
+            part_number += 1;
+        }
+
+        if let Err(e) = self.complete_multipart(dest_key, &upload_id, &parts).await {
+            let _ = self.abort_multipart(dest_key, &upload_id).await;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+
+        // ListObjectsV2 is paginated at 1000 keys per page
+        loop {
+            let output = self.client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(prefix)
+                .set_continuation_token(token.clone())
+                .send()
+                .await
+                .context("Failed to list objects")?;
+
+            for object in output.contents() {
+                objects.push(ObjectInfo {
+                    key: object.key().unwrap_or_default().to_string(),
+                    size: object.size().unwrap_or(0),
+                });
+            }
+
+            if output.is_truncated().unwrap_or(false) {
+                token = output.next_continuation_token().map(|t| t.to_string());
+            } else {
+                break;
+            }
+        }
+
+        Ok(objects)
+    }
+
+    async fn presign_put(&self, key: &str, content_type: Option<&str>, expires_in: Duration) -> Result<String> {
+        let mut builder = self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key);
+
+        if let Some(ct) = content_type {
+            builder = builder.content_type(ct);
+        }
+
+        // AWS SDK requires configuring the expiration
+        let config = PresigningConfig::expires_in(expires_in)
+            .context("Failed to create presigning config")?;
+
+        let presigned_req = builder
+            .presigned(config)
+            .await
+            .context("Failed to generate presigned PUT URL")?;
+
+        Ok(presigned_req.uri().to_string())
+    }
+
+    async fn presign_get(&self, key: &str, overrides: &ResponseOverrides, expires_in: Duration) -> Result<String> {
+        let config = PresigningConfig::expires_in(expires_in)
+            .context("Failed to create presigning config")?;
+
+        let presigned_req = self.client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_response_content_type(overrides.content_type.clone())
+            .set_response_content_disposition(overrides.content_disposition.clone())
+            .presigned(config)
+            .await
+            .context("Failed to generate presigned GET URL")?;
+
+        Ok(presigned_req.uri().to_string())
+    }
+
+    // SigV4 POST policy: https://docs.aws.amazon.com/AmazonS3/latest/API/sigv4-HTTPPOSTConstructPolicy.html
+    async fn presign_post(&self, key: &str, content_type: &str, size: i64, expires_in: Duration) -> Result<PresignedPost> {
+        let now = chrono::Utc::now();
+        let date = now.format("%Y%m%d").to_string();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let credential = format!("{}/{}/{}/s3/aws4_request", self.access_key, date, self.region);
+        let expiration = now + chrono::Duration::from_std(expires_in).context("Invalid policy lifetime")?;
+
+        let policy = serde_json::json!({
+            "expiration": expiration.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+            "conditions": [
+                { "bucket": self.bucket },
+                { "key": key },
+                { "Content-Type": content_type },
+                ["content-length-range", size, size],
+                { "x-amz-algorithm": "AWS4-HMAC-SHA256" },
+                { "x-amz-credential": credential },
+                { "x-amz-date": amz_date },
+            ],
+        });
+        let policy = BASE64.encode(policy.to_string());
+
+        let date_key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        let region_key = hmac_sha256(&date_key, &self.region);
+        let service_key = hmac_sha256(&region_key, "s3");
+        let signing_key = hmac_sha256(&service_key, "aws4_request");
+        let signature = hex::encode(hmac_sha256(&signing_key, &policy));
+
+        let fields = BTreeMap::from([
+            ("key".to_string(), key.to_string()),
+            ("Content-Type".to_string(), content_type.to_string()),
+            ("policy".to_string(), policy),
+            ("x-amz-algorithm".to_string(), "AWS4-HMAC-SHA256".to_string()),
+            ("x-amz-credential".to_string(), credential),
+            ("x-amz-date".to_string(), amz_date),
+            ("x-amz-signature".to_string(), signature),
+        ]);
+
+        // Path-style, which MinIO and AWS both accept for POST
+        Ok(PresignedPost { url: format!("{}/{}", self.endpoint, self.bucket), fields })
+    }
+
+    async fn create_multipart(&self, key: &str, content_type: Option<&str>) -> Result<String> {
+        let mut builder = self.client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key);
+
+        if let Some(ct) = content_type {
+            builder = builder.content_type(ct);
+        }
+
+        let output = builder
+            .send()
+            .await
+            .context("Failed to create multipart upload")?;
+
+        output
+            .upload_id()
+            .map(|id| id.to_string())
+            .context("S3 returned no upload id")
+    }
+
+    async fn upload_part(&self, key: &str, upload_id: &str, part_number: i32, data: Vec<u8>) -> Result<String> {
+        let output = self.client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(S3ByteStream::from(data))
+            .send()
+            .await
+            .context("Failed to upload part")?;
+
+        output
+            .e_tag()
+            .map(|etag| etag.to_string())
+            .context("S3 returned no ETag for part")
+    }
+
+    async fn presign_upload_part(&self, key: &str, upload_id: &str, part_number: i32, expires_in: Duration) -> Result<String> {
+        let config = PresigningConfig::expires_in(expires_in)
+            .context("Failed to create presigning config")?;
+
+        let presigned_req = self.client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .presigned(config)
+            .await
+            .context("Failed to generate presigned UploadPart URL")?;
+
+        Ok(presigned_req.uri().to_string())
+    }
+
+    async fn list_parts(&self, key: &str, upload_id: &str) -> Result<Vec<UploadedPart>> {
+        let mut parts = Vec::new();
+        let mut marker: Option<String> = None;
+
+        // ListParts is paginated at 1000 parts per page
+        loop {
+            let output = self.client
+                .list_parts()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .set_part_number_marker(marker.clone())
+                .send()
+                .await
+                .context("Failed to list multipart parts")?;
+
+            for part in output.parts() {
+                parts.push(UploadedPart {
+                    part_number: part.part_number().unwrap_or(0),
+                    etag: part.e_tag().unwrap_or_default().to_string(),
+                    size: part.size().unwrap_or(0),
+                });
+            }
+
+            if output.is_truncated().unwrap_or(false) {
+                marker = output.next_part_number_marker().map(|m| m.to_string());
+            } else {
+                break;
+            }
+        }
+
+        Ok(parts)
+    }
+
+    async fn complete_multipart(&self, key: &str, upload_id: &str, parts: &[(i32, String)]) -> Result<()> {
+        let completed = CompletedMultipartUpload::builder()
+            .set_parts(Some(
+                parts
+                    .iter()
+                    .map(|(number, etag)| {
+                        CompletedPart::builder()
+                            .part_number(*number)
+                            .e_tag(etag)
+                            .build()
+                    })
+                    .collect(),
+            ))
+            .build();
+
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(completed)
+            .send()
+            .await
+            .context("Failed to complete multipart upload")?;
+
+        Ok(())
+    }
+
+    async fn abort_multipart(&self, key: &str, upload_id: &str) -> Result<()> {
+        self.client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+            .context("Failed to abort multipart upload")?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+//! RFC 6238 time-based one-time passwords (HMAC-SHA1, 6 digits, 30 second steps),
+//! the variant every authenticator app supports.
+
+use base32::Alphabet;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+const STEP: i64 = 30;
+const DIGITS: u32 = 6;
+const SKEW: i64 = 1; // Accept one step either side for clock drift
+
+const ALPHABET: Alphabet = Alphabet::Rfc4648 { padding: false };
+
+/// New 160-bit secret, base32 encoded.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base32::encode(ALPHABET, &bytes)
+}
+
+/// `otpauth://` URI for QR codes, see the Key Uri Format used by authenticator apps.
+pub fn otpauth_uri(secret: &str, account: &str, issuer: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        urlencoding::encode(issuer),
+        urlencoding::encode(account),
+        secret,
+        urlencoding::encode(issuer),
+        DIGITS,
+        STEP,
+    )
+}
+
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Dynamic truncation (RFC 4226 section 5.3)
+    let offset = (digest[19] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([digest[offset] & 0x7f, digest[offset + 1], digest[offset + 2], digest[offset + 3]]);
+    binary % 10u32.pow(DIGITS)
+}
+
+/// The code an authenticator app shows for the secret at `unix_time`.
+pub fn code_at(secret: &str, unix_time: i64) -> Option<String> {
+    let key = base32::decode(ALPHABET, secret)?;
+    Some(format!("{:0width$}", hotp(&key, (unix_time / STEP) as u64), width = DIGITS as usize))
+}
+
+/// Checks a code against the secret at `unix_time`. Returns the matching time step,
+/// which callers store to reject the same code being used twice.
+pub fn verify(secret: &str, code: &str, unix_time: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let key = base32::decode(ALPHABET, secret)?;
+
+    let current = unix_time / STEP;
+    (current - SKEW..=current + SKEW)
+        .filter(|step| *step >= 0)
+        .find(|step| hotp(&key, *step as u64) == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{code_at, verify};
+
+    // The RFC 6238 SHA-1 test key, "12345678901234567890" in base32
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn matches_the_rfc_test_vectors() {
+        // Appendix B lists 8 digits, apps show the last 6
+        for (time, code) in [(59, "287082"), (1111111109, "081804"), (1111111111, "050471"), (1234567890, "005924"), (2000000000, "279037")] {
+            assert_eq!(code_at(SECRET, time).as_deref(), Some(code));
+            assert_eq!(verify(SECRET, code, time), Some(time / 30));
+        }
+    }
+
+    #[test]
+    fn accepts_one_step_of_drift_either_way() {
+        let time = 1111111111;
+        let step = time / 30;
+        assert_eq!(verify(SECRET, "050471", time - 30), Some(step));
+        assert_eq!(verify(SECRET, "050471", time + 30), Some(step));
+        assert_eq!(verify(SECRET, "050471", time - 60), None);
+        assert_eq!(verify(SECRET, "050471", time + 60), None);
+    }
+
+    #[test]
+    fn rejects_malformed_codes_and_secrets() {
+        let time = 1111111111;
+        assert_eq!(verify(SECRET, " 050471 ", time), Some(time / 30));
+        for code in ["", "05047", "0504710", "05o471", "-50471"] {
+            assert_eq!(verify(SECRET, code, time), None, "{:?}", code);
+        }
+        assert_eq!(verify("not base32!", "050471", time), None);
+    }
+}
@@ -0,0 +1,268 @@
+use std::time::Duration;
+use anyhow::{Result, Context};
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{MySql, QueryBuilder};
+
+use crate::models::{File, Folder};
+use crate::services::cleanup::{purge_files, purge_folder_tree};
+use crate::state::AppState;
+
+// Soft delete. The deleted item itself keeps trashed_with = NULL and is what shows up in
+// the trash; its contents are stamped with trashed_with = <folder id> so they come back
+// (or get purged) together with it.
+
+/// Moves a folder and everything still inside it to the trash.
+pub async fn trash_folder(state: &AppState, folder: &Folder, user_id: &str) -> Result<()> {
+    // Don't descend into subfolders that were already trashed on their own
+    let descendants: Vec<String> = sqlx::query_scalar(
+        "WITH RECURSIVE tree AS (
+            SELECT id FROM folders WHERE id = ?
+            UNION ALL
+            SELECT f.id FROM folders f JOIN tree t ON f.parent_id = t.id WHERE f.deleted_at IS NULL
+        )
+        SELECT id FROM tree WHERE id <> ?",
+    )
+    .bind(&folder.id)
+    .bind(&folder.id)
+    .fetch_all(&state.db)
+    .await
+    .context("Failed to collect descendant folders")?;
+
+    let now = Utc::now().naive_utc();
+    let mut tx = state.db.begin().await?;
+
+    sqlx::query("UPDATE folders SET deleted_at = ?, deleted_by = ?, trashed_with = NULL WHERE id = ?")
+        .bind(now)
+        .bind(user_id)
+        .bind(&folder.id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to trash folder")?;
+
+    if !descendants.is_empty() {
+        stamp_folders(&descendants, now, user_id, &folder.id)
+            .build()
+            .execute(&mut *tx)
+            .await
+            .context("Failed to trash subfolders")?;
+    }
+
+    let mut containing = descendants.clone();
+    containing.push(folder.id.clone());
+    stamp_files_in(&containing, now, user_id, &folder.id)
+        .build()
+        .execute(&mut *tx)
+        .await
+        .context("Failed to trash files")?;
+
+    tx.commit().await?;
+
+    let _ = state.cache.purge_cached_entries(&containing, &[]).await;
+    let _ = state.cache.invalidate_folder_listing(folder.parent_id.as_deref().unwrap_or("root")).await;
+
+    Ok(())
+}
+
+pub async fn trash_file(state: &AppState, file: &File, user_id: &str) -> Result<()> {
+    sqlx::query("UPDATE files SET deleted_at = ?, deleted_by = ?, trashed_with = NULL WHERE id = ?")
+        .bind(Utc::now().naive_utc())
+        .bind(user_id)
+        .bind(&file.id)
+        .execute(&state.db)
+        .await
+        .context("Failed to trash file")?;
+
+    let _ = state.cache.purge_cached_entries(&[], std::slice::from_ref(&file.id)).await;
+    let _ = state.cache.invalidate_folder_listing(file.folder_id.as_deref().unwrap_or("root")).await;
+
+    Ok(())
+}
+
+/// Restores a trashed folder with its contents. Returns the parent it was restored into
+/// (`None` = root, used when the original parent no longer exists or is itself in the trash).
+pub async fn restore_folder(state: &AppState, folder: &Folder) -> Result<Option<String>> {
+    let parent_id = live_folder(state, folder.parent_id.as_deref()).await;
+
+    let mut tx = state.db.begin().await?;
+
+    sqlx::query("UPDATE folders SET deleted_at = NULL, deleted_by = NULL, parent_id = ? WHERE id = ?")
+        .bind(&parent_id)
+        .bind(&folder.id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to restore folder")?;
+
+    sqlx::query("UPDATE folders SET deleted_at = NULL, deleted_by = NULL, trashed_with = NULL WHERE trashed_with = ?")
+        .bind(&folder.id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to restore subfolders")?;
+
+    sqlx::query("UPDATE files SET deleted_at = NULL, deleted_by = NULL, trashed_with = NULL WHERE trashed_with = ?")
+        .bind(&folder.id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to restore files")?;
+
+    tx.commit().await?;
+
+    let _ = state.cache.invalidate_folder_listing(parent_id.as_deref().unwrap_or("root")).await;
+    if parent_id != folder.parent_id {
+        // Restored somewhere else, so it inherits from different ancestors now
+        let _ = state.cache.bump_acl_epoch().await;
+    }
+
+    Ok(parent_id)
+}
+
+pub async fn restore_file(state: &AppState, file: &File) -> Result<Option<String>> {
+    let folder_id = live_folder(state, file.folder_id.as_deref()).await;
+
+    sqlx::query("UPDATE files SET deleted_at = NULL, deleted_by = NULL, folder_id = ? WHERE id = ?")
+        .bind(&folder_id)
+        .bind(&file.id)
+        .execute(&state.db)
+        .await
+        .context("Failed to restore file")?;
+
+    let _ = state.cache.invalidate_folder_listing(folder_id.as_deref().unwrap_or("root")).await;
+
+    Ok(folder_id)
+}
+
+/// Permanently deletes trash entries older than the configured retention.
+pub async fn purge_expired(state: &AppState) -> Result<()> {
+    let cutoff = Utc::now().naive_utc() - chrono::Duration::days(state.config.trash_retention_days);
+
+    let folders: Vec<Folder> = sqlx::query_as("SELECT * FROM folders WHERE deleted_at < ? AND trashed_with IS NULL")
+        .bind(cutoff)
+        .fetch_all(&state.db)
+        .await?;
+
+    for folder in &folders {
+        let purged = match detach_own_trash(state, folder).await {
+            Ok(()) => purge_folder_tree(state, folder).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = purged {
+            tracing::warn!("Failed to purge trashed folder {}: {:?}", folder.id, e);
+        }
+    }
+
+    let files: Vec<File> = sqlx::query_as("SELECT * FROM files WHERE deleted_at < ? AND trashed_with IS NULL")
+        .bind(cutoff)
+        .fetch_all(&state.db)
+        .await?;
+
+    if let Err(e) = purge_files(state, &files).await {
+        tracing::warn!("Failed to purge {} trashed file(s): {:?}", files.len(), e);
+    }
+
+    if !folders.is_empty() || !files.is_empty() {
+        tracing::info!("Trash purge removed {} folder(s) and {} file(s)", folders.len(), files.len());
+    }
+
+    Ok(())
+}
+
+/// Moves whatever inside `folder` was trashed on its own, before the folder was, out of
+/// the tree: it has its own retention and must survive the folder's purge. Restoring it
+/// then lands in the root, as it would once its parent is gone anyway.
+async fn detach_own_trash(state: &AppState, folder: &Folder) -> Result<()> {
+    let folder_ids: Vec<String> = sqlx::query_scalar(
+        "WITH RECURSIVE tree AS (
+            SELECT id FROM folders WHERE id = ?
+            UNION ALL
+            SELECT f.id FROM folders f JOIN tree t ON f.parent_id = t.id
+        )
+        SELECT folders.id FROM folders JOIN tree ON folders.id = tree.id
+        WHERE folders.id <> ? AND folders.deleted_at IS NOT NULL AND folders.trashed_with IS NULL",
+    )
+    .bind(&folder.id)
+    .bind(&folder.id)
+    .fetch_all(&state.db)
+    .await
+    .context("Failed to collect separately trashed folders")?;
+
+    let file_ids: Vec<String> = sqlx::query_scalar(
+        "WITH RECURSIVE tree AS (
+            SELECT id FROM folders WHERE id = ?
+            UNION ALL
+            SELECT f.id FROM folders f JOIN tree t ON f.parent_id = t.id
+        )
+        SELECT files.id FROM files JOIN tree ON files.folder_id = tree.id
+        WHERE files.deleted_at IS NOT NULL AND files.trashed_with IS NULL",
+    )
+    .bind(&folder.id)
+    .fetch_all(&state.db)
+    .await
+    .context("Failed to collect separately trashed files")?;
+
+    for (table, column, ids) in [("folders", "parent_id", &folder_ids), ("files", "folder_id", &file_ids)] {
+        if ids.is_empty() {
+            continue;
+        }
+        let mut builder: QueryBuilder<MySql> = QueryBuilder::new(format!("UPDATE {} SET {} = NULL WHERE id IN (", table, column));
+        let mut separated = builder.separated(", ");
+        for id in ids {
+            separated.push_bind(id);
+        }
+        separated.push_unseparated(")");
+        builder
+            .build()
+            .execute(&state.db)
+            .await
+            .context("Failed to detach separately trashed items")?;
+    }
+
+    Ok(())
+}
+
+/// Background loop spawned from `main`.
+pub async fn run_purge_task(state: AppState) {
+    let mut interval = tokio::time::interval(Duration::from_secs(state.config.trash_purge_interval));
+    loop {
+        interval.tick().await;
+        if let Err(e) = purge_expired(&state).await {
+            tracing::warn!("Trash purge failed: {:?}", e);
+        }
+    }
+}
+
+// Returns the folder id if it still exists outside the trash, otherwise None (root).
+async fn live_folder(state: &AppState, folder_id: Option<&str>) -> Option<String> {
+    let folder_id = folder_id?;
+    sqlx::query_scalar("SELECT id FROM folders WHERE id = ? AND deleted_at IS NULL")
+        .bind(folder_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+}
+
+fn stamp_folders<'a>(ids: &'a [String], now: NaiveDateTime, user_id: &'a str, root_id: &'a str) -> QueryBuilder<'a, MySql> {
+    let mut builder: QueryBuilder<MySql> = QueryBuilder::new("UPDATE folders SET deleted_at = ");
+    builder.push_bind(now)
+        .push(", deleted_by = ").push_bind(user_id)
+        .push(", trashed_with = ").push_bind(root_id)
+        .push(" WHERE id IN (");
+    let mut separated = builder.separated(", ");
+    for id in ids {
+        separated.push_bind(id);
+    }
+    separated.push_unseparated(")");
+    builder
+}
+
+fn stamp_files_in<'a>(folder_ids: &'a [String], now: NaiveDateTime, user_id: &'a str, root_id: &'a str) -> QueryBuilder<'a, MySql> {
+    let mut builder: QueryBuilder<MySql> = QueryBuilder::new("UPDATE files SET deleted_at = ");
+    builder.push_bind(now)
+        .push(", deleted_by = ").push_bind(user_id)
+        .push(", trashed_with = ").push_bind(root_id)
+        .push(" WHERE deleted_at IS NULL AND folder_id IN (");
+    let mut separated = builder.separated(", ");
+    for id in folder_ids {
+        separated.push_bind(id);
+    }
+    separated.push_unseparated(")");
+    builder
+}
@@ -0,0 +1,123 @@
+use anyhow::{Result, Context};
+
+use crate::models::{File, Folder};
+use crate::services::cleanup::{purge_files, purge_folder_tree};
+use crate::state::AppState;
+
+// What happens to a user's content before the account row is deleted.
+// folders.owner_id / files.owner_id are ON DELETE RESTRICT, so one of these must run first.
+
+/// Number of folders and files (trashed or not) owned by the user.
+pub async fn owned_item_count(state: &AppState, user_id: &str) -> Result<i64> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT (SELECT COUNT(*) FROM folders WHERE owner_id = ?) + (SELECT COUNT(*) FROM files WHERE owner_id = ?)",
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(&state.db)
+    .await
+    .context("Failed to count owned items")?;
+
+    Ok(count)
+}
+
+/// Hands every folder and file owned by `from` over to `to`, including their storage usage.
+pub async fn transfer_ownership(state: &AppState, from: &str, to: &str) -> Result<()> {
+    let folders: Vec<Folder> = sqlx::query_as("SELECT * FROM folders WHERE owner_id = ?")
+        .bind(from)
+        .fetch_all(&state.db)
+        .await
+        .context("Failed to load owned folders")?;
+
+    let files: Vec<File> = sqlx::query_as("SELECT * FROM files WHERE owner_id = ?")
+        .bind(from)
+        .fetch_all(&state.db)
+        .await
+        .context("Failed to load owned files")?;
+
+    let mut tx = state.db.begin().await?;
+
+    sqlx::query("UPDATE folders SET owner_id = ? WHERE owner_id = ?")
+        .bind(to)
+        .bind(from)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to transfer folders")?;
+
+    sqlx::query("UPDATE files SET owner_id = ? WHERE owner_id = ?")
+        .bind(to)
+        .bind(from)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to transfer files")?;
+
+    tx.commit().await?;
+
+    // Ownership decides roles on whole subtrees, but only for the two owners
+    let _ = state.cache.bump_user_acl_epoch(from).await;
+    let _ = state.cache.bump_user_acl_epoch(to).await;
+
+    let committed: i64 = files.iter().filter(|f| f.status == "committed").map(|f| f.size).sum();
+    if committed > 0 {
+        let _ = state.cache.decrement_usage(from, committed).await;
+        let _ = state.cache.increment_usage(to, committed).await;
+    }
+
+    // Cached metas and listings still carry the old owner_id
+    let mut folder_ids: Vec<String> = folders.into_iter().map(|f| f.id).collect();
+    folder_ids.extend(files.iter().filter_map(|f| f.folder_id.clone()));
+    folder_ids.sort();
+    folder_ids.dedup();
+    let file_ids: Vec<String> = files.into_iter().map(|f| f.id).collect();
+    let _ = state.cache.purge_cached_entries(&folder_ids, &file_ids).await;
+
+    Ok(())
+}
+
+/// Permanently deletes everything the user owns. Refuses (returns `Ok(false)`) when the user's
+/// folders contain content owned by someone else, since purging would take that with it.
+pub async fn purge_owned_content(state: &AppState, user_id: &str) -> Result<bool> {
+    let foreign: i64 = sqlx::query_scalar(
+        "SELECT
+            (SELECT COUNT(*) FROM files f JOIN folders d ON f.folder_id = d.id WHERE d.owner_id = ? AND f.owner_id <> ?)
+          + (SELECT COUNT(*) FROM folders c JOIN folders p ON c.parent_id = p.id WHERE p.owner_id = ? AND c.owner_id <> ?)",
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(&state.db)
+    .await
+    .context("Failed to check for shared content")?;
+
+    if foreign > 0 {
+        return Ok(false);
+    }
+
+    // Top of each owned subtree: parent is missing or belongs to someone else
+    let roots: Vec<Folder> = sqlx::query_as(
+        "SELECT c.* FROM folders c LEFT JOIN folders p ON c.parent_id = p.id
+         WHERE c.owner_id = ? AND (p.id IS NULL OR p.owner_id <> ?)",
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_all(&state.db)
+    .await
+    .context("Failed to load owned folders")?;
+
+    for folder in &roots {
+        purge_folder_tree(state, folder).await?;
+    }
+
+    // Whatever is left sits in the root or in other users' folders
+    let files: Vec<File> = sqlx::query_as("SELECT * FROM files WHERE owner_id = ?")
+        .bind(user_id)
+        .fetch_all(&state.db)
+        .await
+        .context("Failed to load owned files")?;
+
+    purge_files(state, &files).await?;
+
+    Ok(true)
+}
+
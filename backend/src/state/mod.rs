@@ -1,49 +1,122 @@
 use sqlx::MySqlPool;
-use redis::Client;
-use aws_sdk_s3::Client as S3Client;
 use std::sync::Arc;
+use anyhow::{bail, Context, Result};
 use crate::config::Config;
+use crate::services::auth::JwtKeys;
+use crate::services::authenticator::{self, Authenticator};
+use crate::services::mailer::{self, Mailer};
+use crate::services::redis_cache::{Cache, RedisCache};
+use crate::services::storage::{self, StorageBackend};
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: MySqlPool,
-    pub redis: Client,
-    pub s3: S3Client,
+    pub cache: Arc<dyn Cache>,
+    pub storage: Arc<dyn StorageBackend>,
     pub config: Arc<Config>,
+    pub jwt_keys: Arc<JwtKeys>,
+    pub mailer: Arc<dyn Mailer>,
+    pub authenticators: Vec<Arc<dyn Authenticator>>,
 }
 
 impl AppState {
     pub async fn new(config: Arc<Config>) -> Self {
+        Self::builder(config).build().await.expect("Failed to initialize app state")
+    }
+
+    /// Anything not handed to the builder is created from the config, as `new` does.
+    /// Tests use this to swap in `MemoryCache` and `MemoryStorage`.
+    pub fn builder(config: Arc<Config>) -> AppStateBuilder {
+        AppStateBuilder { config, db: None, cache: None, storage: None, mailer: None }
+    }
+}
+
+pub struct AppStateBuilder {
+    config: Arc<Config>,
+    db: Option<MySqlPool>,
+    cache: Option<Arc<dyn Cache>>,
+    storage: Option<Arc<dyn StorageBackend>>,
+    mailer: Option<Arc<dyn Mailer>>,
+}
+
+impl AppStateBuilder {
+    pub fn db(mut self, db: MySqlPool) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    pub fn cache(mut self, cache: Arc<dyn Cache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn storage(mut self, storage: Arc<dyn StorageBackend>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn mailer(mut self, mailer: Arc<dyn Mailer>) -> Self {
+        self.mailer = Some(mailer);
+        self
+    }
+
+    pub async fn build(self) -> Result<AppState> {
+        let config = self.config;
+
+        // Proxy download links are signed with the storage secret
+        match config.download_mode.as_str() {
+            "presigned" => {},
+            "proxy" if !config.storage_signing_secret.is_empty() => {},
+            "proxy" => bail!("STORAGE_SIGNING_SECRET must be set for DOWNLOAD_MODE=proxy"),
+            other => bail!("Unknown DOWNLOAD_MODE '{}' (expected 'presigned' or 'proxy')", other),
+        }
+
         // Connect to Database
-        let db = MySqlPool::connect(&config.database_url)
-            .await
-            .expect("Failed to connect to MySQL");
+        let db = match self.db {
+            Some(db) => db,
+            None => MySqlPool::connect(&config.database_url)
+                .await
+                .context("Failed to connect to MySQL")?,
+        };
 
         // Connect to Redis
-        let redis = Client::open(config.redis_url.clone())
-            .expect("Failed to create Redis client");
-        
-        // Connect to MinIO / S3
-        let s3_config = aws_config::from_env()
-            .endpoint_url(&config.s3_endpoint)
-            .region(aws_sdk_s3::config::Region::new(config.s3_region.clone()))
-            .credentials_provider(aws_sdk_s3::config::Credentials::new(
-                &config.s3_access_key,
-                &config.s3_secret_key,
-                None,
-                None,
-                "static",
-            ))
-            .load()
-            .await;
-        
-        let s3 = S3Client::new(&s3_config);
-
-        Self {
+        let cache: Arc<dyn Cache> = match self.cache {
+            Some(cache) => cache,
+            None => Arc::new(RedisCache::new(
+                redis::Client::open(config.redis_url.clone()).context("Failed to create Redis client")?,
+            )),
+        };
+
+        // MinIO / S3 or local disk
+        let storage = match self.storage {
+            Some(storage) => storage,
+            None => storage::from_config(&config).await.context("Failed to configure storage")?,
+        };
+
+        // Browsers can't POST a form to the local backend
+        match config.upload_mode.as_str() {
+            "put" => {},
+            "post" if storage.as_local().is_none() => {},
+            "post" => bail!("UPLOAD_MODE=post needs the s3 storage backend"),
+            other => bail!("Unknown UPLOAD_MODE '{}' (expected 'put' or 'post')", other),
+        }
+
+        let mailer = match self.mailer {
+            Some(mailer) => mailer,
+            None => mailer::from_config(&config).context("Failed to configure mailer")?,
+        };
+
+        let jwt_keys = Arc::new(JwtKeys::from_config(&config).context("Failed to load JWT keys")?);
+        let authenticators = authenticator::from_config(&config).context("Failed to configure auth backends")?;
+
+        Ok(AppState {
             db,
-            redis,
-            s3,
+            cache,
+            storage,
             config,
-        }
+            jwt_keys,
+            mailer,
+            authenticators,
+        })
     }
 }
@@ -0,0 +1,906 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use backend::services::auth::{create_jwt, verify_jwt};
+use backend::services::oidc::{self, PendingLogin};
+use backend::services::totp;
+use common::{test_config, TestApp};
+use serde_json::json;
+
+// Routes that never reach MySQL
+
+#[tokio::test]
+async fn jwks_is_served_without_auth() {
+    let app = TestApp::without_db().await;
+
+    let (status, body) = app.json("GET", "/.well-known/jwks.json", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    // HS256 has no public keys to publish
+    assert_eq!(body, json!({ "keys": [] }));
+}
+
+#[tokio::test]
+async fn protected_route_requires_token() {
+    let app = TestApp::without_db().await;
+
+    let (status, _) = app.request("POST", "/api/folders", None, Some(json!({ "name": "Docs" }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn tampered_token_is_rejected() {
+    let app = TestApp::without_db().await;
+    let token = create_jwt("u-1", "admin", 0, &app.state.config, &app.state.jwt_keys).unwrap();
+
+    let (header_and_claims, signature) = token.rsplit_once('.').unwrap();
+    let flipped = if signature.starts_with('A') { 'B' } else { 'A' };
+    let tampered = format!("{}.{}{}", header_and_claims, flipped, &signature[1..]);
+
+    let (status, body) = app.request("POST", "/api/folders", Some(&tampered), Some(json!({ "name": "Docs" }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body, b"Invalid token");
+}
+
+#[tokio::test]
+async fn revoked_access_token_is_rejected() {
+    let app = TestApp::without_db().await;
+    let token = create_jwt("u-1", "admin", 0, &app.state.config, &app.state.jwt_keys).unwrap();
+    let claims = verify_jwt(&token, &app.state.jwt_keys).unwrap();
+    app.state.cache.revoke_jti(&claims.jti, 60).await.unwrap();
+
+    let (status, body) = app.request("POST", "/api/folders", Some(&token), Some(json!({ "name": "Docs" }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body, b"Token revoked or account deactivated");
+}
+
+#[tokio::test]
+async fn role_cached_after_an_acl_change_is_ignored() {
+    let app = TestApp::without_db().await;
+    let cache = &app.state.cache;
+
+    // A lookup starts, a grant changes meanwhile, then the lookup writes its result
+    let before = cache.check_permission("f-1", "u-1").await.unwrap();
+    cache.bump_acl_epoch().await.unwrap();
+    cache.cache_permission("f-1", "u-1", "editor", &before.epoch).await.unwrap();
+    assert_eq!(cache.check_permission("f-1", "u-1").await.unwrap().role, None);
+
+    let current = cache.check_permission("f-1", "u-1").await.unwrap();
+    cache.cache_permission("f-1", "u-1", "viewer", &current.epoch).await.unwrap();
+    assert_eq!(cache.check_permission("f-1", "u-1").await.unwrap().role.as_deref(), Some("viewer"));
+}
+
+#[tokio::test]
+async fn a_grant_change_only_drops_the_grantees_cached_roles() {
+    let app = TestApp::without_db().await;
+    let cache = &app.state.cache;
+
+    for user in ["u-1", "u-2"] {
+        let current = cache.check_permission("f-1", user).await.unwrap();
+        cache.cache_permission("f-1", user, "viewer", &current.epoch).await.unwrap();
+    }
+
+    cache.bump_user_acl_epoch("u-1").await.unwrap();
+    assert_eq!(cache.check_permission("f-1", "u-1").await.unwrap().role, None);
+    assert_eq!(cache.check_permission("f-1", "u-2").await.unwrap().role.as_deref(), Some("viewer"));
+
+    // A move still affects everyone
+    cache.bump_acl_epoch().await.unwrap();
+    assert_eq!(cache.check_permission("f-1", "u-2").await.unwrap().role, None);
+}
+
+#[tokio::test]
+async fn cors_only_admits_the_frontend() {
+    let app = TestApp::without_db().await;
+    let frontend = app.state.config.app_url.trim_end_matches('/').to_string();
+
+    let preflight = |origin: &str| Request::builder()
+        .method("OPTIONS")
+        .uri("/api/auth/oidc/link")
+        .header(header::ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization")
+        .body(Body::empty())
+        .unwrap();
+
+    let (_, headers, _) = app.send(preflight(&frontend)).await;
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], frontend.as_str());
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+
+    let (_, headers, _) = app.send(preflight("https://evil.example")).await;
+    assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+}
+
+#[tokio::test]
+async fn oidc_callback_needs_the_browser_that_started_it() {
+    let app = TestApp::without_db().await;
+    let pending = PendingLogin { code_verifier: "v".into(), nonce: "n".into(), link_user_id: Some("u-1".into()) };
+
+    let callback = |cookie: Option<String>| {
+        let mut builder = Request::builder().uri("/api/auth/oidc/callback?code=c&state=s-1");
+        if let Some(cookie) = cookie {
+            builder = builder.header(header::COOKIE, cookie);
+        }
+        builder.body(Body::empty()).unwrap()
+    };
+
+    // Someone else's link handed to this browser
+    app.state.cache.store_oidc_login("s-1", &serde_json::to_string(&pending).unwrap(), 60).await.unwrap();
+    let (status, headers, _) = app.send(callback(None)).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert!(headers[header::LOCATION].to_str().unwrap().contains("another%20browser"));
+    assert!(headers[header::SET_COOKIE].to_str().unwrap().contains("Max-Age=0"));
+
+    // The initiator's own browser gets past the check (and on to the unconfigured provider)
+    app.state.cache.store_oidc_login("s-1", &serde_json::to_string(&pending).unwrap(), 60).await.unwrap();
+    let cookie = format!("other=1; {}={}", oidc::BINDING_COOKIE, oidc::browser_binding("s-1", Some("u-1")));
+    let (_, headers, _) = app.send(callback(Some(cookie))).await;
+    assert!(headers[header::LOCATION].to_str().unwrap().contains("not%20configured"));
+}
+
+#[tokio::test]
+async fn spoofed_forwarded_for_does_not_dodge_the_ip_lockout() {
+    let mut config = test_config();
+    config.trust_proxy = true;
+    let app = TestApp::without_db_config(config).await;
+    app.state.cache.record_login_failure("ip:10.0.0.1", 1, 1, 900).await.unwrap();
+
+    // The proxy appends the real client address after whatever the client sent
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/auth/login")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header("x-forwarded-for", "203.0.113.7, 10.0.0.1")
+        .body(Body::from(json!({ "email": "tom@example.com", "password": "guess" }).to_string()))
+        .unwrap();
+    let (status, _, _) = app.send(request).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn proxy_downloads_need_a_signing_secret() {
+    let mut config = test_config();
+    config.download_mode = "proxy".to_string();
+    config.storage_signing_secret = String::new();
+
+    let db = sqlx::MySqlPool::connect_lazy(&config.database_url).unwrap();
+    let result = backend::state::AppState::builder(std::sync::Arc::new(config)).db(db).build().await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn post_uploads_need_s3() {
+    let mut config = test_config();
+    config.upload_mode = "post".to_string();
+    config.storage_backend = "local".to_string();
+    config.storage_signing_secret = "local-test-secret".to_string();
+    config.local_storage_path = std::env::temp_dir().join(format!("ferrum-test-{}", uuid::Uuid::new_v4())).display().to_string();
+
+    let db = sqlx::MySqlPool::connect_lazy(&config.database_url).unwrap();
+    let result = backend::state::AppState::builder(std::sync::Arc::new(config.clone())).db(db).build().await;
+    assert!(result.is_err());
+
+    let _ = std::fs::remove_dir_all(&config.local_storage_path);
+}
+
+// End to end against a migrated database (TEST_DATABASE_URL), run with --ignored
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn login_issues_tokens_for_valid_credentials() {
+    let app = TestApp::with_db().await;
+    let user_id = app.seed_user("alice@example.com", "correct horse", "osis").await;
+
+    let (status, body) = app.json("POST", "/api/auth/login", None, Some(json!({
+        "email": "alice@example.com",
+        "password": "correct horse",
+    }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["token"].is_string());
+    assert!(body["refresh_token"].is_string());
+    assert_eq!(body["user"]["id"], user_id);
+
+    let (status, _) = app.request("POST", "/api/auth/login", None, Some(json!({
+        "email": "alice@example.com",
+        "password": "wrong",
+    }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn create_and_list_folders() {
+    let app = TestApp::with_db().await;
+    app.seed_user("bob@example.com", "hunter22", "osis").await;
+    let token = app.login("bob@example.com", "hunter22").await;
+
+    let (status, created) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "Reports" }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let folder_id = created["id"].as_str().unwrap().to_string();
+
+    let (status, root) = app.json("GET", "/api/folders/root", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<&str> = root["subfolders"].as_array().unwrap().iter().map(|f| f["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["Reports"]);
+
+    let (status, _) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "2026", "parent_id": folder_id }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, listing) = app.json("GET", &format!("/api/folders/{}", folder_id), Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listing["folder"]["id"], folder_id);
+    assert_eq!(listing["subfolders"][0]["name"], "2026");
+    assert_eq!(listing["files"], json!([]));
+
+    // Anonymous users can't see a private folder
+    let (status, _) = app.request("GET", &format!("/api/folders/{}", folder_id), None, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn upload_complete_and_download_file() {
+    let app = TestApp::with_db().await;
+    app.seed_user("carol@example.com", "s3cret!", "media_guru").await;
+    let token = app.login("carol@example.com", "s3cret!").await;
+
+    let (_, created) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "Photos" }))).await;
+    let folder_id = created["id"].as_str().unwrap().to_string();
+
+    let (status, upload) = app.json("POST", "/api/files/upload", Some(&token), Some(json!({
+        "name": "cat.png",
+        "folder_id": folder_id,
+        "size": 4,
+        "mime_type": "image/png",
+    }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let file_id = upload["file_id"].as_str().unwrap().to_string();
+    assert!(upload["presigned_url"].as_str().unwrap().starts_with("memory://"));
+
+    // Completing before the object exists is refused
+    let (status, _) = app.request("POST", &format!("/api/files/{}/complete", file_id), Some(&token), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Stand in for the client's PUT to the presigned URL
+    let storage_key = format!("{}/{}", folder_id, file_id);
+    app.state.storage.put(&storage_key, b"\x89PNG".to_vec(), Some("image/png")).await.unwrap();
+
+    let (status, file) = app.json("POST", &format!("/api/files/{}/complete", file_id), Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(file["status"], "committed");
+
+    // The first listing fills the cache, the second is served from it
+    for _ in 0..2 {
+        let (status, listing) = app.json("GET", &format!("/api/folders/{}", folder_id), Some(&token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(listing["files"][0]["id"], file_id);
+        assert_eq!(listing["files"][0]["name"], "cat.png");
+    }
+
+    let (status, download) = app.json("GET", &format!("/api/files/{}/download", file_id), Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let url = download["url"].as_str().unwrap();
+    assert!(url.starts_with(&format!("memory://{}?method=GET", storage_key)));
+    // Named after the file, not its storage key
+    assert!(url.contains("&response-content-type=image%2Fpng"));
+    let attachment = urlencoding::encode("attachment; filename=\"cat.png\"; filename*=UTF-8''cat.png").into_owned();
+    assert!(url.contains(&format!("&response-content-disposition={}", attachment)));
+
+    let (status, download) = app.json("GET", &format!("/api/files/{}/download?disposition=inline", file_id), Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let inline = urlencoding::encode("inline; filename=\"cat.png\"; filename*=UTF-8''cat.png").into_owned();
+    assert!(download["url"].as_str().unwrap().contains(&inline));
+
+    let (status, _) = app.request("GET", &format!("/api/files/{}/download?disposition=sideways", file_id), Some(&token), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = app.request("GET", &format!("/api/files/{}/download", file_id), None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let object = app.state.storage.get(&storage_key, None).await.unwrap().unwrap();
+    assert_eq!(object.bytes().await.unwrap(), b"\x89PNG".as_slice());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn content_supports_ranges_and_validators() {
+    let app = TestApp::with_db().await;
+    app.seed_user("dave@example.com", "pa55word", "osis").await;
+    let token = app.login("dave@example.com", "pa55word").await;
+
+    let (_, created) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "Videos" }))).await;
+    let folder_id = created["id"].as_str().unwrap().to_string();
+    let file_id = app.commit_file(&token, &folder_id, "Räume 1.mp4", b"0123456789", "video/mp4").await;
+    let uri = format!("/api/files/{}/content", file_id);
+
+    let get = |headers: &[(header::HeaderName, &str)]| {
+        let mut builder = Request::builder().uri(&uri).header(header::AUTHORIZATION, format!("Bearer {}", token));
+        for (name, value) in headers {
+            builder = builder.header(name, *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    };
+
+    let (status, headers, body) = app.send(get(&[])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, b"0123456789");
+    assert_eq!(headers[header::CONTENT_TYPE], "video/mp4");
+    assert_eq!(headers[header::ACCEPT_RANGES], "bytes");
+    assert_eq!(headers[header::CONTENT_DISPOSITION], "inline; filename=\"R_ume 1.mp4\"; filename*=UTF-8''R%C3%A4ume%201.mp4");
+    let etag = headers[header::ETAG].to_str().unwrap().to_string();
+    let last_modified = headers[header::LAST_MODIFIED].to_str().unwrap().to_string();
+
+    let (status, headers, body) = app.send(get(&[(header::RANGE, "bytes=2-5")])).await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body, b"2345");
+    assert_eq!(headers[header::CONTENT_RANGE], "bytes 2-5/10");
+
+    let (status, _, body) = app.send(get(&[(header::RANGE, "bytes=-3")])).await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body, b"789");
+
+    let (status, headers, _) = app.send(get(&[(header::RANGE, "bytes=10-")])).await;
+    assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(headers[header::CONTENT_RANGE], "bytes */10");
+
+    // A stale If-Range gets the whole file instead of a slice of the wrong one
+    let (status, _, body) = app.send(get(&[(header::RANGE, "bytes=2-5"), (header::IF_RANGE, "\"other\"")])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, b"0123456789");
+
+    let (status, _, body) = app.send(get(&[(header::IF_NONE_MATCH, &etag)])).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert!(body.is_empty());
+
+    let (status, _, _) = app.send(get(&[(header::IF_MODIFIED_SINCE, &last_modified)])).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+    let (status, _) = app.request("GET", &uri, None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn proxy_mode_hands_out_signed_content_links() {
+    let mut config = test_config();
+    config.download_mode = "proxy".to_string();
+    config.storage_signing_secret = "proxy-test-secret".to_string();
+    config.storage_public_url = "https://files.example.com".to_string();
+
+    let app = TestApp::with_db_config(config).await;
+    app.seed_user("erin@example.com", "letmein1", "admin").await;
+    let token = app.login("erin@example.com", "letmein1").await;
+
+    let (_, created) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "Music" }))).await;
+    let folder_id = created["id"].as_str().unwrap().to_string();
+    let file_id = app.commit_file(&token, &folder_id, "song.mp3", b"ID3 tag", "audio/mpeg").await;
+
+    let (status, download) = app.json("GET", &format!("/api/files/{}/download", file_id), Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let url = download["url"].as_str().unwrap();
+    let path = url.strip_prefix("https://files.example.com").unwrap();
+    assert!(path.starts_with(&format!("/api/files/{}/content?expires=", file_id)));
+    assert!(path.ends_with("&disposition=attachment"));
+
+    // The link works without the bearer token, like a presigned URL
+    let (status, body) = app.request("GET", path, None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, b"ID3 tag");
+
+    let tampered = path.replace("signature=", "signature=00");
+    let (status, _) = app.request("GET", &tampered, None, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn post_policy_pins_declared_size_and_type() {
+    let mut config = test_config();
+    config.upload_mode = "post".to_string();
+
+    let app = TestApp::with_db_config(config).await;
+    app.seed_user("frank@example.com", "trustno1", "osis").await;
+    let token = app.login("frank@example.com", "trustno1").await;
+
+    let (_, created) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "Scans" }))).await;
+    let folder_id = created["id"].as_str().unwrap().to_string();
+
+    let (status, _) = app.request("POST", "/api/files/upload", Some(&token), Some(json!({
+        "name": "scan.pdf", "folder_id": folder_id, "size": -1, "mime_type": "application/pdf",
+    }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, upload) = app.json("POST", "/api/files/upload", Some(&token), Some(json!({
+        "name": "scan.pdf", "folder_id": folder_id, "size": 5, "mime_type": "application/pdf",
+    }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(upload["upload_method"], "POST");
+    let file_id = upload["file_id"].as_str().unwrap().to_string();
+    let storage_key = upload["storage_key"].as_str().unwrap().to_string();
+    assert_eq!(upload["fields"]["key"], storage_key);
+    assert_eq!(upload["fields"]["Content-Type"], "application/pdf");
+    let policy: serde_json::Value = serde_json::from_str(upload["fields"]["policy"].as_str().unwrap()).unwrap();
+    assert!(policy["conditions"].as_array().unwrap().contains(&json!(["content-length-range", 5, 5])));
+
+    // Storage that ignored the policy: verification still rejects and deletes it
+    app.state.storage.put(&storage_key, b"%PDF-1.7 and more".to_vec(), Some("application/pdf")).await.unwrap();
+    let (status, _) = app.request("POST", &format!("/api/files/{}/complete", file_id), Some(&token), None).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(app.state.storage.head(&storage_key).await.unwrap().is_none());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn complete_rechecks_committed_files() {
+    let app = TestApp::with_db().await;
+    app.seed_user("grace@example.com", "opensesame", "osis").await;
+    let token = app.login("grace@example.com", "opensesame").await;
+
+    let (_, created) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "Notes" }))).await;
+    let folder_id = created["id"].as_str().unwrap().to_string();
+    let file_id = app.commit_file(&token, &folder_id, "todo.txt", b"milk", "text/plain").await;
+    let complete = format!("/api/files/{}/complete", file_id);
+
+    // Retrying while the object is unchanged is harmless
+    let (status, _) = app.request("POST", &complete, Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The presigned PUT is still valid after the commit and can swap in a bigger object
+    let storage_key = format!("{}/{}", folder_id, file_id);
+    app.state.storage.put(&storage_key, vec![0; 1024], Some("text/plain")).await.unwrap();
+
+    let (status, _) = app.request("POST", &complete, Some(&token), None).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(app.state.storage.head(&storage_key).await.unwrap().is_none());
+
+    let (_, listing) = app.json("GET", &format!("/api/folders/{}", folder_id), Some(&token), None).await;
+    assert_eq!(listing["files"], json!([]));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn uploads_need_edit_rights_on_the_folder() {
+    let app = TestApp::with_db().await;
+    app.seed_user("heidi@example.com", "owner-pass", "osis").await;
+    app.seed_user("ivan@example.com", "other-pass", "osis").await;
+    let owner = app.login("heidi@example.com", "owner-pass").await;
+    let other = app.login("ivan@example.com", "other-pass").await;
+
+    let (_, created) = app.json("POST", "/api/folders", Some(&owner), Some(json!({ "name": "Private" }))).await;
+    let folder_id = created["id"].as_str().unwrap().to_string();
+
+    let upload = |folder_id: &str| json!({ "name": "x.txt", "folder_id": folder_id, "size": 1, "mime_type": "text/plain" });
+
+    let (status, _) = app.request("POST", "/api/files/upload", Some(&other), Some(upload(&folder_id))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = app.request("POST", "/api/files/upload", Some(&other), Some(upload("no-such-folder"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = app.request("POST", "/api/files/upload", Some(&other), Some(upload("root"))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // tus takes the folder from Upload-Metadata
+    let b64 = |v: &str| base64::Engine::encode(&base64::engine::general_purpose::STANDARD, v);
+    let tus = Request::builder()
+        .method("POST")
+        .uri("/api/tus")
+        .header(header::AUTHORIZATION, format!("Bearer {}", other))
+        .header("Tus-Resumable", "1.0.0")
+        .header("Upload-Length", "1")
+        .header("Upload-Metadata", format!("filename {},folder_id {}", b64("x.txt"), b64(&folder_id)))
+        .body(Body::empty())
+        .unwrap();
+    let (status, _, _) = app.send(tus).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn tus_chunks_wait_for_the_request_already_writing() {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+    let app = TestApp::with_db().await;
+    app.seed_user("xena@example.com", "swordfish", "osis").await;
+    let token = app.login("xena@example.com", "swordfish").await;
+
+    let create = Request::builder()
+        .method("POST")
+        .uri("/api/tus")
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .header("Tus-Resumable", "1.0.0")
+        .header("Upload-Length", "5")
+        .header("Upload-Metadata", format!("filename {}", BASE64.encode("notes.txt")))
+        .body(Body::empty())
+        .unwrap();
+    let (status, headers, _) = app.send(create).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let location = headers[header::LOCATION].to_str().unwrap().to_string();
+    let file_id = location.rsplit('/').next().unwrap();
+
+    let patch = || Request::builder()
+        .method("PATCH")
+        .uri(&location)
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .header(header::CONTENT_TYPE, "application/offset+octet-stream")
+        .header("Tus-Resumable", "1.0.0")
+        .header("Upload-Offset", "0")
+        .body(Body::from("hello"))
+        .unwrap();
+
+    // Another PATCH at the same offset is still writing parts
+    let hold = |seconds: i64| sqlx::query("UPDATE tus_uploads SET lock_id = 'other', locked_until = NOW() + INTERVAL ? SECOND WHERE file_id = ?")
+        .bind(seconds)
+        .bind(file_id)
+        .execute(&app.state.db);
+    hold(60).await.unwrap();
+    let (status, _, _) = app.send(patch()).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Its claim lapsed without progress, so this one may take over
+    hold(-1).await.unwrap();
+    let (status, headers, _) = app.send(patch()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(headers["Upload-Offset"], "5");
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn copies_and_uploads_respect_the_quota() {
+    let mut config = test_config();
+    config.user_quota_bytes = 10;
+
+    let app = TestApp::with_db_config(config).await;
+    app.seed_user("judy@example.com", "hunter22", "osis").await;
+    let token = app.login("judy@example.com", "hunter22").await;
+
+    let (_, created) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "Lessons" }))).await;
+    let folder_id = created["id"].as_str().unwrap().to_string();
+    let file_id = app.commit_file(&token, &folder_id, "week1.txt", b"abcdef", "text/plain").await;
+
+    let (status, _) = app.request("POST", &format!("/api/files/{}/copy", file_id), Some(&token), Some(json!({}))).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let (status, _) = app.request("POST", &format!("/api/folders/{}/copy", folder_id), Some(&token), Some(json!({}))).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let upload = |size: i64| json!({ "name": "week2.txt", "folder_id": folder_id, "size": size, "mime_type": "text/plain" });
+    let (status, _) = app.request("POST", "/api/files/upload", Some(&token), Some(upload(5))).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    let (status, _) = app.request("POST", "/api/files/upload", Some(&token), Some(upload(4))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    app.cleanup().await;
+}
+
+async fn wait_for_copy_job(app: &TestApp, token: &str, job_id: &str) -> serde_json::Value {
+    for _ in 0..50 {
+        let (_, job) = app.json("GET", &format!("/api/copy-jobs/{}", job_id), Some(token), None).await;
+        if job["status"] == "completed" || job["status"] == "failed" {
+            return job;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    panic!("copy job {} did not finish", job_id);
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn purging_a_folder_keeps_what_was_trashed_on_its_own() {
+    let app = TestApp::with_db().await;
+    app.seed_user("zoe@example.com", "swordfish", "osis").await;
+    let token = app.login("zoe@example.com", "swordfish").await;
+
+    let (_, outer) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "Outer" }))).await;
+    let outer = outer["id"].as_str().unwrap().to_string();
+    let (_, inner) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "Inner", "parent_id": outer }))).await;
+    let inner = inner["id"].as_str().unwrap().to_string();
+    let file = app.commit_file(&token, &outer, "notes.txt", b"notes", "text/plain").await;
+
+    // Trashed one by one, then the folder around them; only the folder has expired
+    app.request("DELETE", &format!("/api/folders/{}", inner), Some(&token), None).await;
+    app.request("DELETE", &format!("/api/files/{}", file), Some(&token), None).await;
+    app.request("DELETE", &format!("/api/folders/{}", outer), Some(&token), None).await;
+    sqlx::query("UPDATE folders SET deleted_at = NOW() - INTERVAL 60 DAY WHERE id = ?")
+        .bind(&outer)
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+
+    backend::services::trash::purge_expired(&app.state).await.unwrap();
+
+    let (_, trash) = app.json("GET", "/api/trash", Some(&token), None).await;
+    let folders: Vec<&str> = trash["folders"].as_array().unwrap().iter().map(|f| f["id"].as_str().unwrap()).collect();
+    let files: Vec<&str> = trash["files"].as_array().unwrap().iter().map(|f| f["id"].as_str().unwrap()).collect();
+    assert_eq!(folders, vec![inner.as_str()]);
+    assert_eq!(files, vec![file.as_str()]);
+
+    let (status, _) = app.request("POST", &format!("/api/trash/{}/restore", file), Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn grants_are_inherited_and_none_overrides_them() {
+    let app = TestApp::with_db().await;
+    app.seed_user("ken@example.com", "owner-pass", "osis").await;
+    let guest_id = app.seed_user("lena@example.com", "guest-pass", "osis").await;
+    let owner = app.login("ken@example.com", "owner-pass").await;
+    let guest = app.login("lena@example.com", "guest-pass").await;
+
+    let (_, parent) = app.json("POST", "/api/folders", Some(&owner), Some(json!({ "name": "Term 1" }))).await;
+    let parent_id = parent["id"].as_str().unwrap().to_string();
+    let (_, open) = app.json("POST", "/api/folders", Some(&owner), Some(json!({ "name": "Handouts", "parent_id": parent_id }))).await;
+    let open_id = open["id"].as_str().unwrap().to_string();
+    let (_, closed) = app.json("POST", "/api/folders", Some(&owner), Some(json!({ "name": "Exams", "parent_id": parent_id }))).await;
+    let closed_id = closed["id"].as_str().unwrap().to_string();
+
+    let permissions = format!("/api/folders/{}/permissions", parent_id);
+    let (status, _) = app.request("POST", &permissions, Some(&owner), Some(json!({ "user_id": guest_id, "permission": "viewer" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    let permissions = format!("/api/folders/{}/permissions", closed_id);
+    let (status, _) = app.request("POST", &permissions, Some(&owner), Some(json!({ "user_id": guest_id, "permission": "none" }))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Inherited from the parent, read-only
+    let (status, _) = app.request("GET", &format!("/api/folders/{}", open_id), Some(&guest), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.request("POST", "/api/folders", Some(&guest), Some(json!({ "name": "Mine", "parent_id": open_id }))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = app.request("GET", &format!("/api/folders/{}", closed_id), Some(&guest), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // A copy only takes what the guest can see
+    let (status, accepted) = app.json("POST", &format!("/api/folders/{}/copy", parent_id), Some(&guest), Some(json!({ "destination_id": "root" }))).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let job = wait_for_copy_job(&app, &guest, accepted["job_id"].as_str().unwrap()).await;
+    assert_eq!(job["status"], "completed");
+
+    let (_, copy) = app.json("GET", &format!("/api/folders/{}", job["result_folder_id"].as_str().unwrap()), Some(&guest), None).await;
+    let names: Vec<&str> = copy["subfolders"].as_array().unwrap().iter().map(|f| f["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["Handouts"]);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn owners_keep_full_access_below_their_folders() {
+    let app = TestApp::with_db().await;
+    let owner_id = app.seed_user("mia@example.com", "owner-pass", "osis").await;
+    let editor_id = app.seed_user("nate@example.com", "editor-pass", "osis").await;
+    let owner = app.login("mia@example.com", "owner-pass").await;
+    let editor = app.login("nate@example.com", "editor-pass").await;
+
+    let (_, parent) = app.json("POST", "/api/folders", Some(&owner), Some(json!({ "name": "Department" }))).await;
+    let parent_id = parent["id"].as_str().unwrap().to_string();
+    let permissions = format!("/api/folders/{}/permissions", parent_id);
+    app.request("POST", &permissions, Some(&owner), Some(json!({ "user_id": editor_id, "permission": "editor" }))).await;
+
+    // Created by the editor, so owned by them, but still inside the owner's tree
+    let (status, child) = app.json("POST", "/api/folders", Some(&editor), Some(json!({ "name": "Drafts", "parent_id": parent_id }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let child_id = child["id"].as_str().unwrap().to_string();
+
+    let (status, _) = app.request("GET", &format!("/api/folders/{}", child_id), Some(&owner), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.request("PATCH", &format!("/api/folders/{}", child_id), Some(&owner), Some(json!({ "name": "Final" }))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The editor can't lock the owner out...
+    let permissions = format!("/api/folders/{}/permissions", child_id);
+    let (status, _) = app.request("POST", &permissions, Some(&editor), Some(json!({ "user_id": owner_id, "permission": "none" }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // ...and a 'none' row that got there anyway doesn't either
+    sqlx::query("INSERT INTO folder_permissions (id, folder_id, user_id, permission) VALUES (UUID(), ?, ?, 'none')")
+        .bind(&child_id)
+        .bind(&owner_id)
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+    app.state.cache.bump_acl_epoch().await.unwrap();
+
+    let (status, _) = app.request("GET", &format!("/api/folders/{}", child_id), Some(&owner), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.request("GET", &permissions, Some(&owner), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn signing_out_everywhere_rejects_earlier_tokens() {
+    let app = TestApp::with_db().await;
+    app.seed_user("olga@example.com", "swordfish", "osis").await;
+    let token = app.login("olga@example.com", "swordfish").await;
+
+    let (status, _) = app.request("POST", "/api/auth/logout", Some(&token), Some(json!({ "all": true }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = app.request("POST", "/api/folders", Some(&token), Some(json!({ "name": "Docs" }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Tokens issued afterwards carry the new version
+    let token = app.login("olga@example.com", "swordfish").await;
+    let (status, _) = app.request("POST", "/api/folders", Some(&token), Some(json!({ "name": "Docs" }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn signing_out_everywhere_revokes_api_tokens() {
+    let app = TestApp::with_db().await;
+    app.seed_user("yuri@example.com", "swordfish", "osis").await;
+    let session = app.login("yuri@example.com", "swordfish").await;
+
+    let (status, created) = app.json("POST", "/api/tokens", Some(&session), Some(json!({ "name": "backup", "scope": "read" }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let api_token = created["token"].as_str().unwrap();
+
+    let (status, _) = app.request("POST", "/api/auth/logout", Some(&session), Some(json!({ "all": true }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = app.request("GET", "/api/folders/root", Some(api_token), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn upload_scope_manages_content_but_not_admin_endpoints() {
+    let app = TestApp::with_db().await;
+    app.seed_user("pat@example.com", "letmein!", "admin").await;
+    let session = app.login("pat@example.com", "letmein!").await;
+
+    let mut tokens = Vec::new();
+    for scope in ["read", "upload"] {
+        let (status, created) = app.json("POST", "/api/tokens", Some(&session), Some(json!({ "name": scope, "scope": scope }))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        tokens.push(created["token"].as_str().unwrap().to_string());
+    }
+    let (reader, uploader) = (&tokens[0], &tokens[1]);
+
+    let (_, created) = app.json("POST", "/api/folders", Some(uploader), Some(json!({ "name": "Drafts" }))).await;
+    let folder = format!("/api/folders/{}", created["id"].as_str().unwrap());
+
+    let (status, _) = app.request("PATCH", &folder, Some(reader), Some(json!({ "name": "Final" }))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = app.request("PATCH", &folder, Some(uploader), Some(json!({ "name": "Final" }))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app.request("GET", "/api/users", Some(uploader), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = app.request("DELETE", &folder, Some(uploader), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn directory_logins_are_linked_by_an_admin() {
+    let app = TestApp::with_db().await;
+    app.seed_user("rita@example.com", "letmein!", "admin").await;
+    let quinn = app.seed_user("quinn@example.com", "swordfish", "osis").await;
+    let other = app.seed_user("sam@example.com", "swordfish", "osis").await;
+    let admin = app.login("rita@example.com", "letmein!").await;
+
+    let (status, user) = app.json("PATCH", &format!("/api/users/{}", quinn), Some(&admin), Some(json!({ "ldap_uid": "quinn" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(user["ldap_uid"], "quinn");
+
+    // A linked account only signs in through the directory
+    let (status, _) = app.request("POST", "/api/auth/login", None, Some(json!({ "email": "quinn@example.com", "password": "swordfish" }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = app.request("PATCH", &format!("/api/users/{}", other), Some(&admin), Some(json!({ "ldap_uid": "quinn" }))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, user) = app.json("PATCH", &format!("/api/users/{}", quinn), Some(&admin), Some(json!({ "ldap_uid": "" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(user["ldap_uid"].is_null());
+    app.login("quinn@example.com", "swordfish").await;
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn folder_renames_and_moves_are_audited() {
+    let app = TestApp::with_db().await;
+    app.seed_user("uma@example.com", "letmein!", "admin").await;
+    let token = app.login("uma@example.com", "letmein!").await;
+
+    let (_, outer) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "Outer" }))).await;
+    let (_, inner) = app.json("POST", "/api/folders", Some(&token), Some(json!({ "name": "Inner" }))).await;
+    let (outer, inner) = (outer["id"].as_str().unwrap(), inner["id"].as_str().unwrap());
+
+    let (status, _) = app.request("PATCH", &format!("/api/folders/{}", inner), Some(&token), Some(json!({ "name": "Nested", "parent_id": outer }))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, log) = app.json("GET", &format!("/api/audit-events?action=folder.update&target_id={}", inner), Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let details = &log["events"][0]["details"];
+    assert_eq!(details["previous_name"], "Inner");
+    assert_eq!(details["name"], "Nested");
+    assert!(details["previous_parent_id"].is_null());
+    assert_eq!(details["parent_id"], outer);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn reusing_a_rotated_refresh_token_ends_the_session() {
+    let app = TestApp::with_db().await;
+    app.seed_user("vic@example.com", "swordfish", "osis").await;
+    let (_, session) = app.json("POST", "/api/auth/login", None, Some(json!({ "email": "vic@example.com", "password": "swordfish" }))).await;
+    let stolen = session["refresh_token"].as_str().unwrap().to_string();
+
+    let (status, rotated) = app.json("POST", "/api/auth/refresh", None, Some(json!({ "refresh_token": stolen }))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app.request("POST", "/api/auth/refresh", None, Some(json!({ "refresh_token": stolen }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // The whole family and the access tokens issued from it are gone
+    let (status, _) = app.request("POST", "/api/auth/refresh", None, Some(json!({ "refresh_token": rotated["refresh_token"] }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app.request("POST", "/api/folders", rotated["token"].as_str(), Some(json!({ "name": "Docs" }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn a_totp_code_only_works_once() {
+    let app = TestApp::with_db().await;
+    let secret = totp::generate_secret();
+    let user_id = app.seed_user("wes@example.com", "swordfish", "osis").await;
+    sqlx::query("UPDATE users SET totp_secret = ?, totp_enabled = TRUE WHERE id = ?")
+        .bind(&secret)
+        .bind(&user_id)
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+    let code = totp::code_at(&secret, chrono::Utc::now().timestamp()).unwrap();
+
+    for expected in [StatusCode::OK, StatusCode::UNAUTHORIZED] {
+        let (status, challenge) = app.json("POST", "/api/auth/login", None, Some(json!({ "email": "wes@example.com", "password": "swordfish" }))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(challenge["mfa_required"], true);
+
+        let (status, _) = app.request("POST", "/api/auth/mfa/verify", None, Some(json!({ "mfa_token": challenge["mfa_token"], "code": code }))).await;
+        assert_eq!(status, expected);
+    }
+
+    app.cleanup().await;
+}
@@ -262,12 +262,17 @@ export default function DriveLayout() {
                     uploadItem.file.type || 'application/octet-stream'
                 );
 
-                const { presigned_url } = initRes;
+                const { file_id, presigned_url, upload_method, fields } = initRes;
+
+                const markFailed = (message) => {
+                    setUploads(prev => prev.map(u => u.id === uploadItem.id ? { ...u, progress: -1 } : u));
+                    alert(`Upload failed for ${uploadItem.name}: ${message}`);
+                };
 
                 // 2. Upload to S3 (XHR for progress)
                 const xhr = new XMLHttpRequest();
-                xhr.open('PUT', presigned_url, true);
-                if (uploadItem.file.type) {
+                xhr.open(upload_method || 'PUT', presigned_url, true);
+                if (upload_method !== 'POST' && uploadItem.file.type) {
                     xhr.setRequestHeader('Content-Type', uploadItem.file.type);
                 }
 
@@ -278,25 +283,45 @@ export default function DriveLayout() {
                     }
                 };
 
-                xhr.onload = () => {
-                    if (xhr.status === 200) {
-                        setUploads(prev => prev.map(u => u.id === uploadItem.id ? { ...u, progress: 100 } : u));
-                        setTimeout(() => {
-                            fetchData(); // Refresh list
-                            setTimeout(() => {
-                                setUploads(prev => prev.filter(u => u.id !== uploadItem.id));
-                            }, 3000);
-                        }, 500);
-                    } else {
-                        throw new Error("S3 Upload Failed");
+                xhr.onload = async () => {
+                    // POST uploads answer 204
+                    if (xhr.status < 200 || xhr.status >= 300) {
+                        markFailed("S3 Upload Failed");
+                        return;
+                    }
+
+                    // 3. Commit: the server checks the stored object before listing it
+                    try {
+                        await api.completeUpload(file_id);
+                    } catch (e) {
+                        console.error(e);
+                        // 409/422: the object is missing or does not match what was declared
+                        markFailed([409, 422].includes(e.status) ? "the uploaded file was rejected by the server" : e.message);
+                        return;
                     }
+
+                    setUploads(prev => prev.map(u => u.id === uploadItem.id ? { ...u, progress: 100 } : u));
+                    setTimeout(() => {
+                        fetchData(); // Refresh list
+                        setTimeout(() => {
+                            setUploads(prev => prev.filter(u => u.id !== uploadItem.id));
+                        }, 3000);
+                    }, 500);
                 };
 
                 xhr.onerror = () => {
-                    throw new Error("Network Error");
+                    markFailed("Network Error");
                 };
 
-                xhr.send(uploadItem.file);
+                if (upload_method === 'POST') {
+                    // The policy fields first, the file last
+                    const form = new FormData();
+                    Object.entries(fields).forEach(([name, value]) => form.append(name, value));
+                    form.append('file', uploadItem.file);
+                    xhr.send(form);
+                } else {
+                    xhr.send(uploadItem.file);
+                }
 
             } catch (e) {
                 console.error(e);
@@ -23,6 +23,7 @@ export function AuthProvider({ children }) {
             const data = await api.login(email, password);
             if (data.token) {
                 localStorage.setItem('token', data.token);
+                localStorage.setItem('refresh_token', data.refresh_token);
                 localStorage.setItem('user', JSON.stringify(data.user));
                 setUser(data.user);
                 return { success: true };
@@ -33,8 +34,10 @@ export function AuthProvider({ children }) {
         }
     };
 
-    const logout = () => {
+    const logout = async () => {
+        await api.logout();
         localStorage.removeItem('token');
+        localStorage.removeItem('refresh_token');
         localStorage.removeItem('user');
         setUser(null);
         // Redirect to root or refresh? usually just state update is enough.
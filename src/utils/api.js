@@ -1,6 +1,44 @@
 export const API_BASE_URL = 'http://localhost:8080';
 
-export async function fetchWithAuth(endpoint, options = {}) {
+// Concurrent 401s share one refresh: a refresh token works only once, and presenting
+// it twice makes the server end the whole session
+let refreshing = null;
+
+async function refreshSession() {
+    const refreshToken = localStorage.getItem('refresh_token');
+    if (!refreshToken) return false;
+
+    if (!refreshing) {
+        refreshing = fetch(`${API_BASE_URL}/auth/refresh`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ refresh_token: refreshToken }),
+        })
+            .then(async (response) => {
+                if (!response.ok) return false;
+                const data = await response.json();
+                localStorage.setItem('token', data.token);
+                localStorage.setItem('refresh_token', data.refresh_token);
+                localStorage.setItem('user', JSON.stringify(data.user));
+                return true;
+            })
+            .catch(() => false)
+            .finally(() => {
+                refreshing = null;
+            });
+    }
+
+    const refreshed = await refreshing;
+    if (!refreshed) {
+        // The session is over; carry on as a public user
+        localStorage.removeItem('token');
+        localStorage.removeItem('refresh_token');
+        localStorage.removeItem('user');
+    }
+    return refreshed;
+}
+
+export async function fetchWithAuth(endpoint, options = {}, retried = false) {
     const token = localStorage.getItem('token');
     const headers = {
         'Content-Type': 'application/json',
@@ -16,9 +54,17 @@ export async function fetchWithAuth(endpoint, options = {}) {
         headers,
     });
 
+    // Access tokens are short-lived: renew once and repeat the request
+    if (response.status === 401 && token && !retried) {
+        await refreshSession();
+        return fetchWithAuth(endpoint, options, true);
+    }
+
     if (!response.ok) {
         const error = await response.json().catch(() => ({ message: response.statusText }));
-        throw new Error(error.message || `Request failed with status ${response.status}`);
+        const failure = new Error(error.message || `Request failed with status ${response.status}`);
+        failure.status = response.status;
+        throw failure;
     }
 
     // Handle empty responses (like 204 No Content)
@@ -35,6 +81,15 @@ export const api = {
         });
     },
 
+    // Ends this session on the server too; failures don't matter, the tokens are dropped anyway
+    logout: async () => {
+        const refreshToken = localStorage.getItem('refresh_token');
+        return fetchWithAuth('/auth/logout', {
+            method: 'POST',
+            body: JSON.stringify({ refresh_token: refreshToken }),
+        }).catch(() => null);
+    },
+
     listFolder: async (folderId = 'root') => {
         // If not logged in, fetch folder content as public user (no token)
         // fetchWithAuth automatically adds token if present
@@ -60,8 +115,16 @@ export const api = {
         });
     },
 
-    getDownloadUrl: async (fileId) => {
-        return fetchWithAuth(`/files/${fileId}/download`);
+    // Call once the bytes are in storage; until then the file stays pending and hidden
+    completeUpload: async (fileId) => {
+        return fetchWithAuth(`/files/${fileId}/complete`, {
+            method: 'POST',
+        });
+    },
+
+    // disposition: 'attachment' saves under the original name, 'inline' previews in the browser
+    getDownloadUrl: async (fileId, disposition = 'attachment') => {
+        return fetchWithAuth(`/files/${fileId}/download?disposition=${disposition}`);
     },
 
     // Helper to upload directly to Presigned URL